@@ -1,108 +1,2443 @@
 use crate::debugger_command::DebuggerCommand;
-use crate::dwarf_data::{DwarfData, Error as DwarfError};
-use crate::inferior::{Inferior, Status};
+use crate::dwarf_data::{DwarfData, Error as DwarfError, Function, Type, TypeKind};
+use crate::inferior::{self, Frame, Inferior, Launch, Status};
+use crate::json::{self, Json};
+use crate::output::OutputForwarder;
+use crate::printf;
+use crate::record::Recording;
+use crate::shared_library::{self, LinkerHooks, SharedLibrary};
+use crate::syscalls;
+use crate::tui::{Tui, View};
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use regex::Regex;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryInto;
+use std::ffi::OsString;
+use std::io::Write;
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+/// Longest string printed for a printf %s conversion
+const MAX_PRINTF_STRING_LEN: usize = 200;
+
+/// How the debugger talks to its user
+#[derive(Clone, Copy, PartialEq)]
+pub enum Interpreter {
+    /// Commands are typed at a prompt, and results are printed as text
+    Console,
+    /// Commands arrive as JSON lines (`{"id": 1, "command": "break main"}`), and results are
+    /// reported as JSON events, one per line
+    Json,
+}
 
 pub struct Debugger {
+    interpreter: Interpreter,
     target: String,
     history_path: String,
     readline: Editor<()>,
     inferior: Option<Inferior>,
     debug_data: DwarfData,
-    breakpoints: Vec<usize>,
+    /// Breakpoint addresses by breakpoint number; deleted breakpoints are None, so that the
+    /// numbers of the others don't change
+    breakpoints: Vec<Option<usize>>,
+    /// Numbers of the breakpoints to delete once they're hit (from tbreak and start)
+    temporary_breakpoints: Vec<usize>,
+    /// Function breakpoints waiting for a shared library defining the function to be loaded
+    pending_breakpoints: Vec<(String, BreakpointKind)>,
+    /// The dynamic linker's debugging interface, if the inferior is dynamically linked
+    linker_hooks: Option<LinkerHooks>,
+    /// Syscall numbers caught by each catchpoint; an empty list catches every syscall
+    syscall_catchpoints: Vec<Vec<u64>>,
+    trace_syscalls: bool,
+    /// The traced syscall the inferior is currently inside, waiting for its return value
+    pending_syscall: Option<String>,
+    /// Lines from command scripts, executed before reading any more input from the user
+    script_lines: VecDeque<ScriptLine>,
+    /// How many user-defined commands are being run, one inside another
+    user_call_depth: usize,
+    /// Set when a command reports an error, so that the script running it is abandoned
+    command_failed: Cell<bool>,
+    /// User-defined commands (from `define`), mapped to the lines of their bodies
+    user_commands: HashMap<String, Vec<String>>,
+    /// Commands to run when a breakpoint is hit, by breakpoint index
+    breakpoint_commands: HashMap<usize, Vec<String>>,
+    /// The id of the JSON command being executed, echoed back when it completes
+    command_id: Json,
+    /// The split-pane interface, if enabled with --tui
+    tui: Option<Tui>,
+    /// Reports the inferior's output as JSON events, in JSON mode
+    output_forwarder: Option<OutputForwarder>,
+    /// The execution log, while recording for reverse debugging
+    recording: Option<Recording>,
+    /// Arguments (and redirections) of the last run, used again by a run without any
+    run_args: Vec<String>,
+    /// The environment the inferior is started with
+    environment: Vec<(OsString, OsString)>,
+    /// Working directory for the inferior, if not ours
+    cwd: Option<String>,
+    /// Terminal for the inferior's standard streams, if not ours
+    inferior_tty: Option<String>,
+    /// Rewrites of source path prefixes, from `set substitute-path`, tried in order
+    substitute_paths: Vec<(String, String)>,
+    /// Directories searched for source files that aren't where the debug info says
+    source_directories: Vec<String>,
+    /// Expressions shown whenever the inferior stops, by display number - 1 (None once deleted)
+    displays: Vec<Option<String>>,
+    /// Values printed so far, which expressions can refer to as $1, $2...
+    value_history: Vec<(Type, Vec<u8>)>,
+}
+
+/// Deepest nesting of user-defined commands, which stops runaway recursion
+const MAX_USER_CALL_DEPTH: usize = 1024;
+
+/// An entry in the queue of lines to run before reading more input from the user
+enum ScriptLine {
+    Command(String),
+    /// The end of a script (or of a breakpoint's commands), which a failing command skips to
+    EndOfScript,
+    /// The end of the body of a user-defined command
+    EndOfUserCommand,
+}
+
+/// What a breakpoint does once it's hit
+#[derive(Clone)]
+enum BreakpointKind {
+    Breakpoint,
+    /// Deleted once it's hit, as set by tbreak and start
+    Temporary,
+    /// Prints with the given printf arguments and carries on, as set by dprintf
+    Dprintf(String),
+}
+
+impl BreakpointKind {
+    fn name(&self) -> &'static str {
+        match self {
+            BreakpointKind::Breakpoint => "breakpoint",
+            BreakpointKind::Temporary => "temporary breakpoint",
+            BreakpointKind::Dprintf(_) => "dprintf",
+        }
+    }
+}
+
+/// Writes out a C-style declaration of func, such as `int add(int, int);`.
+fn function_declaration(func: &Function) -> String {
+    let params: Vec<&str> = func
+        .variables
+        .iter()
+        .filter(|var| var.is_parameter)
+        .map(|var| var.entity_type.name.as_str())
+        .collect();
+    format!(
+        "{} {}({});",
+        func.return_type
+            .as_ref()
+            .map_or("void", |return_type| return_type.name.as_str()),
+        func.name,
+        if params.is_empty() {
+            "void".to_string()
+        } else {
+            params.join(", ")
+        }
+    )
+}
+
+/// Rewrites path if it starts with the directory from (as a whole path component), as for
+/// `set substitute-path from to`.
+fn replace_path_prefix(path: &str, from: &str, to: &str) -> Option<String> {
+    if !path.starts_with(from) {
+        return None;
+    }
+    let rest = &path[from.len()..];
+    if rest.is_empty() || rest.starts_with('/') || from.ends_with('/') {
+        Some(format!("{}{}", to, rest))
+    } else {
+        None
+    }
+}
+
+/// Parses a floating point literal. Rust also reads words like `inf` and `nan` as numbers, which
+/// are left alone here so that they can still name variables.
+fn parse_float(expr: &str) -> Option<f64> {
+    let digits = expr.trim_start_matches(|c| c == '+' || c == '-');
+    if !digits.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+        return None;
+    }
+    expr.parse().ok()
+}
+
+/// Converts the raw bytes of a value to a printf argument, according to its type. Structs and
+/// arrays have no such value.
+fn printf_value(value_type: &Type, bytes: &[u8]) -> Result<printf::Value, String> {
+    match value_type.kind {
+        TypeKind::Struct(..) | TypeKind::Array(..) | TypeKind::Variants(..) => {
+            return Err("Value can't be converted to integer.".to_string())
+        }
+        _ => {}
+    }
+    let mut raw: u64 = 0;
+    for (i, byte) in bytes.iter().take(8).enumerate() {
+        raw |= (*byte as u64) << (8 * i);
+    }
+    let name = value_type.name.as_str();
+    Ok(if name == "float" && bytes.len() == 4 {
+        printf::Value::Float(f32::from_bits(raw as u32) as f64)
+    } else if name == "double" && bytes.len() == 8 {
+        printf::Value::Float(f64::from_bits(raw))
+    } else if name.contains("unsigned") || name.contains('*') {
+        printf::Value::Unsigned(raw)
+    } else {
+        // Sign-extend from the width of the type
+        let shift = 64 - 8 * bytes.len().max(1).min(8);
+        printf::Value::Int(((raw << shift) as i64) >> shift)
+    })
 }
 
-impl Debugger {
-    /// Initializes the debugger.
-    pub fn new(target: &str) -> Debugger {
-        // TODO (milestone 3): initialize the DwarfData
-        let debug_data = match DwarfData::from_file(target) {
-            Ok(val) => val,
-            Err(DwarfError::ErrorOpeningFile) => {
-                println!("Could not open file {}", target);
-                std::process::exit(1);
+impl Debugger {
+    /// Initializes the debugger.
+    pub fn new(target: &str, interpreter: Interpreter) -> Debugger {
+        // TODO (milestone 3): initialize the DwarfData
+        let debug_data = match DwarfData::from_file(target) {
+            Ok(val) => val,
+            Err(DwarfError::ErrorOpeningFile) => {
+                println!("Could not open file {}", target);
+                std::process::exit(1);
+            }
+            Err(DwarfError::DwarfFormatError(err)) => {
+                println!(
+                    "Could not load debugging symbols from {}: {:?}",
+                    target, err
+                );
+                match DwarfData::symbols_from_file(target) {
+                    Ok(val) => val,
+                    Err(_) => std::process::exit(1),
+                }
+            }
+        };
+        let history_path = format!("{}/.deet_history", std::env::var("HOME").unwrap());
+        let mut readline = Editor::<()>::new();
+        // Attempt to load history from ~/.deet_history if it exists
+        let _ = readline.load_history(&history_path);
+
+        let mut debugger = Debugger {
+            interpreter,
+            target: target.to_string(),
+            history_path,
+            readline,
+            inferior: None,
+            debug_data,
+            breakpoints: vec![],
+            temporary_breakpoints: Vec::new(),
+            pending_breakpoints: vec![],
+            linker_hooks: None,
+            syscall_catchpoints: vec![],
+            trace_syscalls: false,
+            pending_syscall: None,
+            script_lines: VecDeque::new(),
+            user_call_depth: 0,
+            command_failed: Cell::new(false),
+            user_commands: HashMap::new(),
+            breakpoint_commands: HashMap::new(),
+            command_id: Json::Null,
+            tui: None,
+            output_forwarder: None,
+            recording: None,
+            run_args: Vec::new(),
+            // Not necessarily UTF-8, so kept as OsStrings
+            environment: std::env::vars_os().collect(),
+            cwd: None,
+            inferior_tty: None,
+            substitute_paths: Vec::new(),
+            source_directories: Vec::new(),
+            displays: Vec::new(),
+            value_history: Vec::new(),
+        };
+        if !debugger.debug_data.has_debug_info() {
+            debugger.message(&format!("(No debugging symbols found in {})", target));
+        }
+        // Like ~/.gdbinit, run ~/.deetinit (if it exists) before anything else
+        let init_path = format!("{}/.deetinit", std::env::var("HOME").unwrap());
+        if let Ok(lines) = Debugger::read_script(&init_path) {
+            debugger.queue_script(lines);
+        }
+        debugger
+    }
+
+    /// Prints everything in the target's debugging information, for `deet --dump-dwarf`.
+    pub fn dump_debug_data(&self) {
+        self.debug_data.print();
+    }
+
+    /// Queues the commands in the script at path to run after those queued so far (e.g. by
+    /// `deet -x`).
+    pub fn source(&mut self, path: &str) -> Result<(), io::Error> {
+        let lines = Debugger::read_script(path)?;
+        self.queue_script(lines);
+        Ok(())
+    }
+
+    /// Queues the lines of a script after those queued so far.
+    fn queue_script(&mut self, lines: Vec<String>) {
+        self.script_lines
+            .extend(lines.into_iter().map(ScriptLine::Command));
+        self.script_lines.push_back(ScriptLine::EndOfScript);
+    }
+
+    fn read_script(path: &str) -> Result<Vec<String>, io::Error> {
+        Ok(fs::read_to_string(path)?
+            .lines()
+            .map(|line| line.to_string())
+            .collect())
+    }
+
+    /// Runs lines before any other queued script lines, e.g. the body of a user-defined command
+    /// or a script sourced from another script. end marks where they stop, if anything needs to
+    /// know.
+    fn run_lines_next(&mut self, lines: Vec<String>, end: Option<ScriptLine>) {
+        if let Some(end) = end {
+            self.script_lines.push_front(end);
+        }
+        for line in lines.into_iter().rev() {
+            self.script_lines.push_front(ScriptLine::Command(line));
+        }
+    }
+
+    /// Returns whether commands are being read from a script (or a user-defined command) rather
+    /// than typed in.
+    fn reading_script(&self) -> bool {
+        self.script_lines
+            .iter()
+            .any(|line| matches!(line, ScriptLine::Command(_)))
+    }
+
+    /// Drops the rest of the innermost script being run, along with the user-defined commands
+    /// running inside it, after a command in it failed.
+    fn abort_script(&mut self) {
+        while let Some(line) = self.script_lines.pop_front() {
+            match line {
+                ScriptLine::EndOfScript => break,
+                ScriptLine::EndOfUserCommand => self.user_call_depth -= 1,
+                ScriptLine::Command(_) => {}
+            }
+        }
+    }
+
+    /// Reads the lines of a command block (as for `define`) up to the closing "end". Nested
+    /// blocks are kept whole.
+    fn read_command_block(&mut self) -> Vec<String> {
+        // In JSON mode each body line comes with its own id, but the block is reported done
+        // under the id of the command that opened it
+        let command_id = self.command_id.clone();
+        let mut lines = Vec::new();
+        let mut depth = 0;
+        while let Some(line) = self.next_line(">") {
+            let first = line.split_whitespace().next().unwrap_or("");
+            if first == "end" {
+                if depth == 0 {
+                    break;
+                }
+                depth -= 1;
+            } else if first == "define" || first == "commands" {
+                depth += 1;
+            }
+            lines.push(line.trim().to_string());
+        }
+        self.command_id = command_id;
+        lines
+    }
+
+    /// Defines a user command, substituting $arg0...$argN and $argc when it is invoked.
+    fn define_command(&mut self, name: String) {
+        // Only prompt when the body is going to be typed in
+        if !self.reading_script() && self.interpreter == Interpreter::Console {
+            println!("Type commands for definition of \"{}\".", name);
+            println!("End with a line saying just \"end\".");
+        }
+        let body = self.read_command_block();
+        self.user_commands.insert(name, body);
+    }
+
+    /// Expands an invocation of a user-defined command into its body, with the arguments
+    /// substituted in.
+    fn expand_user_command(body: &[String], args: &[&str]) -> Vec<String> {
+        body.iter()
+            .map(|line| {
+                // Replace higher-numbered arguments first, so that $arg1 doesn't clobber $arg10
+                let mut line = line.replace("$argc", &args.len().to_string());
+                for (i, arg) in args.iter().enumerate().rev() {
+                    line = line.replace(&format!("$arg{}", i), arg);
+                }
+                line
+            })
+            .collect()
+    }
+
+    /// Switches to the split-pane terminal interface.
+    pub fn enable_tui(&mut self) {
+        if self.interpreter == Interpreter::Console {
+            self.tui = Some(Tui::new());
+            self.refresh_tui();
+        }
+    }
+
+    /// Redraws the TUI panes (if the TUI is on) for where the inferior is now.
+    fn refresh_tui(&mut self) {
+        if self.tui.is_none() {
+            return;
+        }
+        let mut view = View {
+            source: None,
+            current_line: None,
+            breakpoint_lines: Vec::new(),
+            registers: Vec::new(),
+            frames: Vec::new(),
+            status: "The program is not being run.".to_string(),
+        };
+        // Until the program runs, show main
+        let mut addr = self
+            .debug_data
+            .get_addrs_for_function(None, "main", true)
+            .first()
+            .cloned();
+        if let Some(inferior) = &self.inferior {
+            if let Ok(regs) = inferior.get_registers() {
+                addr = Some(regs.rip as usize);
+                view.registers = vec![
+                    ("rip", regs.rip),
+                    ("rsp", regs.rsp),
+                    ("rbp", regs.rbp),
+                    ("rax", regs.rax),
+                    ("rbx", regs.rbx),
+                    ("rcx", regs.rcx),
+                    ("rdx", regs.rdx),
+                    ("rsi", regs.rsi),
+                    ("rdi", regs.rdi),
+                    ("r8", regs.r8),
+                    ("r9", regs.r9),
+                    ("r10", regs.r10),
+                    ("r11", regs.r11),
+                    ("r12", regs.r12),
+                    ("r13", regs.r13),
+                    ("r14", regs.r14),
+                    ("r15", regs.r15),
+                    ("eflags", regs.eflags),
+                ];
+                view.status = format!("No source available for {:#x}.", regs.rip);
+            }
+            if let Ok(frames) = inferior.backtrace(&self.debug_data) {
+                view.frames = frames
+                    .iter()
+                    .map(|frame| match (&frame.function, &frame.line) {
+                        (Some(func), Some(line)) => {
+                            let file = line.file.rsplit('/').next().unwrap_or(&line.file);
+                            format!("{} ({}:{})", func, file, line.number)
+                        }
+                        (Some(func), None) => func.to_string(),
+                        (None, _) => format!("{:#x}", frame.address),
+                    })
+                    .collect();
+            }
+        }
+        if let Some(line) = addr.and_then(|addr| self.debug_data.get_line_from_addr(addr)) {
+            if let Some(lines) = self.source_lines(&line.file) {
+                view.breakpoint_lines = self
+                    .active_breakpoints()
+                    .into_iter()
+                    .filter_map(|addr| self.debug_data.get_line_from_addr(addr))
+                    .filter(|bp_line| bp_line.file == line.file)
+                    .map(|bp_line| bp_line.number)
+                    .collect();
+                if self.inferior.is_some() {
+                    view.current_line = Some(line.number);
+                }
+                view.source = Some((line.file, lines));
+            } else {
+                view.status = format!("{}: No such file or directory.", line.file);
+            }
+        }
+        self.tui.as_mut().unwrap().draw(&view);
+    }
+
+    /// Reports an event: in JSON mode as an object with the given fields, or else by printing
+    /// text (if any).
+    fn emit(&self, event: &str, fields: Vec<(&str, Json)>, text: &str) {
+        // Debug info is parsed as it's needed, so problems with it turn up at any time
+        for error in self.debug_data.take_load_errors() {
+            self.message(&error);
+        }
+        match self.interpreter {
+            Interpreter::Console => {
+                if !text.is_empty() {
+                    println!("{}", text);
+                }
+            }
+            Interpreter::Json => {
+                // Whatever the inferior printed before this happened comes first
+                if let Some(forwarder) = &self.output_forwarder {
+                    forwarder.flush();
+                }
+                let mut object = vec![("event", Json::from(event))];
+                object.extend(fields);
+                println!("{}", Json::object(object));
+            }
+        }
+    }
+
+    /// Prints an informational message.
+    fn message(&self, text: &str) {
+        self.emit("message", vec![("text", Json::from(text))], text);
+    }
+
+    /// Reports a failed command.
+    fn error(&self, text: &str) {
+        self.command_failed.set(true);
+        self.emit("error", vec![("message", Json::from(text))], text);
+    }
+
+    /// JSON fields describing the code location of addr.
+    fn location_fields(&self, addr: usize) -> Vec<(&'static str, Json)> {
+        let line = self.debug_data.get_line_from_addr(addr);
+        vec![
+            ("address", Json::from(format!("{:#x}", addr))),
+            (
+                "function",
+                Json::from(self.debug_data.get_function_from_addr(addr)),
+            ),
+            (
+                "file",
+                Json::from(line.as_ref().map(|line| line.file.clone())),
+            ),
+            ("line", Json::from(line.map(|line| line.number))),
+        ]
+    }
+
+    /// Describes addr as "func (file:line)" if there is debug info for it.
+    fn format_location(&self, addr: usize) -> String {
+        let line = self.debug_data.get_line_from_addr(addr);
+        let func = self.debug_data.get_function_from_addr(addr);
+        match (line, func) {
+            (Some(line), Some(func)) => format!("{} ({}:{})", func, line.file, line.number),
+            _ => {
+                let symbol = match self.debug_data.get_symbol_from_addr(addr) {
+                    Some((name, offset)) => format!("{}+{:#x}", name, offset),
+                    None => format!("{:#x}", addr),
+                };
+                match self.debug_data.get_library_name_from_addr(addr) {
+                    Some(library) => format!("{} in {}", symbol, library),
+                    None => symbol,
+                }
+            }
+        }
+    }
+
+    fn handle_status(&mut self, status: Status) {
+        match status {
+            Status::Stopped(signal, rip) => {
+                let mut commands: Vec<String> = Vec::new();
+                if signal == Signal::SIGTRAP {
+                    for (index, _) in self
+                        .breakpoints
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, addr)| **addr == Some(rip))
+                    {
+                        if let Some(body) = self.breakpoint_commands.get(&index) {
+                            commands.extend(body.iter().cloned());
+                        }
+                    }
+                }
+                // A command list starting with "silent" suppresses the usual stop message
+                if commands.first().map(|command| command.as_str()) == Some("silent") {
+                    commands.remove(0);
+                } else {
+                    let breakpoint = if signal == Signal::SIGTRAP {
+                        self.breakpoints.iter().position(|addr| *addr == Some(rip))
+                    } else {
+                        None
+                    };
+                    let mut fields = vec![
+                        (
+                            "reason",
+                            Json::from(if breakpoint.is_some() {
+                                "breakpoint-hit"
+                            } else {
+                                "signal"
+                            }),
+                        ),
+                        ("signal", Json::from(signal.as_str())),
+                        ("breakpoint", Json::from(breakpoint)),
+                    ];
+                    fields.extend(self.location_fields(rip));
+                    let text = format!(
+                        "Child stopped by {} at {}",
+                        signal,
+                        self.format_location(rip)
+                    );
+                    self.emit("stopped", fields, &text);
+                }
+                if signal == Signal::SIGTRAP {
+                    self.delete_temporary_breakpoints(rip);
+                }
+                self.show_displays();
+                self.refresh_tui();
+                self.run_lines_next(commands, Some(ScriptLine::EndOfScript));
+            }
+            Status::Exited(exit_code) => {
+                self.inferior = None;
+                self.recording = None;
+                self.emit(
+                    "exited",
+                    vec![("exit_code", Json::from(exit_code))],
+                    &format!("Child exited (status {})", exit_code),
+                );
+                self.refresh_tui();
+            }
+            Status::Signaled(signal) => {
+                self.inferior = None;
+                self.recording = None;
+                self.emit(
+                    "exited",
+                    vec![("signal", Json::from(signal.as_str()))],
+                    &format!("Child exited by {}", signal),
+                );
+                self.refresh_tui();
+            }
+            Status::SyscallEntry(number) | Status::SyscallExit(number, _) => {
+                let index = self.catchpoint_for_syscall(number).unwrap_or(0);
+                let event = match status {
+                    Status::SyscallEntry(_) => "call to",
+                    _ => "returned from",
+                };
+                let name = syscalls::syscall_name(number)
+                    .map(|name| name.to_string())
+                    .unwrap_or_else(|| number.to_string());
+                let rip = match self.inferior.as_ref().unwrap().get_registers() {
+                    Ok(regs) => regs.rip as usize,
+                    Err(_) => 0,
+                };
+                let mut fields = vec![
+                    (
+                        "reason",
+                        Json::from(match status {
+                            Status::SyscallEntry(_) => "syscall-entry",
+                            _ => "syscall-return",
+                        }),
+                    ),
+                    ("catchpoint", Json::from(index)),
+                    ("syscall", Json::from(name.as_str())),
+                ];
+                fields.extend(self.location_fields(rip));
+                let text = format!(
+                    "Catchpoint {} ({} syscall {}) at {}",
+                    index,
+                    event,
+                    name,
+                    self.format_location(rip)
+                );
+                self.emit("stopped", fields, &text);
+                self.show_displays();
+                self.refresh_tui();
+            }
+        }
+    }
+
+    fn kill_inferior(&mut self) {
+        let inferior = self.inferior.as_mut().unwrap();
+        match inferior.kill() {
+            Ok(status) => self.handle_status(status),
+            Err(err) => {
+                self.error(&format!("Error killing subprocess -> {}", err));
+            }
+        }
+    }
+
+    /// Lists the functions (or the global variables, if variables is set) whose names match
+    /// regex, for info functions and info variables.
+    fn print_symbols(&self, regex: Option<&str>, variables: bool) {
+        let matcher = match regex.map(Regex::new).transpose() {
+            Ok(matcher) => matcher,
+            Err(err) => {
+                self.error(&format!("Invalid regexp: {}", err));
+                return;
+            }
+        };
+        let matches = |name: &str| matcher.as_ref().map_or(true, |regex| regex.is_match(name));
+        let kind = if variables { "variables" } else { "functions" };
+        let mut text = match regex {
+            Some(regex) => format!("All {} matching regular expression \"{}\":\n", kind, regex),
+            None => format!("All defined {}:\n", kind),
+        };
+        let mut files = Vec::new();
+        let libraries = self.debug_data.shared_libraries().iter();
+        let debug_data = std::iter::once(&self.debug_data)
+            .chain(libraries.filter_map(|library| library.debug_data.as_ref()));
+        for file in debug_data.flat_map(|debug_data| debug_data.files_defining(variables, matches))
+        {
+            // (line, name, declaration)
+            let mut symbols: Vec<(usize, &str, String)> = if variables {
+                file.global_variables
+                    .iter()
+                    .filter(|var| matches(&var.name))
+                    .map(|var| {
+                        let declaration = format!("{} {};", var.entity_type.name, var.name);
+                        (var.line_number, var.name.as_str(), declaration)
+                    })
+                    .collect()
+            } else {
+                file.functions
+                    .iter()
+                    // Leave out declarations of functions defined elsewhere
+                    .filter(|func| func.text_length > 0 || !func.inlined_addresses.is_empty())
+                    .filter(|func| !func.name.is_empty() && matches(&func.name))
+                    .map(|func| {
+                        (
+                            func.line_number,
+                            func.name.as_str(),
+                            function_declaration(func),
+                        )
+                    })
+                    .collect()
+            };
+            symbols.sort_by_key(|symbol| symbol.1);
+            symbols.dedup_by(|a, b| a.1 == b.1);
+            if symbols.is_empty() {
+                continue;
+            }
+            text.push_str(&format!("\nFile {}:\n", file.name));
+            for (line, _, declaration) in &symbols {
+                text.push_str(&format!("{}:\t{}\n", line, declaration));
+            }
+            let symbols = symbols
+                .into_iter()
+                .map(|(line, name, declaration)| {
+                    Json::object(vec![
+                        ("line", Json::from(line)),
+                        ("name", Json::from(name)),
+                        ("declaration", Json::from(declaration)),
+                    ])
+                })
+                .collect();
+            files.push(Json::object(vec![
+                ("file", Json::from(file.name.as_str())),
+                ("symbols", Json::Array(symbols)),
+            ]));
+        }
+        let mut fields = vec![("files", Json::Array(files))];
+        if !variables {
+            let mut symbols: Vec<(usize, &str)> = self
+                .debug_data
+                .non_debugging_functions()
+                .into_iter()
+                .filter(|(_, name)| matches(name))
+                .collect();
+            symbols.sort();
+            symbols.dedup();
+            if !symbols.is_empty() {
+                text.push_str("\nNon-debugging symbols:\n");
+            }
+            for (addr, name) in &symbols {
+                text.push_str(&format!("{:#018x}  {}\n", addr, name));
+            }
+            let symbols = symbols
+                .into_iter()
+                .map(|(addr, name)| {
+                    Json::object(vec![
+                        ("address", Json::from(format!("{:#x}", addr))),
+                        ("name", Json::from(name)),
+                    ])
+                })
+                .collect();
+            fields.push(("non_debugging", Json::Array(symbols)));
+        }
+        self.emit(kind, fields, text.trim_end());
+    }
+
+    /// Formats addr as `0x401136 <main+16>`.
+    fn format_symbolic_address(&self, addr: usize) -> String {
+        match self.debug_data.get_symbol_from_addr(addr) {
+            Some((name, 0)) => format!("{:#x} <{}>", addr, name),
+            Some((name, offset)) => format!("{:#x} <{}+{}>", addr, name, offset),
+            None => format!("{:#x}", addr),
+        }
+    }
+
+    /// Finds the first address of location for the info commands, reporting an error if there
+    /// is none. Function names resolve to the function's entry, before its prologue.
+    fn resolve_info_location(&self, location: &str) -> Option<usize> {
+        let is_function = !location.starts_with('*') && location.parse::<usize>().is_err();
+        let resolved = if is_function {
+            self.resolve_location(&format!("*{}", location))
+        } else {
+            self.resolve_location(location)
+        };
+        match resolved?.first() {
+            Some((addr, _)) => Some(*addr),
+            None => {
+                self.error(&format!("Function \"{}\" not defined.", location));
+                None
+            }
+        }
+    }
+
+    /// Prints the address range of the code for a source line, for info line. Without a
+    /// location, uses the line the inferior is stopped at.
+    fn print_line_info(&self, location: Option<&str>) {
+        let addr = match location {
+            Some(location) => match self.resolve_info_location(location) {
+                Some(addr) => addr,
+                None => return,
+            },
+            None => match self
+                .inferior
+                .as_ref()
+                .map(|inferior| inferior.get_registers())
+            {
+                Some(Ok(regs)) => regs.rip as usize,
+                _ => {
+                    self.error("Argument required (location).");
+                    return;
+                }
+            },
+        };
+        let (line, (start, end)) = match (
+            self.debug_data.get_line_from_addr(addr),
+            self.debug_data.get_line_range(addr),
+        ) {
+            (Some(line), Some(range)) => (line, range),
+            _ => {
+                self.error(&format!(
+                    "No line number information available for address {}",
+                    self.format_symbolic_address(addr)
+                ));
+                return;
+            }
+        };
+        // A line without code of its own resolves to the next line that has some
+        let requested = location.and_then(|location| location.parse::<usize>().ok());
+        let text = match requested {
+            Some(number) if number != line.number => format!(
+                "Line {} of \"{}\" is at address {} but contains no code.",
+                number,
+                line.file,
+                self.format_symbolic_address(addr)
+            ),
+            _ => format!(
+                "Line {} of \"{}\" starts at address {} and ends at {}.",
+                line.number,
+                line.file,
+                self.format_symbolic_address(start),
+                self.format_symbolic_address(end)
+            ),
+        };
+        self.emit(
+            "line",
+            vec![
+                ("file", Json::from(line.file.as_str())),
+                ("line", Json::from(requested.unwrap_or(line.number))),
+                ("start", Json::from(format!("{:#x}", start))),
+                ("end", Json::from(format!("{:#x}", end))),
+            ],
+            &text,
+        );
+    }
+
+    /// Lists the variables visible at location, for info scope.
+    fn print_scope(&self, location: &str) {
+        let addr = match self.resolve_location(location) {
+            Some(addrs) if addrs.is_empty() => {
+                self.error(&format!("No function \"{}\" in this program.", location));
+                return;
+            }
+            Some(addrs) => addrs[0].0,
+            None => return,
+        };
+        let func = match self.debug_data.get_function_data_from_addr(addr) {
+            Some(func) => func,
+            None => {
+                self.error("No function contains specified address.");
+                return;
+            }
+        };
+        let mut text = format!("Scope for {}:", location);
+        let mut symbols = Vec::new();
+        for var in func.variables_in_scope(addr) {
+            text.push_str(&format!(
+                "\nSymbol {} is {}, length {}.",
+                var.name,
+                var.location.describe(),
+                var.entity_type.size
+            ));
+            symbols.push(Json::object(vec![
+                ("name", Json::from(var.name.as_str())),
+                ("location", Json::from(var.location.describe())),
+                ("length", Json::from(var.entity_type.size)),
+            ]));
+        }
+        if symbols.is_empty() {
+            text.push_str("\ncontains no locals or arguments.");
+        }
+        self.emit("scope", vec![("symbols", Json::Array(symbols))], &text);
+    }
+
+    /// Says where the symbol called name lives, for info address. Local variables are looked up
+    /// where the inferior is stopped.
+    fn print_address_info(&self, name: &str) {
+        let rip = match self
+            .inferior
+            .as_ref()
+            .map(|inferior| inferior.get_registers())
+        {
+            Some(Ok(regs)) => regs.rip as usize,
+            _ => 0,
+        };
+        let description = match self.debug_data.get_variable_at(name, rip) {
+            Some(var) => format!("is {}", var.location.describe()),
+            None => match self.debug_data.get_function_entry(name) {
+                Some((addr, Some(_))) => format!("is a function at address {:#x}", addr),
+                Some((addr, None)) => {
+                    format!("is at {:#x} in a file compiled without debugging", addr)
+                }
+                None => {
+                    self.error(&format!("No symbol \"{}\" in current context.", name));
+                    return;
+                }
+            },
+        };
+        self.emit(
+            "address",
+            vec![
+                ("name", Json::from(name)),
+                ("description", Json::from(description.as_str())),
+            ],
+            &format!("Symbol \"{}\" {}.", name, description),
+        );
+    }
+
+    fn print_shared_libraries(&self) {
+        let libraries = self.debug_data.shared_libraries();
+        if self.interpreter == Interpreter::Json {
+            let libraries = libraries
+                .iter()
+                .map(|library| {
+                    Json::object(vec![
+                        ("name", Json::from(library.name.as_str())),
+                        ("from", Json::from(format!("{:#x}", library.text.0))),
+                        ("to", Json::from(format!("{:#x}", library.text.1))),
+                        (
+                            "debug_info",
+                            Json::from(library.debug_data.as_ref().map(|d| d.has_debug_info())),
+                        ),
+                    ])
+                })
+                .collect();
+            self.emit(
+                "shared-libraries",
+                vec![("libraries", Json::Array(libraries))],
+                "",
+            );
+            return;
+        }
+        if libraries.is_empty() {
+            println!("No shared libraries loaded at this time.");
+            return;
+        }
+        println!(
+            "{:<20}{:<20}{:<12}Shared Object Library",
+            "From", "To", "Syms Read"
+        );
+        let mut missing_debug_info = false;
+        for library in libraries {
+            let syms_read = match &library.debug_data {
+                Some(debug_data) if debug_data.has_debug_info() => "Yes",
+                Some(_) => {
+                    missing_debug_info = true;
+                    "Yes (*)"
+                }
+                None => "No",
+            };
+            println!(
+                "{:<#20x}{:<#20x}{:<12}{}",
+                library.text.0, library.text.1, syms_read, library.name
+            );
+        }
+        if missing_debug_info {
+            println!("(*): Shared library is missing debugging information.");
+        }
+    }
+
+    /// Finds the addresses of location (a line number, function name, or *address), each with
+    /// a description for messages. Returns None (after reporting why) if the location is
+    /// invalid, or an empty list for a function that isn't defined (yet).
+    fn resolve_location(&self, location: &str) -> Option<Vec<(usize, String)>> {
+        let mut addrs = Vec::new();
+        if !location.starts_with("*") {
+            if let Ok(line) = location.parse::<usize>() {
+                for addr in self.debug_data.get_addrs_for_line(None, line) {
+                    addrs.push((addr, format!(" (line {})", line)));
+                }
+                if addrs.is_empty() {
+                    self.error("Invalid line breakpoint");
+                    return None;
+                }
+            } else if let Some((file, line)) = Debugger::parse_file_line(location) {
+                // The file may be named by where it is now, rather than where it was built
+                let mut files = vec![file.to_string()];
+                for (from, to) in &self.substitute_paths {
+                    if let Some(original) = replace_path_prefix(file, to, from) {
+                        files.push(original);
+                    }
+                }
+                if !files.iter().any(|file| self.debug_data.has_file(file)) {
+                    self.error(&format!("No source file named {}.", file));
+                    return None;
+                }
+                let found = files
+                    .iter()
+                    .map(|file| self.debug_data.get_addrs_for_line(Some(file), line))
+                    .find(|found| !found.is_empty())
+                    .unwrap_or_default();
+                for addr in found {
+                    addrs.push((addr, format!(" ({}:{})", file, line)));
+                }
+                if addrs.is_empty() {
+                    self.error("Invalid line breakpoint");
+                    return None;
+                }
+            } else {
+                // Inlined functions get a breakpoint at every inlined copy
+                for addr in self.debug_data.get_addrs_for_function(None, location, true) {
+                    addrs.push((addr, format!(" (function {})", location)));
+                }
+            }
+        } else {
+            // *func breaks on the function's raw entry point, before its prologue
+            for addr in self
+                .debug_data
+                .get_addrs_for_function(None, &location[1..], false)
+            {
+                addrs.push((addr, format!(" (function {})", &location[1..])));
+            }
+            if addrs.is_empty() {
+                match Debugger::parse_address(&location[1..]) {
+                    Some(addr) => addrs.push((addr, String::new())),
+                    None => {
+                        self.error("Invalid address breakpoint");
+                        return None;
+                    }
+                }
+            }
+        }
+        Some(addrs)
+    }
+
+    /// Splits a file:line location, such as `main.c:12`.
+    fn parse_file_line(location: &str) -> Option<(&str, usize)> {
+        let colon = location.rfind(':')?;
+        let line = location[colon + 1..].parse().ok()?;
+        if colon == 0 {
+            return None;
+        }
+        Some((&location[..colon], line))
+    }
+
+    /// Sets breakpoints at location (see resolve_location), printing "Set <kind> ..." for each.
+    fn add_breakpoints(&mut self, location: &str, kind: BreakpointKind) {
+        let addrs = match self.resolve_location(location) {
+            Some(addrs) => addrs,
+            None => return,
+        };
+        if addrs.is_empty() {
+            // A function that isn't in the program may still be defined by a shared library, if
+            // any can be loaded
+            let may_load_libraries = self.linker_hooks.is_some()
+                || (self.inferior.is_none() && self.debug_data.is_dynamic());
+            if !may_load_libraries {
+                self.error("Invalid function breakpoint");
+                return;
+            }
+            let text = format!(
+                "Function \"{}\" not defined; breakpoint pending on future \
+                 shared library load",
+                location
+            );
+            self.emit(
+                "breakpoint-pending",
+                vec![("function", Json::from(location))],
+                &text,
+            );
+            self.pending_breakpoints
+                .push((location.to_string(), kind.clone()));
+        }
+        self.place_breakpoints(addrs, &kind);
+    }
+
+    /// Numbers breakpoints at each of the (address, description) pairs and reports them.
+    fn place_breakpoints(&mut self, addrs: Vec<(usize, String)>, kind: &BreakpointKind) {
+        for (addr, description) in addrs {
+            let index = self.breakpoints.len();
+            self.breakpoints.push(Some(addr));
+            match kind {
+                BreakpointKind::Breakpoint => {}
+                BreakpointKind::Temporary => self.temporary_breakpoints.push(index),
+                BreakpointKind::Dprintf(printf_args) => {
+                    self.breakpoint_commands.insert(
+                        index,
+                        vec![
+                            "silent".to_string(),
+                            format!("printf {}", printf_args),
+                            "continue".to_string(),
+                        ],
+                    );
+                }
+            }
+            self.report_breakpoint(kind.name(), index, &description);
+        }
+    }
+
+    /// Deletes the temporary breakpoints at addr, which the inferior just hit.
+    fn delete_temporary_breakpoints(&mut self, addr: usize) {
+        let breakpoints = &mut self.breakpoints;
+        let breakpoint_commands = &mut self.breakpoint_commands;
+        self.temporary_breakpoints.retain(|index| {
+            if breakpoints[*index] != Some(addr) {
+                return true;
+            }
+            breakpoints[*index] = None;
+            breakpoint_commands.remove(index);
+            false
+        });
+        if self.breakpoints.contains(&Some(addr)) {
+            return;
+        }
+        let result = self
+            .inferior
+            .as_mut()
+            .map(|inferior| inferior.remove_breakpoint(addr));
+        if let Some(Err(err)) = result {
+            self.error(&format!("Error removing temporary breakpoint -> {}", err));
+        }
+    }
+
+    /// Addresses of the breakpoints that haven't been deleted.
+    fn active_breakpoints(&self) -> Vec<usize> {
+        self.breakpoints.iter().flatten().cloned().collect()
+    }
+
+    /// Reports a newly set breakpoint.
+    fn report_breakpoint(&self, kind: &str, index: usize, description: &str) {
+        let addr = self.breakpoints[index].unwrap();
+        let mut fields = vec![("kind", Json::from(kind)), ("number", Json::from(index))];
+        fields.extend(self.location_fields(addr));
+        let text = format!("Set {} {} at {:#x}{}", kind, index, addr, description);
+        self.emit("breakpoint-created", fields, &text);
+    }
+
+    /// Reads the command list for breakpoint index (the last breakpoint set, by default).
+    fn define_breakpoint_commands(&mut self, index: Option<usize>) {
+        let index = match index.or_else(|| self.breakpoints.len().checked_sub(1)) {
+            Some(index) if self.breakpoints.get(index).map_or(false, Option::is_some) => index,
+            Some(index) => {
+                self.error(&format!("No breakpoint number {}.", index));
+                return;
+            }
+            None => {
+                self.error("No breakpoints specified.");
+                return;
+            }
+        };
+        // Only prompt when the commands are going to be typed in
+        if !self.reading_script() && self.interpreter == Interpreter::Console {
+            println!("Type commands for breakpoint {}, one per line.", index);
+            println!("End with a line saying just \"end\".");
+        }
+        let commands = self.read_command_block();
+        if commands.is_empty() {
+            self.breakpoint_commands.remove(&index);
+        } else {
+            self.breakpoint_commands.insert(index, commands);
+        }
+    }
+
+    /// Sets a dprintf: a breakpoint that prints a message with `printf` and continues. text is
+    /// `location,"format",args...`.
+    fn add_dprintf(&mut self, text: &str) {
+        let comma = match text.find(',') {
+            Some(comma) => comma,
+            None => {
+                self.error("Format string required");
+                return;
+            }
+        };
+        let (location, printf_args) = (text[..comma].trim(), &text[comma + 1..]);
+        if let Err(err) = printf::parse_args(printf_args) {
+            self.error(&err);
+            return;
+        }
+        let kind = BreakpointKind::Dprintf(printf_args.trim().to_string());
+        self.add_breakpoints(location, kind);
+    }
+
+    /// Formats the arguments of a printf command (`"format", args...`). Arguments are evaluated
+    /// with evaluate.
+    fn format_printf(&mut self, text: &str) -> Result<String, String> {
+        let (format, args) = printf::parse_args(text)?;
+        let mut values = Vec::new();
+        for arg in &args {
+            let (value_type, bytes) = self
+                .evaluate(arg)?
+                .ok_or_else(|| "Value can't be converted to integer.".to_string())?;
+            values.push(printf_value(&value_type, &bytes)?);
+        }
+        let inferior = self.inferior.as_ref();
+        printf::format(&format, &values, &|addr| {
+            let mut bytes = Vec::new();
+            loop {
+                let byte = *inferior?.read_memory(addr + bytes.len(), 1).ok()?.first()?;
+                if byte == 0 || bytes.len() >= MAX_PRINTF_STRING_LEN {
+                    return Some(String::from_utf8_lossy(&bytes).to_string());
+                }
+                bytes.push(byte);
+            }
+        })
+    }
+
+    /// Evaluates an expression: a number, a value from the history, the name of a variable
+    /// visible where the inferior is stopped, or a call of a function in the inferior,
+    /// `name(args...)`. Returns the type and raw bytes of the value, or None for a call of a void
+    /// function.
+    fn evaluate(&mut self, expr: &str) -> Result<Option<(Type, Vec<u8>)>, String> {
+        let expr = expr.trim();
+        if expr.starts_with('$') {
+            return self.history_value(expr).map(Some);
+        }
+        if let Ok(value) = expr.parse::<i64>() {
+            let bytes = value.to_le_bytes().to_vec();
+            return Ok(Some((Type::new("long".to_string(), 8), bytes)));
+        }
+        if expr.to_lowercase().starts_with("0x") {
+            if let Some(addr) = Debugger::parse_address(expr) {
+                let bytes = (addr as u64).to_le_bytes().to_vec();
+                return Ok(Some((Type::new("unsigned long".to_string(), 8), bytes)));
+            }
+        }
+        if let Some(value) = parse_float(expr) {
+            let bytes = value.to_bits().to_le_bytes().to_vec();
+            return Ok(Some((Type::new("double".to_string(), 8), bytes)));
+        }
+        if expr.ends_with(')') {
+            if let Some(open) = expr.find('(') {
+                let args = printf::split_args(&expr[open + 1..expr.len() - 1]);
+                return self.call_function(expr[..open].trim(), &args);
+            }
+        }
+
+        let no_symbol = || format!("No symbol \"{}\" in current context.", expr);
+        let inferior = self.inferior.as_ref().ok_or_else(no_symbol)?;
+        let rip = inferior.get_registers().map_err(|err| err.to_string())?.rip as usize;
+        let var = self
+            .debug_data
+            .get_variable_at(expr, rip)
+            .ok_or_else(no_symbol)?;
+        let func = self.debug_data.get_function_data_from_addr(rip);
+        let frame = inferior
+            .stopped_frame(&self.debug_data, func)
+            .map_err(|err| err.to_string())?;
+        let bytes = inferior
+            .read_variable_bytes(var, &frame)
+            .map_err(|err| err.to_string())?
+            .map_err(|placeholder| format!("{} is {}", expr, placeholder))?;
+        Ok(Some((var.entity_type.clone(), bytes)))
+    }
+
+    /// Looks up a value history reference: $N is the Nth value printed, $ the last one, $$ the
+    /// one before it, and $$N the one N back from the last.
+    fn history_value(&self, expr: &str) -> Result<(Type, Vec<u8>), String> {
+        let len = self.value_history.len();
+        let number = if expr.starts_with("$$") {
+            let back = match &expr[2..] {
+                "" => 1,
+                back => back
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid history reference {}", expr))?,
+            };
+            len.saturating_sub(back)
+        } else {
+            match &expr[1..] {
+                "" => len,
+                number => number
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid history reference {}", expr))?,
+            }
+        };
+        if len == 0 {
+            return Err("History is empty.".to_string());
+        }
+        match number
+            .checked_sub(1)
+            .and_then(|i| self.value_history.get(i))
+        {
+            Some(value) => Ok(value.clone()),
+            None => Err(format!("History has not yet reached ${}.", number)),
+        }
+    }
+
+    /// Calls the function called name in the inferior, with the values of the given argument
+    /// expressions. The function returns to the program's entry point, which is never run again
+    /// once the program is going. Returns the function's result as for evaluate. Functions known
+    /// only from the symbol table are assumed to return an int.
+    fn call_function(
+        &mut self,
+        name: &str,
+        args: &[String],
+    ) -> Result<Option<(Type, Vec<u8>)>, String> {
+        if self.inferior.is_none() {
+            return Err("You can't do that without a process to debug.".to_string());
+        }
+        let (addr, param_types, return_type) = match self.debug_data.get_function_entry(name) {
+            Some((addr, Some(func))) => (
+                addr,
+                Some(
+                    func.variables
+                        .iter()
+                        .filter(|var| var.is_parameter)
+                        .map(|var| var.entity_type.name.clone())
+                        .collect::<Vec<_>>(),
+                ),
+                func.return_type.clone(),
+            ),
+            Some((addr, None)) => (addr, None, Some(Type::new("int".to_string(), 4))),
+            None => return Err(format!("No symbol \"{}\" in current context.", name)),
+        };
+        if let Some(param_types) = &param_types {
+            if args.len() < param_types.len() {
+                return Err("Too few arguments in function call.".to_string());
+            }
+        }
+        if let Some(return_type) = &return_type {
+            if return_type.size > size_of::<u64>() {
+                return Err(format!(
+                    "Can't call {}: returning values of type {} is not supported",
+                    name, return_type.name
+                ));
+            }
+        }
+
+        let mut int_args = Vec::new();
+        let mut float_args = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            let (arg_type, bytes) = self
+                .evaluate(arg)?
+                .ok_or_else(|| "Invalid cast.".to_string())?;
+            let value = printf_value(&arg_type, &bytes)?;
+            let param_type = param_types.as_ref().and_then(|types| types.get(i));
+            match param_type.map(|name| name.as_str()) {
+                Some("float") => float_args.push((value.as_f64() as f32).to_bits() as u64),
+                Some("double") => float_args.push(value.as_f64().to_bits()),
+                Some(_) => int_args.push(value.as_u64()),
+                // Without a prototype, floats are promoted to double
+                None if arg_type.name == "float" || arg_type.name == "double" => {
+                    float_args.push(value.as_f64().to_bits())
+                }
+                None => int_args.push(value.as_u64()),
+            }
+        }
+        if int_args.len() > 6 || float_args.len() > 8 {
+            return Err(format!(
+                "Can't call {}: too many arguments to pass in registers",
+                name
+            ));
+        }
+
+        let inferior = self.inferior.as_mut().unwrap();
+        let return_addr = shared_library::read_auxv(inferior.pid(), shared_library::AT_ENTRY)
+            .ok_or_else(|| "Can't find the program's entry point".to_string())?
+            as usize;
+        match inferior.call_function(addr, &int_args, &float_args, return_addr) {
+            Ok(Ok((rax, xmm0))) => Ok(return_type.map(|return_type| {
+                let raw = match return_type.name.as_str() {
+                    "float" | "double" => xmm0,
+                    _ => rax,
+                };
+                let bytes = raw.to_le_bytes()[..return_type.size].to_vec();
+                (return_type, bytes)
+            })),
+            Ok(Err(status @ Status::Exited(_))) | Ok(Err(status @ Status::Signaled(_))) => {
+                self.handle_status(status);
+                Err(format!(
+                    "The program being debugged exited while in a function called from deet.\n\
+                     Evaluation of the expression containing the function\n({}) will be abandoned.",
+                    name
+                ))
+            }
+            Ok(Err(_)) => Err(format!(
+                "The program being debugged was signaled while in a function called from deet.\n\
+                 Its state has been restored to what it was before the call.\n\
+                 Evaluation of the expression containing the function\n({}) will be abandoned.",
+                name
+            )),
+            Err(err) => Err(format!("Error calling {} -> {}", name, err)),
+        }
+    }
+
+    /// Formats a value, reading any memory it points to from the inferior.
+    fn format_value(&self, value_type: &Type, bytes: &[u8]) -> String {
+        let inferior = self.inferior.as_ref();
+        inferior::format_value(value_type, bytes, &|addr, len| {
+            inferior?.read_memory(addr, len).ok()
+        })
+    }
+
+    /// Evaluates and prints an expression for the print and call commands, adding its value to
+    /// the history. Void results are printed as "void" by print and not at all by call.
+    fn print_expression(&mut self, expr: &str, print_void: bool) {
+        if expr.trim().is_empty() {
+            self.error("Argument required (expression to compute).");
+            return;
+        }
+        let (number, value) = match self.evaluate(expr) {
+            Ok(Some((value_type, bytes))) => {
+                let value = self.format_value(&value_type, &bytes);
+                self.value_history.push((value_type, bytes));
+                (Some(self.value_history.len()), value)
+            }
+            Ok(None) if print_void => (None, "void".to_string()),
+            Ok(None) => return,
+            Err(err) => {
+                self.error(&err);
+                return;
+            }
+        };
+        let text = match number {
+            Some(number) => format!("${} = {}", number, value),
+            None => value.clone(),
+        };
+        self.emit(
+            "value",
+            vec![
+                ("expression", Json::from(expr.trim())),
+                ("history", Json::from(number)),
+                ("value", Json::from(value)),
+            ],
+            &text,
+        );
+    }
+
+    /// Adds an expression to show whenever the inferior stops, showing it right away if the
+    /// inferior is running. Without an expression, shows all of them.
+    fn add_display(&mut self, expr: &str) {
+        if expr.trim().is_empty() {
+            self.show_displays();
+            return;
+        }
+        self.displays.push(Some(expr.trim().to_string()));
+        if self.inferior.is_some() {
+            self.show_display(self.displays.len());
+        }
+    }
+
+    /// Deletes the given displays, or all of them if numbers is empty.
+    fn undisplay(&mut self, numbers: &[usize]) {
+        if numbers.is_empty() {
+            self.displays.clear();
+            return;
+        }
+        for number in numbers {
+            match number.checked_sub(1).and_then(|i| self.displays.get_mut(i)) {
+                Some(display) if display.is_some() => *display = None,
+                _ => self.error(&format!("No display number {}.", number)),
+            }
+        }
+    }
+
+    /// Evaluates and prints display number.
+    fn show_display(&mut self, number: usize) {
+        let expr = match &self.displays[number - 1] {
+            Some(expr) => expr.clone(),
+            None => return,
+        };
+        let value = match self.evaluate(&expr) {
+            Ok(Some((value_type, bytes))) => self.format_value(&value_type, &bytes),
+            Ok(None) => "void".to_string(),
+            Err(err) => {
+                self.error(&format!("{}: {}", number, err));
+                return;
+            }
+        };
+        let text = format!("{}: {} = {}", number, expr, value);
+        self.emit(
+            "display",
+            vec![
+                ("number", Json::from(number)),
+                ("expression", Json::from(expr)),
+                ("value", Json::from(value)),
+            ],
+            &text,
+        );
+    }
+
+    /// Shows every display, when the inferior stops.
+    fn show_displays(&mut self) {
+        for number in 1..=self.displays.len() {
+            if self.inferior.is_none() {
+                return;
+            }
+            self.show_display(number);
+        }
+    }
+
+    /// Returns the index of the first catchpoint that catches the given syscall.
+    fn catchpoint_for_syscall(&self, number: u64) -> Option<usize> {
+        self.syscall_catchpoints
+            .iter()
+            .position(|numbers| numbers.is_empty() || numbers.contains(&number))
+    }
+
+    fn cont_inferior(&mut self) {
+        match self.cont_until_stop() {
+            Ok(status) => self.handle_status(status),
+            Err(err) => self.error(&format!("Error continuing subprocess -> {}", err)),
+        }
+    }
+
+    /// Continues the inferior until it stops for a reason the user should hear about, and
+    /// returns that status.
+    fn cont_until_stop(&mut self) -> Result<Status, nix::Error> {
+        if self.recording.is_some() {
+            return self.record_until_stop();
+        }
+        let syscalls = self.trace_syscalls || !self.syscall_catchpoints.is_empty();
+        loop {
+            let breakpoints = self.active_breakpoints();
+            let inferior = self.inferior.as_mut().unwrap();
+            let status = inferior.cont(&breakpoints, syscalls)?;
+            // Syscall stops only reach the user if a catchpoint matches; tracing just logs them
+            let number = match status {
+                Status::SyscallEntry(number) => {
+                    if self.trace_syscalls {
+                        self.trace_syscall_entry(number);
+                    }
+                    number
+                }
+                Status::Stopped(Signal::SIGTRAP, rip) if self.is_linker_event(rip) => {
+                    self.update_shared_libraries();
+                    continue;
+                }
+                Status::SyscallExit(number, ret) => {
+                    if let Some(call) = self.pending_syscall.take() {
+                        self.report_syscall(&call, &syscalls::format_return(number, ret));
+                    }
+                    number
+                }
+                _ => {
+                    if let Some(call) = self.pending_syscall.take() {
+                        self.report_syscall(&call, "?");
+                    }
+                    return Ok(status);
+                }
+            };
+            if self.catchpoint_for_syscall(number).is_some() {
+                return Ok(status);
+            }
+        }
+    }
+
+    /// Continues the inferior one instruction at a time, so that every instruction is recorded,
+    /// until it reaches a breakpoint or stops for some other reason. Syscall catchpoints and
+    /// tracing don't apply while recording.
+    fn record_until_stop(&mut self) -> Result<Status, nix::Error> {
+        let inferior = self.inferior.as_mut().unwrap();
+        for addr in self.breakpoints.iter().flatten() {
+            let _ = inferior.set_breakpoint(*addr);
+        }
+        loop {
+            match self.single_step()? {
+                Status::Stopped(Signal::SIGTRAP, rip) if self.is_linker_event(rip) => {
+                    self.update_shared_libraries();
+                }
+                Status::Stopped(Signal::SIGTRAP, rip)
+                    if !self.inferior.as_ref().unwrap().has_breakpoint(rip) => {}
+                status => return Ok(status),
+            }
+        }
+    }
+
+    /// Executes one instruction, logging it if we're recording.
+    fn single_step(&mut self) -> Result<Status, nix::Error> {
+        let inferior = self.inferior.as_mut().unwrap();
+        let before = inferior.get_registers()?;
+        let status = inferior.step(None)?;
+        if let (Some(recording), Status::Stopped(_, _)) = (&mut self.recording, &status) {
+            let after = inferior.get_registers()?;
+            if let Err(err) = recording.record_step(before, &after) {
+                self.recording = None;
+                self.error(&format!("Process record stopped -> {}", err));
+            }
+        }
+        Ok(status)
+    }
+
+    /// Undoes the last recorded instruction, returning the new rip, or None if there's no more
+    /// execution history.
+    fn undo_step(&mut self) -> Result<Option<usize>, String> {
+        let recording = self.recording.as_mut().unwrap();
+        let regs = match recording.undo() {
+            Ok(Some(regs)) => regs,
+            Ok(None) => return Ok(None),
+            Err(err) => return Err(format!("Error restoring memory -> {}", err)),
+        };
+        let inferior = self.inferior.as_mut().unwrap();
+        inferior
+            .set_registers(regs)
+            .map_err(|err| format!("Error restoring registers -> {}", err))?;
+        Ok(Some(regs.rip as usize))
+    }
+
+    fn line_key(&self, addr: usize) -> Option<(String, usize)> {
+        self.debug_data
+            .get_line_from_addr(addr)
+            .map(|line| (line.file, line.number))
+    }
+
+    /// Runs backwards: one instruction (reverse-stepi), to the start of the previous line in this
+    /// frame (reverse-next), or to the previous breakpoint (reverse-continue).
+    fn reverse(&mut self, command: DebuggerCommand) {
+        if self.recording.is_none() {
+            self.error("Process record is not started.");
+            return;
+        }
+        let start_function = self.current_function();
+        let result = match command {
+            DebuggerCommand::ReverseStepInstruction => self.undo_step(),
+            DebuggerCommand::ReverseNext => self.reverse_next(),
+            _ => self.reverse_continue(),
+        };
+        match result {
+            Ok(Some(rip)) => match command {
+                DebuggerCommand::ReverseContinue => {
+                    self.handle_status(Status::Stopped(Signal::SIGTRAP, rip))
+                }
+                DebuggerCommand::ReverseNext => {
+                    let function_changed = self.current_function() != start_function;
+                    self.report_step(rip, function_changed);
+                }
+                _ => self.report_step(rip, true),
+            },
+            Ok(None) => {
+                self.message("No more reverse-execution history.");
+                match self.inferior.as_ref().unwrap().get_registers() {
+                    Ok(regs) => self.report_step(regs.rip as usize, true),
+                    Err(err) => self.error(&format!("Error reading registers -> {}", err)),
+                }
+            }
+            Err(err) => self.error(&err),
+        }
+    }
+
+    /// Undoes instructions back to the start of the previous line executed in the current frame
+    /// (or to the call, if that comes first), skipping over the insides of calls. Returns the new
+    /// rip, or None if the history ran out.
+    fn reverse_next(&mut self) -> Result<Option<usize>, String> {
+        let start_depth = self.recording.as_ref().unwrap().depth();
+        let rip = self
+            .inferior
+            .as_ref()
+            .unwrap()
+            .get_registers()
+            .map_err(|err| err.to_string())?
+            .rip as usize;
+        let start_line = self.line_key(rip);
+        // Back up to the last instruction of the previous line
+        let (mut rip, line) = loop {
+            let rip = match self.undo_step()? {
+                Some(rip) => rip,
+                None => return Ok(None),
+            };
+            let depth = self.recording.as_ref().unwrap().depth();
+            if depth < start_depth {
+                // We backed out of the current function, to its call
+                return Ok(Some(rip));
+            }
+            let line = self.line_key(rip);
+            if depth == start_depth && line != start_line {
+                break (rip, line);
+            }
+        };
+        // Then back to its first instruction, including any calls made from it
+        let steps = self.recording.as_ref().unwrap().steps();
+        let mut first = steps.len();
+        for (i, step) in steps.iter().enumerate().rev() {
+            if step.depth > start_depth {
+                continue;
+            }
+            if step.depth < start_depth || self.line_key(step.regs.rip as usize) != line {
+                break;
+            }
+            first = i;
+        }
+        for _ in first..steps.len() {
+            rip = self.undo_step()?.unwrap();
+        }
+        Ok(Some(rip))
+    }
+
+    /// Undoes instructions until reaching a breakpoint. Returns the breakpoint's address, or None
+    /// if the history ran out first.
+    fn reverse_continue(&mut self) -> Result<Option<usize>, String> {
+        while let Some(rip) = self.undo_step()? {
+            if self.breakpoints.contains(&Some(rip)) {
+                return Ok(Some(rip));
+            }
+        }
+        Ok(None)
+    }
+
+    fn restart_checkpoint(&mut self, n: usize) {
+        match self.inferior.as_mut().unwrap().restart(n) {
+            Ok(true) => {
+                // The execution log belongs to the process we just left behind
+                self.recording = None;
+                self.pending_syscall = None;
+                let inferior = self.inferior.as_ref().unwrap();
+                self.message(&format!("Switching to process {}", inferior.pid()));
+                let rip = inferior.get_registers().map(|regs| regs.rip).unwrap_or(0);
+                self.report_step(rip as usize, true);
+            }
+            Ok(false) => self.error(&format!("No checkpoint number {}.", n)),
+            Err(err) => self.error(&format!("Error restarting checkpoint -> {}", err)),
+        }
+    }
+
+    fn print_checkpoints(&self) {
+        let checkpoints = self.inferior.as_ref().unwrap().checkpoints();
+        if self.interpreter == Interpreter::Json {
+            let checkpoints = checkpoints
+                .iter()
+                .map(|(n, checkpoint)| {
+                    let mut fields = vec![
+                        ("number", Json::from(*n)),
+                        ("pid", Json::from(checkpoint.pid.as_raw())),
+                    ];
+                    fields.extend(self.location_fields(checkpoint.rip));
+                    Json::object(fields)
+                })
+                .collect();
+            self.emit(
+                "checkpoints",
+                vec![("checkpoints", Json::Array(checkpoints))],
+                "",
+            );
+        } else if checkpoints.is_empty() {
+            println!("No checkpoints.");
+        } else {
+            for (n, checkpoint) in checkpoints {
+                println!(
+                    "{} process {} at {}",
+                    n,
+                    checkpoint.pid,
+                    self.format_location(checkpoint.rip)
+                );
+            }
+        }
+    }
+
+    /// Executes a single machine instruction.
+    fn step_instruction(&mut self) {
+        match self.single_step() {
+            Ok(Status::Stopped(Signal::SIGTRAP, rip)) => self.report_step(rip, true),
+            Ok(status) => self.handle_status(status),
+            Err(err) => self.error(&format!("Error stepping subprocess -> {}", err)),
+        }
+    }
+
+    /// Steps the inferior to the next source line, stepping into called functions that have line
+    /// info (step) or over all calls (next, if over_calls is true).
+    fn step_line(&mut self, over_calls: bool) {
+        let start_function = self.current_function();
+        match self.step_line_status(over_calls) {
+            Ok(None) => match self.inferior.as_ref().unwrap().get_registers() {
+                Ok(regs) => {
+                    let function_changed = self.current_function() != start_function;
+                    self.report_step(regs.rip as usize, function_changed);
+                }
+                Err(err) => self.error(&format!("Error reading registers -> {}", err)),
+            },
+            Ok(Some(status)) => self.handle_status(status),
+            Err(err) => self.error(&format!("Error stepping subprocess -> {}", err)),
+        }
+    }
+
+    fn current_function(&self) -> Option<String> {
+        let regs = self.inferior.as_ref()?.get_registers().ok()?;
+        self.debug_data.get_function_from_addr(regs.rip as usize)
+    }
+
+    /// Does the work of step_line. Returns None once stepping is done, or the status if the
+    /// inferior stopped for some other reason along the way (a breakpoint, a signal, or exiting).
+    fn step_line_status(&mut self, over_calls: bool) -> Result<Option<Status>, nix::Error> {
+        let start_rip = self.inferior.as_ref().unwrap().get_registers()?.rip as usize;
+        let start_line = match self.debug_data.get_line_from_addr(start_rip) {
+            Some(line) => line,
+            None => {
+                self.message(
+                    "Single stepping until exit from function with no line number information.",
+                );
+                let frame = self
+                    .inferior
+                    .as_ref()
+                    .unwrap()
+                    .frame_info(&self.debug_data)?;
+                return self.run_to_return(frame.saved_rip, frame.cfa - 8);
+            }
+        };
+        loop {
+            let before = self.inferior.as_ref().unwrap().get_registers()?;
+            match self.single_step()? {
+                Status::Stopped(Signal::SIGTRAP, _) => {}
+                status => return Ok(Some(status)),
+            }
+            let inferior = self.inferior.as_ref().unwrap();
+            let regs = inferior.get_registers()?;
+            let mut rip = regs.rip as usize;
+            // A call pushes the address of the instruction after it, which is at most 15 bytes
+            // long
+            if regs.rsp == before.rsp - 8 {
+                let return_addr = Debugger::read_word(inferior, regs.rsp as usize)?;
+                if return_addr > before.rip as usize && return_addr <= before.rip as usize + 15 {
+                    let prologue_end = self.debug_data.get_prologue_end_for_entry(rip);
+                    match prologue_end {
+                        Some(prologue_end) if !over_calls => {
+                            while rip != prologue_end {
+                                match self.single_step()? {
+                                    Status::Stopped(Signal::SIGTRAP, addr) => rip = addr,
+                                    status => return Ok(Some(status)),
+                                }
+                            }
+                            return Ok(None);
+                        }
+                        _ => {
+                            if let Some(status) =
+                                self.run_to_return(return_addr, regs.rsp as usize)?
+                            {
+                                return Ok(Some(status));
+                            }
+                            rip = return_addr;
+                        }
+                    }
+                }
+            }
+            match self.debug_data.get_line_from_addr(rip) {
+                // Stop at the start of a different line, or when jumping back to the start of
+                // this one (in a loop)
+                Some(line) => {
+                    let new_line = line.file != start_line.file || line.number != start_line.number;
+                    if self.debug_data.is_line_start(rip) && (new_line || rip < before.rip as usize)
+                    {
+                        return Ok(None);
+                    }
+                }
+                // We returned into code without line info (e.g. from main into libc), so there's
+                // no next line to stop at
+                None => return Ok(Some(self.cont_until_stop()?)),
+            }
+        }
+    }
+
+    /// Like next, but doesn't stop at lines before the current one, so that `until` at the end of
+    /// a loop runs the rest of the loop. Stops anyway once the current frame returns.
+    fn until_next_line(&mut self) {
+        let start_function = self.current_function();
+        match self.until_next_line_status() {
+            Ok(None) => match self.inferior.as_ref().unwrap().get_registers() {
+                Ok(regs) => {
+                    let function_changed = self.current_function() != start_function;
+                    self.report_step(regs.rip as usize, function_changed);
+                }
+                Err(err) => self.error(&format!("Error reading registers -> {}", err)),
+            },
+            Ok(Some(status)) => self.handle_status(status),
+            Err(err) => self.error(&format!("Error stepping subprocess -> {}", err)),
+        }
+    }
+
+    /// Does the work of until_next_line, returning like step_line_status.
+    fn until_next_line_status(&mut self) -> Result<Option<Status>, nix::Error> {
+        let inferior = self.inferior.as_ref().unwrap();
+        let start_rip = inferior.get_registers()?.rip as usize;
+        let start_cfa = inferior.frame_info(&self.debug_data)?.cfa;
+        let start_line = self.debug_data.get_line_from_addr(start_rip);
+        loop {
+            if let Some(status) = self.step_line_status(true)? {
+                return Ok(Some(status));
+            }
+            let inferior = self.inferior.as_ref().unwrap();
+            let rip = inferior.get_registers()?.rip as usize;
+            if inferior.frame_info(&self.debug_data)?.cfa != start_cfa {
+                return Ok(None);
+            }
+            match (self.debug_data.get_line_from_addr(rip), &start_line) {
+                (Some(line), Some(start_line))
+                    if line.file == start_line.file && line.number <= start_line.number => {}
+                _ => return Ok(None),
+            }
+        }
+    }
+
+    /// Runs the inferior until it reaches location or the current frame returns, for until
+    /// (if same_frame is set, only stopping at location in the current frame or an outer one)
+    /// and advance.
+    fn run_to_location(&mut self, location: &str, same_frame: bool) {
+        let addrs: Vec<usize> = match self.resolve_location(location) {
+            Some(addrs) if addrs.is_empty() => {
+                self.error(&format!("Function \"{}\" not defined.", location));
+                return;
             }
-            Err(DwarfError::DwarfFormatError(err)) => {
-                println!("Could not debugging symbols from {}: {:?}", target, err);
-                std::process::exit(1);
+            Some(addrs) => addrs.into_iter().map(|(addr, _)| addr).collect(),
+            None => return,
+        };
+        let start_function = self.current_function();
+        match self.run_to_addrs(&addrs, same_frame) {
+            Ok(Status::Stopped(Signal::SIGTRAP, rip)) if !self.breakpoints.contains(&Some(rip)) => {
+                let function_changed = self.current_function() != start_function;
+                self.report_step(rip, function_changed);
+            }
+            Ok(status) => self.handle_status(status),
+            Err(err) => self.error(&format!("Error continuing subprocess -> {}", err)),
+        }
+    }
+
+    /// Does the work of run_to_location, given the location's addresses.
+    fn run_to_addrs(&mut self, addrs: &[usize], same_frame: bool) -> Result<Status, nix::Error> {
+        let inferior = self.inferior.as_mut().unwrap();
+        let frame = inferior.frame_info(&self.debug_data)?;
+        let mut temporary = Vec::new();
+        for addr in addrs.iter().chain(std::iter::once(&frame.saved_rip)) {
+            if !inferior.has_breakpoint(*addr) {
+                if let Err(err) = inferior.set_breakpoint(*addr) {
+                    // Don't leave the ones already placed behind in the inferior's code
+                    for addr in temporary {
+                        let _ = inferior.remove_breakpoint(addr);
+                    }
+                    return Err(err);
+                }
+                temporary.push(*addr);
+            }
+        }
+        let status = loop {
+            let status = self.cont_until_stop()?;
+            let inferior = match &self.inferior {
+                Some(inferior) => inferior,
+                None => return Ok(status),
+            };
+            if let Status::Stopped(Signal::SIGTRAP, rip) = status {
+                // Skip stops in frames deeper than the current one: returns from recursive
+                // calls, and (for until) the location reached from a callee
+                let cfa = inferior.frame_info(&self.debug_data)?.cfa;
+                let deeper = if addrs.contains(&rip) {
+                    same_frame && cfa < frame.cfa
+                } else {
+                    rip == frame.saved_rip && (inferior.get_registers()?.rsp as usize) < frame.cfa
+                };
+                if deeper && !self.breakpoints.contains(&Some(rip)) {
+                    continue;
+                }
             }
+            break status;
         };
+        let inferior = self.inferior.as_mut().unwrap();
+        for addr in temporary {
+            inferior.remove_breakpoint(addr)?;
+        }
+        Ok(status)
+    }
 
-        debug_data.print();
+    /// Resumes the inferior at location, for jump. A breakpoint there is reported as hit straight
+    /// away.
+    fn jump(&mut self, location: &str) {
+        let addr = match self.resolve_location(location) {
+            Some(addrs) if addrs.is_empty() => {
+                self.error(&format!("Function \"{}\" not defined.", location));
+                return;
+            }
+            Some(addrs) => addrs[0].0,
+            None => return,
+        };
+        let inferior = self.inferior.as_mut().unwrap();
+        let result = inferior.get_registers().and_then(|mut regs| {
+            regs.rip = addr as u64;
+            // Don't let the kernel restart a syscall we were stopped in
+            regs.orig_rax = u64::MAX;
+            inferior.set_registers(regs)
+        });
+        if let Err(err) = result {
+            self.error(&format!("Error setting rip -> {}", err));
+            return;
+        }
+        self.message(&format!("Continuing at {:#x}.", addr));
+        if self.breakpoints.contains(&Some(addr)) {
+            self.handle_status(Status::Stopped(Signal::SIGTRAP, addr));
+        } else {
+            self.cont_inferior();
+        }
+    }
 
-        let history_path = format!("{}/.deet_history", std::env::var("HOME").unwrap());
-        let mut readline = Editor::<()>::new();
-        // Attempt to load history from ~/.deet_history if it exists
-        let _ = readline.load_history(&history_path);
+    /// Pops the current frame, for return: its function returns to its caller right away, with
+    /// the value of expr (if given) as its return value.
+    fn return_from_frame(&mut self, expr: Option<String>) {
+        let value = match expr {
+            Some(expr) => match self.evaluate(&expr) {
+                Ok(Some((value_type, bytes))) => match printf_value(&value_type, &bytes) {
+                    Ok(value) => Some(value),
+                    Err(err) => {
+                        self.error(&err);
+                        return;
+                    }
+                },
+                Ok(None) => {
+                    self.error("Invalid cast.");
+                    return;
+                }
+                Err(err) => {
+                    self.error(&err);
+                    return;
+                }
+            },
+            None => None,
+        };
+        let inferior = self.inferior.as_mut().unwrap();
+        let rip = match inferior.get_registers() {
+            Ok(regs) => regs.rip as usize,
+            Err(err) => {
+                self.error(&format!("Error reading registers -> {}", err));
+                return;
+            }
+        };
+        // The value is converted to the function's return type, if known
+        let return_type = self
+            .debug_data
+            .get_function_data_from_addr(rip)
+            .and_then(|func| func.return_type.as_ref())
+            .map(|return_type| return_type.name.as_str());
+        let bits = value.map(|value| match return_type {
+            Some("float") => (value.as_f64() as f32).to_bits() as u64,
+            Some("double") => value.as_f64().to_bits(),
+            _ => value.as_u64(),
+        });
+        let float = matches!(return_type, Some("float") | Some("double"));
+        match inferior.pop_frame(&self.debug_data, bits, float) {
+            Ok(rip) => self.report_step(rip, true),
+            Err(err) => self.error(&format!("Error popping frame -> {}", err)),
+        }
+    }
 
-        Debugger {
-            target: target.to_string(),
-            history_path,
-            readline,
-            inferior: None,
-            debug_data,
-            breakpoints: vec![],
+    /// Runs the inferior until the call made with the stack pointer at call_rsp returns to
+    /// return_addr. Returns the status if the inferior stops for any other reason first.
+    fn run_to_return(
+        &mut self,
+        return_addr: usize,
+        call_rsp: usize,
+    ) -> Result<Option<Status>, nix::Error> {
+        let temporary = !self.breakpoints.contains(&Some(return_addr));
+        if temporary {
+            self.inferior
+                .as_mut()
+                .unwrap()
+                .set_breakpoint(return_addr)?;
+        }
+        loop {
+            let status = self.cont_until_stop()?;
+            let inferior = match &mut self.inferior {
+                Some(inferior) => inferior,
+                None => return Ok(Some(status)),
+            };
+            if let Status::Exited(_) | Status::Signaled(_) = status {
+                return Ok(Some(status));
+            }
+            if temporary {
+                if let Status::Stopped(Signal::SIGTRAP, rip) = status {
+                    // Returns from deeper recursive calls don't count
+                    if rip == return_addr && inferior.get_registers()?.rsp as usize <= call_rsp {
+                        continue;
+                    }
+                }
+                inferior.remove_breakpoint(return_addr)?;
+            }
+            return Ok(match status {
+                Status::Stopped(Signal::SIGTRAP, rip) if rip == return_addr && temporary => None,
+                status => Some(status),
+            });
         }
     }
 
-    fn handle_status(&mut self, status: Status) {
-        match status {
-            Status::Stopped(signal, rip) => {
-                let line = self.debug_data.get_line_from_addr(rip);
-                let func = self.debug_data.get_function_from_addr(rip);
-
-                if line.is_some() && func.is_some() {
-                    let line_unwrap = line.unwrap();
-                    let func_unwrap = func.unwrap();
-                    println!(
-                        "Child stopped by {} at {} ({}:{})",
-                        signal, func_unwrap, line_unwrap.file, line_unwrap.number
-                    );
-                } else {
-                    println!("Child stopped by {} at {:#x}", signal, rip);
+    fn read_word(inferior: &Inferior, addr: usize) -> Result<usize, nix::Error> {
+        let bytes = inferior.read_memory(addr, size_of::<usize>())?;
+        Ok(usize::from_le_bytes(bytes[..].try_into().unwrap()))
+    }
+
+    /// Reports that a step finished at rip, showing the new source line (and the new location,
+    /// if show_location is set or the source isn't available).
+    fn report_step(&mut self, rip: usize, mut show_location: bool) {
+        let line = self.debug_data.get_line_from_addr(rip);
+        let source = line
+            .as_ref()
+            .and_then(|line| match self.source_lines(&line.file) {
+                Some(lines) => Some(format!(
+                    "{}\t{}",
+                    line.number,
+                    lines.get(line.number.checked_sub(1)?)?
+                )),
+                None => {
+                    show_location = true;
+                    Some(format!(
+                        "{}\t{}: No such file or directory.",
+                        line.number, line.file
+                    ))
                 }
+            });
+        let text = match source {
+            Some(source) if !show_location => source,
+            Some(source) => format!("{}\n{}", self.format_location(rip), source),
+            None => self.format_location(rip),
+        };
+        let mut fields = vec![("reason", Json::from("end-stepping-range"))];
+        fields.extend(self.location_fields(rip));
+        self.emit("stopped", fields, &text);
+        self.show_displays();
+        self.refresh_tui();
+    }
+
+    /// Reads the lines of a source file, wherever find_source finds it.
+    fn source_lines(&self, path: &str) -> Option<Vec<String>> {
+        let text = fs::read_to_string(self.find_source(path)?).ok()?;
+        Some(text.lines().map(|line| line.to_string()).collect())
+    }
+
+    /// Finds the source file the debug info calls path: first with the substitute-path rules
+    /// applied, then in each source directory (by its path and then by its base name), and
+    /// finally where the debug info says it is.
+    fn find_source(&self, path: &str) -> Option<PathBuf> {
+        let mut candidates = Vec::new();
+        if let Some(substituted) = self
+            .substitute_paths
+            .iter()
+            .find_map(|(from, to)| replace_path_prefix(path, from, to))
+        {
+            candidates.push(PathBuf::from(substituted));
+        }
+        for dir in &self.source_directories {
+            candidates.push(Path::new(dir).join(path.trim_start_matches('/')));
+            if let Some(name) = Path::new(path).file_name() {
+                candidates.push(Path::new(dir).join(name));
             }
-            Status::Exited(exit_code) => {
-                self.inferior = None;
-                println!("Child exited (status {})", exit_code)
+        }
+        candidates.push(PathBuf::from(path));
+        candidates.into_iter().find(|candidate| candidate.is_file())
+    }
+
+    fn is_linker_event(&self, addr: usize) -> bool {
+        match &self.linker_hooks {
+            Some(hooks) => hooks.debug_state == addr,
+            None => false,
+        }
+    }
+
+    /// Syncs the shared libraries we know about with the dynamic linker's link map, then tries
+    /// to resolve pending breakpoints against newly loaded libraries.
+    fn update_shared_libraries(&mut self) {
+        let inferior = self.inferior.as_ref().unwrap();
+        let r_debug = self.linker_hooks.as_ref().unwrap().r_debug;
+        let loaded = match shared_library::read_link_map(inferior, r_debug) {
+            Ok(Some(loaded)) => loaded,
+            Ok(None) => return,
+            Err(err) => {
+                self.error(&format!("Error reading shared library list -> {}", err));
+                return;
             }
-            Status::Signaled(signal) => {
-                self.inferior = None;
-                println!("Child exited by {}", signal)
+        };
+        self.debug_data.retain_shared_libraries(|library| {
+            loaded
+                .iter()
+                .any(|(name, base)| *name == library.name && *base == library.base)
+        });
+        let mut added = false;
+        for (name, base) in loaded {
+            let known = self
+                .debug_data
+                .shared_libraries()
+                .iter()
+                .any(|library| library.name == name && library.base == base);
+            if !known {
+                if let Some(library) = SharedLibrary::load(&name, base) {
+                    self.debug_data.add_shared_library(library);
+                    added = true;
+                }
             }
         }
+        if added {
+            self.resolve_pending_breakpoints();
+        }
     }
 
-    fn kill_inferior(&mut self) {
-        let inferior = self.inferior.as_mut().unwrap();
-        match inferior.kill() {
-            Ok(status) => self.handle_status(status),
-            Err(err) => {
-                println!("Error killing subprocess -> {}", err);
+    fn resolve_pending_breakpoints(&mut self) {
+        let pending = std::mem::take(&mut self.pending_breakpoints);
+        for (func_name, kind) in pending {
+            let addrs = self
+                .debug_data
+                .get_addrs_for_function(None, &func_name, true);
+            if addrs.is_empty() {
+                self.pending_breakpoints.push((func_name, kind));
+                continue;
             }
+            let description = format!(" (function {})", func_name);
+            let addrs = addrs
+                .into_iter()
+                .map(|addr| (addr, description.clone()))
+                .collect();
+            self.place_breakpoints(addrs, &kind);
         }
     }
 
-    fn cont_inferior(&mut self) {
-        let inferior = self.inferior.as_mut().unwrap();
-        match inferior.cont(&self.breakpoints) {
-            Ok(status) => self.handle_status(status),
+    /// Decodes the arguments of the syscall being entered. The trace line is printed once the
+    /// syscall returns (or right away, for syscalls that never return).
+    fn trace_syscall_entry(&mut self, number: u64) {
+        let inferior = self.inferior.as_ref().unwrap();
+        let call = match inferior.get_registers() {
+            Ok(regs) => syscalls::format_call(inferior, &regs),
+            Err(err) => format!("<error reading registers: {}>", err),
+        };
+        if syscalls::is_noreturn(number) {
+            self.report_syscall(&call, "?");
+        } else {
+            self.pending_syscall = Some(call);
+        }
+    }
+
+    /// Logs a traced syscall.
+    fn report_syscall(&self, call: &str, ret: &str) {
+        self.emit(
+            "syscall",
+            vec![("call", Json::from(call)), ("return", Json::from(ret))],
+            &format!("{} = {}", call, ret),
+        );
+    }
+
+    /// Starts the inferior with the given arguments, killing the running one first if there is
+    /// one.
+    fn run_inferior(&mut self, args: &[String]) {
+        if !args.is_empty() {
+            self.run_args = args.to_vec();
+        }
+        let mut launch = match Launch::from_args(&self.run_args) {
+            Ok(launch) => launch,
             Err(err) => {
-                println!("Error continuing subprocess -> {}", err);
+                self.error(&err);
+                return;
+            }
+        };
+        launch.environment = Some(self.environment.clone());
+        launch.cwd = self.cwd.clone();
+        launch.tty = self.inferior_tty.clone();
+        launch.capture_output = self.interpreter == Interpreter::Json;
+        if let Some(inferior) = &self.inferior {
+            let text = format!(
+                "Killing the running inferior (pid {}) before running new inferior",
+                inferior.pid()
+            );
+            self.message(&text);
+            self.kill_inferior();
+        }
+        self.create_new_inferior(&launch);
+    }
+
+    fn create_new_inferior(&mut self, launch: &Launch) {
+        // Breakpoints are inserted below, once we know where the program was loaded
+        match Inferior::launch(&self.target, launch, &Vec::new()) {
+            Ok(mut inferior) => {
+                self.relocate_program(inferior.pid());
+                self.output_forwarder = inferior
+                    .take_output()
+                    .and_then(|pty| OutputForwarder::start(pty).ok());
+                for addr in self.active_breakpoints() {
+                    let _ = inferior.set_breakpoint(addr);
+                }
+                // Libraries are discovered afresh once the dynamic linker reports them
+                self.debug_data.retain_shared_libraries(|_| false);
+                self.linker_hooks = shared_library::find_linker_hooks(inferior.pid());
+                if let Some(hooks) = &self.linker_hooks {
+                    if let Err(err) = inferior.set_breakpoint(hooks.debug_state) {
+                        self.error(&format!(
+                            "Error setting shared library event breakpoint -> {}",
+                            err
+                        ));
+                        self.linker_hooks = None;
+                    }
+                }
+                // Create the inferior
+                self.inferior = Some(inferior);
+                // You may use self.inferior.as_mut().unwrap() to get a mutable reference
+                // to the Inferior object
+                self.cont_inferior();
             }
+            Err(err) => self.error(&format!("Error starting subprocess -> {}", err)),
         }
     }
 
-    fn create_new_inferior(&mut self, args: &Vec<String>) {
-        if let Some(inferior) = Inferior::new(&self.target, &args, &self.breakpoints) {
-            // Create the inferior
-            self.inferior = Some(inferior);
-            // You may use self.inferior.as_mut().unwrap() to get a mutable reference
-            // to the Inferior object
-            self.cont_inferior();
+    /// Moves the program's debug info to where a freshly started inferior was loaded, which only
+    /// differs from the link-time addresses for position-independent executables. Breakpoints
+    /// set before the program was loaded move along with it.
+    fn relocate_program(&mut self, pid: Pid) {
+        let entry = match shared_library::read_auxv(pid, shared_library::AT_ENTRY) {
+            Some(entry) => entry as usize,
+            None => return,
+        };
+        let delta = self.debug_data.relocate(entry);
+        if delta != 0 {
+            for addr in self.breakpoints.iter_mut().flatten() {
+                *addr = addr.wrapping_add(delta);
+            }
+        }
+    }
+
+    fn print_backtrace(&self, frames: &[Frame]) {
+        if self.interpreter == Interpreter::Json {
+            let frames = frames
+                .iter()
+                .map(|frame| {
+                    Json::object(vec![
+                        ("address", Json::from(format!("{:#x}", frame.address))),
+                        ("function", Json::from(frame.function.clone())),
+                        ("offset", Json::from(frame.offset)),
+                        (
+                            "file",
+                            Json::from(frame.line.as_ref().map(|l| l.file.clone())),
+                        ),
+                        ("line", Json::from(frame.line.as_ref().map(|l| l.number))),
+                        ("inlined", Json::from(frame.inlined)),
+                        ("library", Json::from(frame.library.clone())),
+                    ])
+                })
+                .collect();
+            self.emit("backtrace", vec![("frames", Json::Array(frames))], "");
+            return;
+        }
+        for frame in frames {
+            let inlined = if frame.inlined { " [inlined]" } else { "" };
+            match (&frame.function, &frame.line, frame.offset) {
+                (Some(func), Some(line), _) => {
+                    println!("{}{} ({}:{})", func, inlined, line.file, line.number)
+                }
+                (Some(func), None, Some(offset)) => match &frame.library {
+                    Some(library) => println!("{}+{:#x} in {}", func, offset, library),
+                    None => println!("{}+{:#x}", func, offset),
+                },
+                (Some(func), None, None) => println!("{}{}", func, inlined),
+                (None, _, _) => println!("??? [rip -> {:#x}]", frame.address),
+            }
+        }
+    }
+
+    /// Prints the formal parameters (if params is true) or the local variables of the current
+    /// function.
+    fn print_variables(&self, params: bool) {
+        let inferior = match &self.inferior {
+            Some(inferior) => inferior,
+            None => {
+                self.error("The program is not being run");
+                return;
+            }
+        };
+        let variables = match inferior.variables(&self.debug_data, params) {
+            Ok(Some(variables)) => variables,
+            Ok(None) => {
+                self.error("No symbol table info available.");
+                return;
+            }
+            Err(err) => {
+                self.error(&err.to_string());
+                return;
+            }
+        };
+        if self.interpreter == Interpreter::Json {
+            let variables = variables
+                .into_iter()
+                .map(|(name, value)| {
+                    Json::object(vec![
+                        ("name", Json::from(name)),
+                        ("value", Json::from(value)),
+                    ])
+                })
+                .collect();
+            self.emit("variables", vec![("variables", Json::Array(variables))], "");
+        } else if variables.is_empty() {
+            println!(
+                "{}",
+                if params {
+                    "No arguments."
+                } else {
+                    "No locals."
+                }
+            );
         } else {
-            println!("Error starting subprocess");
+            for (name, value) in variables {
+                println!("{} = {}", name, value);
+            }
+        }
+    }
+
+    fn print_frame_info(&self) {
+        let inferior = match &self.inferior {
+            Some(inferior) => inferior,
+            None => {
+                self.error("The program is not being run");
+                return;
+            }
+        };
+        let info = match inferior.frame_info(&self.debug_data) {
+            Ok(info) => info,
+            Err(err) => {
+                self.error(&err.to_string());
+                return;
+            }
+        };
+        if self.interpreter == Interpreter::Json {
+            let mut fields = vec![("cfa", Json::from(format!("{:#x}", info.cfa)))];
+            fields.extend(self.location_fields(info.rip));
+            fields.push(("saved_rip", Json::from(format!("{:#x}", info.saved_rip))));
+            fields.push(("caller_cfa", Json::from(format!("{:#x}", info.caller_cfa))));
+            fields.push(("caller_function", Json::from(info.caller_function)));
+            self.emit("frame", fields, "");
+            return;
+        }
+        println!("Stack frame at {:#x}:", info.cfa);
+        match (&info.function, &info.line) {
+            (Some(func), Some(line)) => println!(
+                " rip = {:#x} in {} ({}); saved rip = {:#x}",
+                info.rip, func, line, info.saved_rip
+            ),
+            _ => println!(" rip = {:#x}; saved rip = {:#x}", info.rip, info.saved_rip),
+        }
+        match (&info.caller_function, &info.caller_line) {
+            (Some(func), Some(line)) => println!(
+                " called by frame at {:#x} in {} ({})",
+                info.caller_cfa, func, line
+            ),
+            _ => println!(" caller unknown"),
+        }
+        println!(" Saved registers:");
+        println!("  rbp at {:#x}, rip at {:#x}", info.rbp, info.cfa - 8);
+    }
+
+    /// In JSON mode, reports that the current command has finished.
+    fn command_done(&mut self) {
+        for error in self.debug_data.take_load_errors() {
+            self.message(&error);
+        }
+        if self.interpreter == Interpreter::Json {
+            let id = std::mem::replace(&mut self.command_id, Json::Null);
+            self.emit("done", vec![("id", id)], "");
         }
     }
 
@@ -110,7 +2445,7 @@ impl Debugger {
         let addr_without_0x = if addr.to_lowercase().starts_with("0x") {
             &addr[2..]
         } else {
-            &addr
+            addr
         };
         usize::from_str_radix(addr_without_0x, 16).ok()
     }
@@ -119,127 +2454,427 @@ impl Debugger {
     pub fn run(&mut self) {
         loop {
             match self.get_next_command() {
-                DebuggerCommand::Run(args) => match &mut self.inferior {
-                    Some(inferior) => {
-                        println!(
-                            "Killing the running inferior (pid {}) before running new inferior",
-                            inferior.pid()
-                        );
-                        self.kill_inferior();
-                        self.create_new_inferior(&args);
-                    }
-                    None => {
-                        self.create_new_inferior(&args);
-                    }
-                },
+                DebuggerCommand::Run(args) => self.run_inferior(&args),
+                DebuggerCommand::Start(args) => {
+                    self.add_breakpoints("main", BreakpointKind::Temporary);
+                    self.run_inferior(&args);
+                }
                 DebuggerCommand::Continue => match &mut self.inferior {
                     Some(_) => self.cont_inferior(),
                     None => {
-                        println!("The program is not being run");
+                        self.error("The program is not being run");
                     }
                 },
                 DebuggerCommand::Quit => match &mut self.inferior {
                     Some(inferior) => {
-                        println!(
+                        let text = format!(
                             "Killing the running inferior (pid {}) before quitting",
                             inferior.pid()
                         );
+                        self.message(&text);
                         self.kill_inferior();
+                        self.command_done();
                         return;
                     }
                     None => {
+                        self.command_done();
                         return;
                     }
                 },
+                DebuggerCommand::Step
+                | DebuggerCommand::Next
+                | DebuggerCommand::StepInstruction
+                | DebuggerCommand::Until(_)
+                | DebuggerCommand::Advance(_)
+                | DebuggerCommand::Jump(_)
+                | DebuggerCommand::Return(_)
+                    if self.inferior.is_none() =>
+                {
+                    self.error("The program is not being run");
+                }
+                DebuggerCommand::Step => self.step_line(false),
+                DebuggerCommand::Next => self.step_line(true),
+                DebuggerCommand::StepInstruction => self.step_instruction(),
+                DebuggerCommand::Until(None) => self.until_next_line(),
+                DebuggerCommand::Until(Some(location)) => self.run_to_location(&location, true),
+                DebuggerCommand::Advance(location) => self.run_to_location(&location, false),
+                DebuggerCommand::Jump(location) => self.jump(&location),
+                DebuggerCommand::Return(expr) => self.return_from_frame(expr),
+                DebuggerCommand::Record(_)
+                | DebuggerCommand::ReverseStepInstruction
+                | DebuggerCommand::ReverseNext
+                | DebuggerCommand::ReverseContinue
+                    if self.inferior.is_none() =>
+                {
+                    self.error("The program is not being run");
+                }
+                DebuggerCommand::Record(true) => {
+                    if self.recording.is_none() {
+                        match Recording::start(self.inferior.as_ref().unwrap().pid()) {
+                            Ok(recording) => self.recording = Some(recording),
+                            Err(err) => self.error(&format!("Error starting record -> {}", err)),
+                        }
+                    } else {
+                        self.error("The process is already being recorded.");
+                    }
+                }
+                DebuggerCommand::Record(false) => {
+                    if self.recording.take().is_some() {
+                        self.message(
+                            "Process record is stopped and all execution logs are deleted.",
+                        );
+                    } else {
+                        self.error("Process record is not started.");
+                    }
+                }
+                DebuggerCommand::Checkpoint
+                | DebuggerCommand::Restart(_)
+                | DebuggerCommand::InfoCheckpoints
+                | DebuggerCommand::DeleteCheckpoint(_)
+                    if self.inferior.is_none() =>
+                {
+                    self.error("The program is not being run");
+                }
+                DebuggerCommand::Checkpoint => match self.inferior.as_mut().unwrap().checkpoint() {
+                    Ok((n, pid)) => self.emit(
+                        "checkpoint-created",
+                        vec![("number", Json::from(n)), ("pid", Json::from(pid.as_raw()))],
+                        &format!("Checkpoint {}: fork returned pid {}.", n, pid),
+                    ),
+                    Err(err) => self.error(&format!("Error making checkpoint -> {}", err)),
+                },
+                DebuggerCommand::Restart(n) => self.restart_checkpoint(n),
+                DebuggerCommand::InfoCheckpoints => self.print_checkpoints(),
+                DebuggerCommand::DeleteCheckpoint(n) => {
+                    if !self.inferior.as_mut().unwrap().delete_checkpoint(n) {
+                        self.error(&format!("No checkpoint number {}.", n));
+                    }
+                }
+                command @ DebuggerCommand::ReverseStepInstruction
+                | command @ DebuggerCommand::ReverseNext
+                | command @ DebuggerCommand::ReverseContinue => self.reverse(command),
                 DebuggerCommand::Backtrace => match &self.inferior {
-                    Some(inferior) => match inferior.print_backtrace(&self.debug_data) {
-                        Ok(_) => {}
-                        Err(err) => println!("{}", err),
+                    Some(inferior) => match inferior.backtrace(&self.debug_data) {
+                        Ok(frames) => self.print_backtrace(&frames),
+                        Err(err) => self.error(&err.to_string()),
                     },
                     None => {
-                        println!("The program is not being run");
+                        self.error("The program is not being run");
                     }
                 },
-                DebuggerCommand::Break(breakpoint) => {
-                    if !breakpoint.starts_with("*") {
-                        let line_wrap = usize::from_str_radix(&breakpoint, 10);
-                        if line_wrap.is_ok() {
-                            let line = line_wrap.unwrap();
-                            match self.debug_data.get_addr_for_line(None, line) {
-                                Some(addr) => {
-                                    let index = self.breakpoints.len();
-                                    self.breakpoints.push(addr);
-                                    println!("Set breakpoint {} at {:#x} (line {})", index, addr, line);
-                                }
-                                None => {
-                                    println!("Invalid line breakpoint");
-                                }
-                            }
+                DebuggerCommand::InfoArgs => self.print_variables(true),
+                DebuggerCommand::InfoLocals => self.print_variables(false),
+                DebuggerCommand::InfoFrame => self.print_frame_info(),
+                DebuggerCommand::InfoFunctions(regex) => {
+                    self.print_symbols(regex.as_deref(), false)
+                }
+                DebuggerCommand::InfoVariables(regex) => self.print_symbols(regex.as_deref(), true),
+                DebuggerCommand::InfoLine(location) => self.print_line_info(location.as_deref()),
+                DebuggerCommand::InfoScope(location) => self.print_scope(&location),
+                DebuggerCommand::InfoAddress(name) => self.print_address_info(&name),
+                DebuggerCommand::InfoSharedLibrary => self.print_shared_libraries(),
+                DebuggerCommand::Source(path) => match Debugger::read_script(&path) {
+                    Ok(lines) => {
+                        // When sourced from a script, a failing command fails that script too
+                        let end = if self.reading_script() {
+                            None
                         } else {
-                            match self.debug_data.get_addr_for_function(None, &breakpoint) {
-                                Some(addr) => {
-                                    let index = self.breakpoints.len();
-                                    self.breakpoints.push(addr);
-                                    println!("Set breakpoint {} at {:#x} (function {})", index, addr, &breakpoint);
-                                }
-                                None => {
-                                    println!("Invalid function breakpoint");
-                                }
-                            }
+                            Some(ScriptLine::EndOfScript)
+                        };
+                        self.run_lines_next(lines, end);
+                    }
+                    Err(err) => self.error(&format!("{}: {}", path, err)),
+                },
+                DebuggerCommand::Define(name) => self.define_command(name),
+                DebuggerCommand::CatchSyscall(syscall_names) => {
+                    let mut numbers = Vec::new();
+                    for name in &syscall_names {
+                        match name
+                            .parse::<u64>()
+                            .ok()
+                            .or_else(|| syscalls::syscall_number(name))
+                        {
+                            Some(number) => numbers.push(number),
+                            None => self.error(&format!("Unknown syscall name '{}'.", name)),
                         }
+                    }
+                    if numbers.len() != syscall_names.len() {
+                        self.command_done();
+                        continue;
+                    }
+                    let index = self.syscall_catchpoints.len();
+                    let fields = vec![
+                        ("number", Json::from(index)),
+                        (
+                            "syscalls",
+                            Json::Array(numbers.iter().map(|n| Json::from(*n as usize)).collect()),
+                        ),
+                    ];
+                    if numbers.is_empty() {
+                        self.emit(
+                            "catchpoint-created",
+                            fields,
+                            &format!("Catchpoint {} (any syscall)", index),
+                        );
                     } else {
-                        match Debugger::parse_address(&breakpoint[1..]) {
-                            Some(addr) => {
-                                let index = self.breakpoints.len();
-                                self.breakpoints.push(addr);
-                                println!("Set breakpoint {} at {:#x}", index, addr);
-                            }
-                            None => println!("Invalid address breakpoint"),
+                        let described: Vec<String> = numbers
+                            .iter()
+                            .map(|number| match syscalls::syscall_name(*number) {
+                                Some(name) => format!("'{}' [{}]", name, number),
+                                None => format!("{}", number),
+                            })
+                            .collect();
+                        let text =
+                            format!("Catchpoint {} (syscalls {})", index, described.join(" "));
+                        self.emit("catchpoint-created", fields, &text);
+                    }
+                    self.syscall_catchpoints.push(numbers);
+                }
+                DebuggerCommand::TraceSyscalls(enabled) => {
+                    self.trace_syscalls = enabled;
+                    if enabled {
+                        self.message("Tracing syscalls");
+                    } else {
+                        self.pending_syscall = None;
+                        self.message("Syscall tracing off");
+                    }
+                }
+                DebuggerCommand::Break(breakpoint) => {
+                    self.add_breakpoints(&breakpoint, BreakpointKind::Breakpoint);
+                }
+                DebuggerCommand::SetArgs(args) => self.run_args = args,
+                DebuggerCommand::SetEnvironment(name, value) => {
+                    let name = OsString::from(name);
+                    self.environment.retain(|(var, _)| *var != name);
+                    self.environment.push((name, OsString::from(value)));
+                }
+                DebuggerCommand::UnsetEnvironment(Some(name)) => {
+                    self.environment.retain(|(var, _)| *var != *name)
+                }
+                DebuggerCommand::UnsetEnvironment(None) => self.environment.clear(),
+                DebuggerCommand::SetCwd(cwd) => self.cwd = cwd,
+                DebuggerCommand::SetInferiorTty(tty) => self.inferior_tty = tty,
+                DebuggerCommand::SetSubstitutePath(from, to) => {
+                    let from = from.trim_end_matches('/').to_string();
+                    self.substitute_paths.retain(|(rule, _)| *rule != from);
+                    self.substitute_paths.push((from, to));
+                }
+                DebuggerCommand::UnsetSubstitutePath(Some(from)) => {
+                    let from = from.trim_end_matches('/');
+                    let count = self.substitute_paths.len();
+                    self.substitute_paths.retain(|(rule, _)| rule != from);
+                    if self.substitute_paths.len() == count {
+                        self.error(&format!("No substitution rule defined for \"{}\"", from));
+                    }
+                }
+                DebuggerCommand::UnsetSubstitutePath(None) => self.substitute_paths.clear(),
+                DebuggerCommand::Directory(dirs) => {
+                    if dirs.is_empty() {
+                        self.source_directories.clear();
+                    }
+                    // Like gdb, new directories go to the front, and may be given as dir1:dir2
+                    for dir in dirs.iter().rev() {
+                        for dir in dir.split(':').rev().filter(|dir| !dir.is_empty()) {
+                            self.source_directories.retain(|known| known != dir);
+                            self.source_directories.insert(0, dir.to_string());
                         }
                     }
+                    self.message(&format!(
+                        "Source directories searched: {}",
+                        self.source_directories.join(":")
+                    ));
                 }
+                DebuggerCommand::TemporaryBreak(location) => {
+                    self.add_breakpoints(&location, BreakpointKind::Temporary);
+                }
+                DebuggerCommand::Commands(index) => self.define_breakpoint_commands(index),
+                DebuggerCommand::Printf(text) => match self.format_printf(&text) {
+                    Ok(output) => match self.interpreter {
+                        Interpreter::Console => {
+                            print!("{}", output);
+                            let _ = io::stdout().flush();
+                        }
+                        Interpreter::Json => {
+                            self.emit("output", vec![("text", Json::from(output))], "")
+                        }
+                    },
+                    Err(err) => self.error(&err),
+                },
+                DebuggerCommand::Dprintf(text) => self.add_dprintf(&text),
+                DebuggerCommand::Print(expr) => self.print_expression(&expr, true),
+                DebuggerCommand::Call(expr) => self.print_expression(&expr, false),
+                DebuggerCommand::Display(expr) => self.add_display(&expr),
+                DebuggerCommand::Undisplay(numbers) => self.undisplay(&numbers),
             }
+            self.command_done();
         }
     }
 
-    /// This function prompts the user to enter a command, and continues re-prompting until the user
-    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
-    ///
-    /// You don't need to read, understand, or modify this function.
-    fn get_next_command(&mut self) -> DebuggerCommand {
+    /// Returns the next line of input: queued script lines first, then lines typed by the user.
+    /// Returns None once the user closes the input (ctrl+d).
+    fn next_line(&mut self, prompt: &str) -> Option<String> {
+        while let Some(line) = self.script_lines.pop_front() {
+            match line {
+                ScriptLine::Command(line) => return Some(line),
+                ScriptLine::EndOfUserCommand => self.user_call_depth -= 1,
+                ScriptLine::EndOfScript => {}
+            }
+        }
+        if self.interpreter == Interpreter::Json {
+            return self.next_json_line();
+        }
+        if let Some(tui) = &mut self.tui {
+            return tui.read_line(prompt);
+        }
         loop {
-            // Print prompt and get next line of user input
-            match self.readline.readline("(deet) ") {
+            match self.readline.readline(prompt) {
                 Err(ReadlineError::Interrupted) => {
                     // User pressed ctrl+c. We're going to ignore it
                     println!("Type \"quit\" to exit");
                 }
-                Err(ReadlineError::Eof) => {
-                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
-                    return DebuggerCommand::Quit;
-                }
+                Err(ReadlineError::Eof) => return None,
                 Err(err) => {
                     panic!("Unexpected I/O error: {:?}", err);
                 }
                 Ok(line) => {
-                    if line.trim().len() == 0 {
-                        continue;
-                    }
-                    self.readline.add_history_entry(line.as_str());
-                    if let Err(err) = self.readline.save_history(&self.history_path) {
-                        println!(
-                            "Warning: failed to save history file at {}: {}",
-                            self.history_path, err
-                        );
-                    }
-                    let tokens: Vec<&str> = line.split_whitespace().collect();
-                    if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
-                        return cmd;
-                    } else {
-                        println!("Unrecognized command.");
+                    if !line.trim().is_empty() {
+                        self.readline.add_history_entry(line.as_str());
+                        if let Err(err) = self.readline.save_history(&self.history_path) {
+                            println!(
+                                "Warning: failed to save history file at {}: {}",
+                                self.history_path, err
+                            );
+                        }
                     }
+                    return Some(line);
+                }
+            }
+        }
+    }
+
+    /// Reads the next command line from a JSON request on stdin: either an object with a
+    /// "command" field (and optionally an "id" to be echoed back), or just a string.
+    fn next_json_line(&mut self) -> Option<String> {
+        loop {
+            let mut input = String::new();
+            match io::stdin().read_line(&mut input) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(err) => panic!("Unexpected I/O error: {:?}", err),
+            }
+            if input.trim().is_empty() {
+                continue;
+            }
+            let request = match json::parse(input.trim()) {
+                Ok(request) => request,
+                Err(err) => {
+                    self.error(&format!("Invalid JSON request: {}", err));
+                    continue;
+                }
+            };
+            if let Some(command) = request.as_str() {
+                return Some(command.to_string());
+            }
+            match request.get("command").and_then(|command| command.as_str()) {
+                Some(command) => {
+                    self.command_id = request.get("id").cloned().unwrap_or(Json::Null);
+                    return Some(command.to_string());
+                }
+                None => self.error("Request has no \"command\""),
+            }
+        }
+    }
+
+    /// This function prompts the user to enter a command, and continues re-prompting until the user
+    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
+    /// Commands from scripts and user-defined commands are handled here too.
+    fn get_next_command(&mut self) -> DebuggerCommand {
+        loop {
+            // Like gdb, give up on a script once one of its commands fails
+            if self.command_failed.replace(false) {
+                self.abort_script();
+            }
+            let line = match self.next_line("(deet) ") {
+                Some(line) => line,
+                // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
+                None => return DebuggerCommand::Quit,
+            };
+            // Skip blank lines and comments (in scripts)
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if let Some(body) = self.user_commands.get(tokens[0]) {
+                let lines = Debugger::expand_user_command(body, &tokens[1..]);
+                if self.user_call_depth >= MAX_USER_CALL_DEPTH {
+                    self.error("Max user call depth exceeded -- command aborted.");
+                    self.command_done();
+                    continue;
                 }
+                self.user_call_depth += 1;
+                self.run_lines_next(lines, Some(ScriptLine::EndOfUserCommand));
+                continue;
+            }
+            if let Some(cmd) = DebuggerCommand::from_tokens(&tokens, &line) {
+                return cmd;
+            } else {
+                self.error("Unrecognized command.");
+                self.command_done();
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_whole_path_components() {
+        assert_eq!(
+            replace_path_prefix("/build/src/main.c", "/build", "/home/me/src"),
+            Some("/home/me/src/src/main.c".to_string())
+        );
+        assert_eq!(
+            replace_path_prefix("/build/src/main.c", "/build/", "/moved/"),
+            Some("/moved/src/main.c".to_string())
+        );
+        assert_eq!(
+            replace_path_prefix("/build", "/build", "/moved"),
+            Some("/moved".to_string())
+        );
+        assert_eq!(
+            replace_path_prefix("/builder/main.c", "/build", "/moved"),
+            None
+        );
+        assert_eq!(
+            replace_path_prefix("/other/main.c", "/build", "/moved"),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_only_numeric_floats() {
+        assert_eq!(parse_float("2.5"), Some(2.5));
+        assert_eq!(parse_float("-.5"), Some(-0.5));
+        assert_eq!(parse_float("1e3"), Some(1000.0));
+        assert_eq!(parse_float("nan"), None);
+        assert_eq!(parse_float("inf"), None);
+        assert_eq!(parse_float("-infinity"), None);
+        assert_eq!(parse_float("x1"), None);
+    }
+
+    #[test]
+    fn parses_file_line_locations() {
+        assert_eq!(Debugger::parse_file_line("main.c:12"), Some(("main.c", 12)));
+        assert_eq!(
+            Debugger::parse_file_line("/src/a:b.c:7"),
+            Some(("/src/a:b.c", 7))
+        );
+        assert_eq!(Debugger::parse_file_line("main.c"), None);
+        assert_eq!(Debugger::parse_file_line("main.c:"), None);
+        assert_eq!(Debugger::parse_file_line(":12"), None);
+        // Rust paths aren't file names
+        assert_eq!(Debugger::parse_file_line("r::show"), None);
+    }
+}
@@ -1,13 +1,70 @@
 pub enum DebuggerCommand {
     Quit,
     Run(Vec<String>),
+    SetArgs(Vec<String>),
+    SetEnvironment(String, String),
+    UnsetEnvironment(Option<String>),
+    SetCwd(Option<String>),
+    SetInferiorTty(Option<String>),
+    SetSubstitutePath(String, String),
+    UnsetSubstitutePath(Option<String>),
+    Directory(Vec<String>),
+    Start(Vec<String>),
     Continue,
+    Step,
+    Next,
+    StepInstruction,
+    Until(Option<String>),
+    Advance(String),
+    Jump(String),
+    Return(Option<String>),
+    Record(bool),
+    ReverseStepInstruction,
+    ReverseNext,
+    ReverseContinue,
+    Checkpoint,
+    Restart(usize),
+    InfoCheckpoints,
+    DeleteCheckpoint(usize),
     Backtrace,
     Break(String),
+    TemporaryBreak(String),
+    InfoArgs,
+    InfoLocals,
+    InfoFrame,
+    InfoFunctions(Option<String>),
+    InfoVariables(Option<String>),
+    InfoLine(Option<String>),
+    InfoScope(String),
+    InfoAddress(String),
+    InfoSharedLibrary,
+    CatchSyscall(Vec<String>),
+    TraceSyscalls(bool),
+    Source(String),
+    Define(String),
+    Commands(Option<usize>),
+    Printf(String),
+    Print(String),
+    Call(String),
+    Display(String),
+    Undisplay(Vec<usize>),
+    Dprintf(String),
+}
+
+/// Returns what follows the first count words of line, keeping the whitespace inside it as typed
+/// (for format strings, for instance).
+fn rest_of_line(line: &str, count: usize) -> &str {
+    let mut rest = line.trim();
+    for _ in 0..count {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        rest = rest[end..].trim_start();
+    }
+    rest
 }
 
 impl DebuggerCommand {
-    pub fn from_tokens(tokens: &Vec<&str>) -> Option<DebuggerCommand> {
+    /// Parses a command from the whitespace-separated tokens of line.
+    pub fn from_tokens(tokens: &Vec<&str>, line: &str) -> Option<DebuggerCommand> {
         match tokens[0] {
             "q" | "quit" => Some(DebuggerCommand::Quit),
             "r" | "run" => {
@@ -16,11 +73,178 @@ impl DebuggerCommand {
                     args.iter().map(|s| s.to_string()).collect(),
                 ))
             }
+            "set" => match *tokens.get(1)? {
+                "args" => Some(DebuggerCommand::SetArgs(
+                    tokens[2..].iter().map(|s| s.to_string()).collect(),
+                )),
+                // set environment NAME=VALUE, or NAME VALUE
+                "env" | "environment" => {
+                    let text = rest_of_line(line, 2);
+                    let (name, value) = match text.find('=') {
+                        Some(equals) => (text[..equals].trim(), text[equals + 1..].trim()),
+                        None => match text.find(char::is_whitespace) {
+                            Some(space) => (&text[..space], text[space + 1..].trim()),
+                            None => (text, ""),
+                        },
+                    };
+                    if name.is_empty() {
+                        return None;
+                    }
+                    Some(DebuggerCommand::SetEnvironment(
+                        name.to_string(),
+                        value.to_string(),
+                    ))
+                }
+                "cwd" => Some(DebuggerCommand::SetCwd(
+                    tokens.get(2).map(|_| rest_of_line(line, 2).to_string()),
+                )),
+                "inferior-tty" => Some(DebuggerCommand::SetInferiorTty(
+                    tokens.get(2).map(|tty| tty.to_string()),
+                )),
+                "substitute-path" => Some(DebuggerCommand::SetSubstitutePath(
+                    tokens.get(2)?.to_string(),
+                    tokens.get(3)?.to_string(),
+                )),
+                _ => None,
+            },
+            "unset" => match *tokens.get(1)? {
+                "env" | "environment" => Some(DebuggerCommand::UnsetEnvironment(
+                    tokens.get(2).map(|name| name.to_string()),
+                )),
+                "substitute-path" => Some(DebuggerCommand::UnsetSubstitutePath(
+                    tokens.get(2).map(|from| from.to_string()),
+                )),
+                _ => None,
+            },
+            "dir" | "directory" => Some(DebuggerCommand::Directory(
+                tokens[1..].iter().map(|dir| dir.to_string()).collect(),
+            )),
+            "tty" => Some(DebuggerCommand::SetInferiorTty(Some(
+                tokens.get(1)?.to_string(),
+            ))),
+            "start" => Some(DebuggerCommand::Start(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
             "c" | "cont" | "continue" => Some(DebuggerCommand::Continue),
+            "s" | "step" => Some(DebuggerCommand::Step),
+            "n" | "next" => Some(DebuggerCommand::Next),
+            "si" | "stepi" => Some(DebuggerCommand::StepInstruction),
+            "u" | "until" => Some(DebuggerCommand::Until(
+                tokens.get(1).map(|location| location.to_string()),
+            )),
+            "advance" => Some(DebuggerCommand::Advance(tokens.get(1)?.to_string())),
+            "j" | "jump" => Some(DebuggerCommand::Jump(tokens.get(1)?.to_string())),
+            "return" if tokens.len() > 1 => {
+                Some(DebuggerCommand::Return(Some(tokens[1..].join(" "))))
+            }
+            "return" => Some(DebuggerCommand::Return(None)),
+            "rec" | "record" => match tokens.get(1) {
+                None => Some(DebuggerCommand::Record(true)),
+                Some(&"stop") => Some(DebuggerCommand::Record(false)),
+                _ => None,
+            },
+            "rsi" | "reverse-stepi" => Some(DebuggerCommand::ReverseStepInstruction),
+            "rn" | "reverse-next" => Some(DebuggerCommand::ReverseNext),
+            "rc" | "reverse-continue" => Some(DebuggerCommand::ReverseContinue),
+            "checkpoint" => Some(DebuggerCommand::Checkpoint),
+            "restart" => Some(DebuggerCommand::Restart(tokens.get(1)?.parse().ok()?)),
+            "delete" => match *tokens.get(1)? {
+                "checkpoint" => Some(DebuggerCommand::DeleteCheckpoint(
+                    tokens.get(2)?.parse().ok()?,
+                )),
+                _ => None,
+            },
             "bt" | "back" | "backtrace" => Some(DebuggerCommand::Backtrace),
-            "b" | "break" => Some(DebuggerCommand::Break(tokens[1].to_string())),
+            "b" | "break" => Some(DebuggerCommand::Break(tokens.get(1)?.to_string())),
+            "tb" | "tbreak" => Some(DebuggerCommand::TemporaryBreak(tokens.get(1)?.to_string())),
+            "i" | "info" => match *tokens.get(1)? {
+                "args" => Some(DebuggerCommand::InfoArgs),
+                "locals" => Some(DebuggerCommand::InfoLocals),
+                "f" | "frame" => Some(DebuggerCommand::InfoFrame),
+                "functions" => Some(DebuggerCommand::InfoFunctions(
+                    tokens.get(2).map(|regex| regex.to_string()),
+                )),
+                "variables" => Some(DebuggerCommand::InfoVariables(
+                    tokens.get(2).map(|regex| regex.to_string()),
+                )),
+                "line" => Some(DebuggerCommand::InfoLine(
+                    tokens.get(2).map(|location| location.to_string()),
+                )),
+                "scope" => Some(DebuggerCommand::InfoScope(tokens.get(2)?.to_string())),
+                "address" => Some(DebuggerCommand::InfoAddress(tokens.get(2)?.to_string())),
+                "sharedlibrary" | "dll" => Some(DebuggerCommand::InfoSharedLibrary),
+                "checkpoints" => Some(DebuggerCommand::InfoCheckpoints),
+                _ => None,
+            },
+            "catch" => match *tokens.get(1)? {
+                "syscall" => Some(DebuggerCommand::CatchSyscall(
+                    tokens[2..].iter().map(|s| s.to_string()).collect(),
+                )),
+                _ => None,
+            },
+            "trace" => match *tokens.get(1)? {
+                "syscalls" => Some(DebuggerCommand::TraceSyscalls(true)),
+                "off" => Some(DebuggerCommand::TraceSyscalls(false)),
+                _ => None,
+            },
+            "source" if tokens.len() > 1 => Some(DebuggerCommand::Source(tokens[1..].join(" "))),
+            "define" => Some(DebuggerCommand::Define(tokens.get(1)?.to_string())),
+            "commands" => match tokens.get(1) {
+                Some(index) => Some(DebuggerCommand::Commands(Some(index.parse().ok()?))),
+                None => Some(DebuggerCommand::Commands(None)),
+            },
+            "printf" => Some(DebuggerCommand::Printf(rest_of_line(line, 1).to_string())),
+            "p" | "print" => Some(DebuggerCommand::Print(tokens[1..].join(" "))),
+            "call" => Some(DebuggerCommand::Call(tokens[1..].join(" "))),
+            "display" => Some(DebuggerCommand::Display(tokens[1..].join(" "))),
+            "undisplay" => Some(DebuggerCommand::Undisplay(
+                tokens[1..]
+                    .iter()
+                    .map(|number| number.parse().ok())
+                    .collect::<Option<Vec<usize>>>()?,
+            )),
+            "dprintf" => Some(DebuggerCommand::Dprintf(rest_of_line(line, 1).to_string())),
             // Default case:
             _ => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<DebuggerCommand> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        DebuggerCommand::from_tokens(&tokens, line)
+    }
+
+    #[test]
+    fn keeps_spacing_of_printf_formats() {
+        assert!(matches!(
+            parse("printf  \"a   b\\t\tc\\n\", x"),
+            Some(DebuggerCommand::Printf(text)) if text == "\"a   b\\t\tc\\n\", x"
+        ));
+        assert!(matches!(
+            parse("dprintf func3,\"x  =  %d\\n\", a"),
+            Some(DebuggerCommand::Dprintf(text)) if text == "func3,\"x  =  %d\\n\", a"
+        ));
+    }
+
+    #[test]
+    fn keeps_spacing_of_paths_and_values() {
+        assert!(matches!(
+            parse("set cwd /tmp/two  spaces "),
+            Some(DebuggerCommand::SetCwd(Some(dir))) if dir == "/tmp/two  spaces"
+        ));
+        assert!(matches!(
+            parse("set environment GREETING = hello   there"),
+            Some(DebuggerCommand::SetEnvironment(name, value))
+                if name == "GREETING" && value == "hello   there"
+        ));
+        assert!(matches!(
+            parse("set env EDITOR\tvi"),
+            Some(DebuggerCommand::SetEnvironment(name, value)) if name == "EDITOR" && value == "vi"
+        ));
+    }
+}
@@ -1,6 +1,10 @@
 use crate::gimli_wrapper;
+use crate::shared_library::SharedLibrary;
 use addr2line::Context;
-use object::Object;
+use object::{Object, ObjectSection};
+use once_cell::unsync::OnceCell;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::convert::TryInto;
 use std::{fmt, fs};
 
@@ -11,13 +15,29 @@ pub enum Error {
 }
 
 pub struct DwarfData {
-    files: Vec<File>,
-    addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
+    units: Vec<Unit>,
+    functions_by_name: HashMap<String, Vec<usize>>, // Function name -> indexes into units
+    variables_by_name: HashMap<String, Vec<usize>>, // Global variable name -> indexes into units
+    unit_ranges: Vec<(usize, usize, usize)>, // (low_pc, high_pc, unit index), sorted by low_pc
+    path: String,                            // Where to look for split DWARF (.dwo/.dwp) files
+    mmap: Option<memmap::Mmap>,              // The binary, which units are parsed from
+    endian: gimli::RunTimeEndian,
+    // None if addr2line can't read the DWARF (e.g. DWARF 5 split units), in which case lookups
+    // fall back to the units' own line tables
+    addr2line: Option<Context<gimli::EndianRcSlice<gimli::RunTimeEndian>>>,
+    eh_frame: Option<(u64, Vec<u8>)>, // Address and contents of the .eh_frame section
+    symbols: Vec<Symbol>,             // Function symbols from .symtab/.dynsym, sorted by address
+    entry: usize,                     // Entry point given in the ELF header
+    bias: usize, // How far the program was loaded from its link-time addresses (PIE executables)
+    dynamic: bool, // Whether the program has an interpreter (PT_INTERP) to load shared libraries
+    shared_libraries: Vec<SharedLibrary>,
+    load_errors: RefCell<Vec<String>>, // Units that failed to parse, until they're reported
 }
 
 impl fmt::Debug for DwarfData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "DwarfData {{files: {:?}}}", self.files)
+        let names: Vec<&str> = self.units.iter().map(|unit| unit.name.as_str()).collect();
+        write!(f, "DwarfData {{units: {:?}}}", names)
     }
 }
 
@@ -29,41 +49,358 @@ impl From<gimli_wrapper::Error> for Error {
 
 impl DwarfData {
     pub fn from_file(path: &str) -> Result<DwarfData, Error> {
+        DwarfData::load(path, true)
+    }
+
+    /// Loads only the ELF symbol table and unwind information of path, for binaries whose DWARF
+    /// is missing or can't be parsed.
+    pub fn symbols_from_file(path: &str) -> Result<DwarfData, Error> {
+        DwarfData::load(path, false)
+    }
+
+    fn load(path: &str, with_dwarf: bool) -> Result<DwarfData, Error> {
         let file = fs::File::open(path).or(Err(Error::ErrorOpeningFile))?;
         let mmap = unsafe { memmap::Mmap::map(&file).or(Err(Error::ErrorOpeningFile))? };
-        let object = object::File::parse(&*mmap)
-            .or_else(|e| Err(gimli_wrapper::Error::ObjectError(e.to_string())))?;
+        let object =
+            object::File::parse(&mmap).map_err(|e| gimli_wrapper::Error::Object(e.to_string()))?;
         let endian = if object.is_little_endian() {
             gimli::RunTimeEndian::Little
         } else {
             gimli::RunTimeEndian::Big
         };
+        let entry = object.entry() as usize;
+        // PT_INTERP points into the .interp section
+        let dynamic = object.section_by_name(".interp").is_some();
+        let eh_frame = match (
+            object.section_by_name(".eh_frame"),
+            gimli_wrapper::section_data(&object, ".eh_frame"),
+        ) {
+            (Some(section), Some(data)) => Some((section.address(), data.into_owned())),
+            _ => None,
+        };
+        let mut symbols: Vec<Symbol> = object
+            .symbols()
+            .chain(object.dynamic_symbols())
+            .filter(|(_, symbol)| {
+                symbol.kind() == object::SymbolKind::Text
+                    && !symbol.is_undefined()
+                    && symbol.address() != 0
+            })
+            .filter_map(|(_, symbol)| {
+                let name = symbol.name()?;
+                Some(Symbol {
+                    // Rust symbols (both the legacy and v0 manglings) are shown demangled
+                    name: addr2line::demangle(name, gimli::DW_LANG_Rust)
+                        .unwrap_or_else(|| name.to_string()),
+                    address: symbol.address() as usize,
+                    size: symbol.size() as usize,
+                })
+            })
+            .collect();
+        symbols.sort_by_key(|symbol| symbol.address);
+        // .dynsym mostly repeats .symtab
+        symbols.dedup_by(|a, b| a.address == b.address && a.name == b.name);
+        let (index, addr2line) = if with_dwarf {
+            (
+                gimli_wrapper::load_index(&object, endian, path)?,
+                Context::new(&object).ok(),
+            )
+        } else {
+            (Default::default(), None)
+        };
+        let gimli_wrapper::Index {
+            units,
+            functions,
+            variables,
+            ranges,
+        } = index;
         Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian)?,
-            addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+            units: units
+                .into_iter()
+                .map(|(offset, name, files)| Unit {
+                    offset,
+                    name,
+                    files,
+                    file: OnceCell::new(),
+                })
+                .collect(),
+            functions_by_name: functions,
+            variables_by_name: variables,
+            unit_ranges: ranges,
+            path: path.to_string(),
+            mmap: if with_dwarf { Some(mmap) } else { None },
+            endian,
+            addr2line,
+            eh_frame,
+            symbols,
+            entry,
+            bias: 0,
+            dynamic,
+            shared_libraries: Vec::new(),
+            load_errors: RefCell::new(Vec::new()),
         })
     }
 
-    #[allow(dead_code)]
-    fn get_target_file(&self, file: &str) -> Option<&File> {
-        self.files.iter().find(|f| {
-            f.name == file || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
+    /// Relocates the program for having been loaded with its entry point at entry. A
+    /// position-independent executable only gets its addresses once it starts, so everything
+    /// until then is at its link-time address. Returns how far addresses moved (as a wrapping
+    /// offset, so 0 if they didn't).
+    pub fn relocate(&mut self, entry: usize) -> usize {
+        let bias = entry.wrapping_sub(self.entry);
+        let delta = bias.wrapping_sub(self.bias);
+        if delta == 0 {
+            return 0;
+        }
+        self.bias = bias;
+        for unit in self.units.iter_mut() {
+            if let Some(file) = unit.file.get_mut() {
+                file.relocate(delta);
+            }
+        }
+        for range in self.unit_ranges.iter_mut() {
+            range.0 = range.0.wrapping_add(delta);
+            range.1 = range.1.wrapping_add(delta);
+        }
+        for symbol in self.symbols.iter_mut() {
+            symbol.address = symbol.address.wrapping_add(delta);
+        }
+        if let Some((address, _)) = self.eh_frame.as_mut() {
+            *address = address.wrapping_add(delta as u64);
+        }
+        delta
+    }
+
+    /// Returns true if any compilation unit with debugging information was found.
+    pub fn has_debug_info(&self) -> bool {
+        !self.units.is_empty()
+    }
+
+    /// Returns the compilation unit at index, parsing it if this is the first time it's needed.
+    fn file(&self, index: usize) -> &File {
+        let unit = &self.units[index];
+        unit.file.get_or_init(|| {
+            let parsed = self
+                .mmap
+                .as_ref()
+                .ok_or(Error::ErrorOpeningFile)
+                .and_then(|mmap| {
+                    let object = object::File::parse(mmap)
+                        .map_err(|e| gimli_wrapper::Error::Object(e.to_string()))?;
+                    Ok(gimli_wrapper::load_unit(
+                        &object,
+                        self.endian,
+                        &self.path,
+                        unit.offset,
+                    )?)
+                });
+            let mut file = parsed.unwrap_or_else(|err| {
+                self.load_errors.borrow_mut().push(format!(
+                    "Could not load debugging symbols for {}: {:?}",
+                    unit.name, err
+                ));
+                File {
+                    name: unit.name.clone(),
+                    ..Default::default()
+                }
+            });
+            file.relocate(self.bias);
+            file
         })
     }
 
-    #[allow(dead_code)]
-    pub fn get_addr_for_line(&self, file: Option<&str>, line_number: usize) -> Option<usize> {
-        let target_file = match file {
-            Some(filename) => self.get_target_file(filename)?,
-            None => self.files.get(0)?,
+    /// Returns the errors from parsing compilation units (here and in shared libraries) since
+    /// the last call, for the debugger to report.
+    pub fn take_load_errors(&self) -> Vec<String> {
+        let mut errors = self.load_errors.replace(Vec::new());
+        for library in &self.shared_libraries {
+            if let Some(debug_data) = &library.debug_data {
+                errors.extend(debug_data.take_load_errors());
+            }
+        }
+        errors
+    }
+
+    /// Returns every compilation unit of this binary (not including shared libraries). This
+    /// parses all of them, so prefer the indexed lookups where there are any.
+    pub fn files(&self) -> impl Iterator<Item = &File> {
+        (0..self.units.len()).map(move |index| self.file(index))
+    }
+
+    /// Returns the compilation units defining a function (or a global variable, if variables
+    /// is set) whose name matches.
+    pub fn files_defining<F: Fn(&str) -> bool>(&self, variables: bool, matches: F) -> Vec<&File> {
+        let names = if variables {
+            &self.variables_by_name
+        } else {
+            &self.functions_by_name
+        };
+        let mut indexes: Vec<usize> = names
+            .iter()
+            .filter(|(name, _)| matches(name))
+            .flat_map(|(_, units)| units.iter().cloned())
+            .collect();
+        indexes.sort();
+        indexes.dedup();
+        indexes.into_iter().map(|index| self.file(index)).collect()
+    }
+
+    /// Returns the compilation units defining a function called name.
+    fn files_with_function(&self, name: &str) -> impl Iterator<Item = &File> {
+        let indexes = self
+            .functions_by_name
+            .get(name)
+            .map_or(&[][..], |units| &units[..]);
+        indexes.iter().map(move |index| self.file(*index))
+    }
+
+    /// Returns the compilation unit whose code contains addr.
+    fn file_for_addr(&self, addr: usize) -> Option<&File> {
+        let end = match self
+            .unit_ranges
+            .binary_search_by_key(&addr, |range| range.0)
+        {
+            Ok(index) => index + 1,
+            Err(index) => index,
+        };
+        // Ranges don't usually overlap, but look back in case a small one is nested in another
+        self.unit_ranges[..end]
+            .iter()
+            .rev()
+            .find(|range| range.0 <= addr && addr < range.1)
+            .map(|range| self.file(range.2))
+    }
+
+    /// Returns the (address, name) of every function symbol without debug info, in this binary
+    /// and the loaded shared libraries.
+    pub fn non_debugging_functions(&self) -> Vec<(usize, &str)> {
+        let mut functions: Vec<(usize, &str)> = self
+            .symbols
+            .iter()
+            .filter(|symbol| {
+                !self
+                    .unit_ranges
+                    .iter()
+                    .any(|range| range.0 <= symbol.address && symbol.address < range.1)
+            })
+            .map(|symbol| (symbol.address, symbol.name.as_str()))
+            .collect();
+        for library in &self.shared_libraries {
+            if let Some(debug_data) = &library.debug_data {
+                functions.extend(
+                    debug_data
+                        .non_debugging_functions()
+                        .into_iter()
+                        .map(|(addr, name)| (library.base + addr, name)),
+                );
+            }
+        }
+        functions
+    }
+
+    /// Whether the program is dynamically linked, so that functions may turn up in shared
+    /// libraries loaded when it runs.
+    pub fn is_dynamic(&self) -> bool {
+        self.dynamic
+    }
+
+    pub fn shared_libraries(&self) -> &[SharedLibrary] {
+        &self.shared_libraries
+    }
+
+    /// Makes the symbols of a newly loaded shared library available to lookups.
+    pub fn add_shared_library(&mut self, library: SharedLibrary) {
+        self.shared_libraries.push(library);
+    }
+
+    /// Forgets the shared libraries that are no longer loaded.
+    pub fn retain_shared_libraries<F: FnMut(&SharedLibrary) -> bool>(&mut self, f: F) {
+        self.shared_libraries.retain(f);
+    }
+
+    /// Returns the library whose code contains addr, along with its debug data.
+    fn get_library_for_addr(&self, addr: usize) -> Option<(&SharedLibrary, &DwarfData)> {
+        let library = self
+            .shared_libraries
+            .iter()
+            .find(|lib| lib.contains(addr))?;
+        Some((library, library.debug_data.as_ref()?))
+    }
+
+    /// Returns the name of the shared library whose code contains addr.
+    pub fn get_library_name_from_addr(&self, addr: usize) -> Option<&str> {
+        self.shared_libraries
+            .iter()
+            .find(|lib| lib.contains(addr))
+            .map(|lib| lib.name.as_str())
+    }
+
+    /// Returns the index of the compilation unit for file, which may be given by its full path
+    /// or by any trailing part of it (e.g. `main.c` or `src/main.c`). Units named relative to
+    /// their build directory also match full paths ending in that name.
+    fn find_unit(&self, file: &str) -> Option<usize> {
+        self.units
+            .iter()
+            .position(|unit| file_matches(&unit.name, file))
+    }
+
+    /// Returns the indexes of the compilation units with code from file (see find_unit), which
+    /// may also be a header included by them.
+    fn find_units_with_lines(&self, file: &str) -> Vec<usize> {
+        (0..self.units.len())
+            .filter(|index| {
+                let unit = &self.units[*index];
+                file_matches(&unit.name, file)
+                    || unit.files.iter().any(|path| file_matches(path, file))
+            })
+            .collect()
+    }
+
+    /// Returns whether file (see find_unit) is one of the target's source files or headers.
+    pub fn has_file(&self, file: &str) -> bool {
+        !self.find_units_with_lines(file).is_empty()
+    }
+
+    fn get_target_file(&self, file: &str) -> Option<&File> {
+        Some(self.file(self.find_unit(file)?))
+    }
+
+    /// Returns the addresses to break at for line_number of file (or of the first compilation
+    /// unit): the lowest address of the line in each function with code for it. If the line has
+    /// no code, the next line that does is used instead.
+    pub fn get_addrs_for_line(&self, file: Option<&str>, line_number: usize) -> Vec<usize> {
+        let (units, file) = match file {
+            Some(filename) => (self.find_units_with_lines(filename), filename),
+            None if self.units.is_empty() => return Vec::new(),
+            None => (vec![0], self.units[0].name.as_str()),
+        };
+        let rows: Vec<(&File, &Line)> = units
+            .into_iter()
+            .map(|index| self.file(index))
+            .flat_map(|unit| unit.lines.iter().map(move |line| (unit, line)))
+            .filter(|(_, line)| line.number >= line_number && file_matches(&line.file, file))
+            .collect();
+        let number = match rows.iter().map(|(_, line)| line.number).min() {
+            Some(number) => number,
+            None => return Vec::new(),
         };
-        Some(
-            target_file
-                .lines
+        // (function entry, lowest address), where code outside of any function is on its own
+        let mut addrs: Vec<(usize, usize)> = Vec::new();
+        for (unit, line) in rows.into_iter().filter(|(_, line)| line.number == number) {
+            let function = unit
+                .functions
                 .iter()
-                .find(|line| line.number >= line_number)?
-                .address,
-        )
+                .find(|func| {
+                    func.address <= line.address && line.address < func.address + func.text_length
+                })
+                .map_or(line.address, |func| func.address);
+            match addrs.iter_mut().find(|addr| addr.0 == function) {
+                Some(addr) => addr.1 = addr.1.min(line.address),
+                None => addrs.push((function, line.address)),
+            }
+        }
+        let mut addrs: Vec<usize> = addrs.into_iter().map(|addr| addr.1).collect();
+        addrs.sort();
+        addrs
     }
 
     #[allow(dead_code)]
@@ -73,12 +410,13 @@ impl DwarfData {
                 self.get_target_file(filename)?
                     .functions
                     .iter()
-                    .find(|func| func.name == func_name)?
+                    .find(|func| func.is_called(func_name))?
                     .address,
             ),
             None => {
-                for file in &self.files {
-                    if let Some(func) = file.functions.iter().find(|func| func.name == func_name) {
+                for file in self.files_with_function(func_name) {
+                    if let Some(func) = file.functions.iter().find(|func| func.is_called(func_name))
+                    {
                         return Some(func.address);
                     }
                 }
@@ -87,33 +425,308 @@ impl DwarfData {
         }
     }
 
+    /// Returns every address at which a breakpoint on func_name should be placed: the entry of
+    /// the out-of-line copy (if there is one) and the entry of each inlined instance. If
+    /// skip_prologue is true, the out-of-line breakpoint is placed after the function prologue,
+    /// once the frame has been set up.
+    pub fn get_addrs_for_function(
+        &self,
+        file: Option<&str>,
+        func_name: &str,
+        skip_prologue: bool,
+    ) -> Vec<usize> {
+        let files: Vec<&File> = match file {
+            Some(filename) => self.get_target_file(filename).into_iter().collect(),
+            None => self.files_with_function(func_name).collect(),
+        };
+        let mut addrs = Vec::new();
+        for file in files {
+            for func in file
+                .functions
+                .iter()
+                .filter(|func| func.is_called(func_name))
+            {
+                if func.text_length > 0 {
+                    let addr = if skip_prologue {
+                        file.get_prologue_end(func)
+                    } else {
+                        func.address
+                    };
+                    if !addrs.contains(&addr) {
+                        addrs.push(addr);
+                    }
+                }
+                for addr in &func.inlined_addresses {
+                    if !addrs.contains(addr) {
+                        addrs.push(*addr);
+                    }
+                }
+            }
+        }
+        if addrs.is_empty() && file.is_none() {
+            // Without debug info, break at the symbol itself (there's no line table to find
+            // the end of the prologue with)
+            if let Some(symbol) = self.symbols.iter().find(|symbol| symbol.name == func_name) {
+                addrs.push(symbol.address);
+            }
+        }
+        for library in &self.shared_libraries {
+            if let Some(debug_data) = &library.debug_data {
+                for addr in debug_data.get_addrs_for_function(file, func_name, skip_prologue) {
+                    addrs.push(library.base + addr);
+                }
+            }
+        }
+        addrs
+    }
+
+    /// Finds the out-of-line code of the function called func_name, for calling it. Returns its
+    /// entry address, along with its debug info if there is any (None for functions known only
+    /// from the symbol table).
+    pub fn get_function_entry(&self, func_name: &str) -> Option<(usize, Option<&Function>)> {
+        if let Some(func) = self
+            .files_with_function(func_name)
+            .flat_map(|file| file.functions.iter())
+            .find(|func| func.is_called(func_name) && func.text_length > 0)
+        {
+            return Some((func.address, Some(func)));
+        }
+        if let Some(symbol) = self.symbols.iter().find(|symbol| symbol.name == func_name) {
+            return Some((symbol.address, None));
+        }
+        self.shared_libraries.iter().find_map(|library| {
+            let (addr, func) = library.debug_data.as_ref()?.get_function_entry(func_name)?;
+            Some((library.base + addr, func))
+        })
+    }
+
+    /// Returns the address range [start, end) of the code for the source line containing addr.
+    pub fn get_line_range(&self, addr: usize) -> Option<(usize, usize)> {
+        if let Some((library, debug_data)) = self.get_library_for_addr(addr) {
+            let (start, end) = debug_data.get_line_range(addr - library.base)?;
+            return Some((library.base + start, library.base + end));
+        }
+        let mut rows: Vec<&Line> = self.file_for_addr(addr)?.lines.iter().collect();
+        rows.sort_by_key(|row| row.address);
+        let index = rows.iter().rposition(|row| row.address <= addr)?;
+        let line = rows[index];
+        let same_line = |row: &&&Line| row.number == line.number && row.file == line.file;
+        let start = rows[..=index]
+            .iter()
+            .rev()
+            .take_while(same_line)
+            .last()?
+            .address;
+        let end = rows[index..]
+            .iter()
+            .find(|row| !same_line(row))
+            .map_or(addr + 1, |row| row.address);
+        Some((start, end))
+    }
+
+    /// Returns whether addr starts a row of the line table, i.e. is the first instruction
+    /// generated for (part of) a source line.
+    pub fn is_line_start(&self, addr: usize) -> bool {
+        if let Some((library, debug_data)) = self.get_library_for_addr(addr) {
+            return debug_data.is_line_start(addr - library.base);
+        }
+        self.file_for_addr(addr).map_or(false, |file| {
+            file.lines.iter().any(|line| line.address == addr)
+        })
+    }
+
+    /// If addr is the entry point of a function with line info, returns the address of the first
+    /// instruction after its prologue.
+    pub fn get_prologue_end_for_entry(&self, addr: usize) -> Option<usize> {
+        if let Some((library, debug_data)) = self.get_library_for_addr(addr) {
+            return debug_data
+                .get_prologue_end_for_entry(addr - library.base)
+                .map(|end| library.base + end);
+        }
+        let file = self.file_for_addr(addr)?;
+        let func = file.functions.iter().find(|func| func.address == addr)?;
+        Some(file.get_prologue_end(func))
+    }
+
     #[allow(dead_code)]
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
-        let location = self
-            .addr2line
-            .find_location(curr_addr.try_into().unwrap())
-            .ok()??;
+        if let Some((library, debug_data)) = self.get_library_for_addr(curr_addr) {
+            let line = debug_data.get_line_from_addr(curr_addr - library.base)?;
+            return Some(Line {
+                address: curr_addr,
+                ..line
+            });
+        }
+        let location = self.addr2line.as_ref().and_then(|addr2line| {
+            addr2line
+                .find_location(curr_addr.wrapping_sub(self.bias).try_into().unwrap())
+                .ok()?
+        });
+        match location.and_then(|location| {
+            Some(Line {
+                file: location.file?.to_string(),
+                number: location.line?.try_into().unwrap(),
+                address: curr_addr,
+            })
+        }) {
+            Some(line) => Some(line),
+            None => self.get_line_from_line_table(curr_addr),
+        }
+    }
+
+    /// Looks up curr_addr in the line table of the unit covering it, for when addr2line can't.
+    fn get_line_from_line_table(&self, curr_addr: usize) -> Option<Line> {
+        let line = self
+            .file_for_addr(curr_addr)?
+            .lines
+            .iter()
+            .filter(|line| line.address <= curr_addr)
+            .max_by_key(|line| line.address)?;
         Some(Line {
-            file: location.file?.to_string(),
-            number: location.line?.try_into().unwrap(),
             address: curr_addr,
+            ..line.clone()
         })
     }
 
     #[allow(dead_code)]
     pub fn get_function_from_addr(&self, curr_addr: usize) -> Option<String> {
-        let frame = self
-            .addr2line
-            .find_frames(curr_addr.try_into().unwrap())
-            .ok()?
-            .next()
-            .ok()??;
-        Some(frame.function?.raw_name().ok()?.to_string())
+        if let Some((library, debug_data)) = self.get_library_for_addr(curr_addr) {
+            return debug_data.get_function_from_addr(curr_addr - library.base);
+        }
+        let frame = self.addr2line.as_ref().and_then(|addr2line| {
+            addr2line
+                .find_frames(curr_addr.wrapping_sub(self.bias).try_into().unwrap())
+                .ok()?
+                .next()
+                .ok()?
+        });
+        match frame.and_then(|frame| Some(frame.function?.demangle().ok()?.to_string())) {
+            Some(name) => Some(name),
+            None => self
+                .get_symbol_from_addr(curr_addr)
+                .map(|(name, _offset)| name.to_string()),
+        }
+    }
+
+    /// Returns the function symbol containing curr_addr and the offset of curr_addr into it.
+    /// Unlike the other lookups, this only needs the ELF symbol table.
+    pub fn get_symbol_from_addr(&self, curr_addr: usize) -> Option<(&str, usize)> {
+        if let Some((library, debug_data)) = self.get_library_for_addr(curr_addr) {
+            return debug_data.get_symbol_from_addr(curr_addr - library.base);
+        }
+        let index = match self
+            .symbols
+            .binary_search_by_key(&curr_addr, |symbol| symbol.address)
+        {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let symbol = &self.symbols[index];
+        if symbol.size > 0 && curr_addr >= symbol.address + symbol.size {
+            return None;
+        }
+        Some((&symbol.name, curr_addr - symbol.address))
+    }
+
+    /// Returns the chain of functions executing at curr_addr, innermost first. When code has been
+    /// inlined, there is one entry per inlined call followed by the function that really owns
+    /// curr_addr. Each entry comes with its source location: the location of curr_addr for the
+    /// innermost frame, and the call site of the inner frame for the others.
+    pub fn get_inline_frames_from_addr(&self, curr_addr: usize) -> Vec<(String, Option<Line>)> {
+        if let Some((library, debug_data)) = self.get_library_for_addr(curr_addr) {
+            let mut frames = debug_data.get_inline_frames_from_addr(curr_addr - library.base);
+            for (_, line) in frames.iter_mut() {
+                if let Some(line) = line {
+                    line.address = curr_addr;
+                }
+            }
+            return frames;
+        }
+        let mut frames = Vec::new();
+        let iter = self.addr2line.as_ref().and_then(|addr2line| {
+            addr2line
+                .find_frames(curr_addr.wrapping_sub(self.bias).try_into().unwrap())
+                .ok()
+        });
+        if let Some(mut iter) = iter {
+            while let Ok(Some(frame)) = iter.next() {
+                let name = match frame.function.as_ref().and_then(|f| f.demangle().ok()) {
+                    Some(name) => name.to_string(),
+                    None => continue,
+                };
+                let line = frame.location.and_then(|location| {
+                    Some(Line {
+                        file: location.file?.to_string(),
+                        number: location.line?.try_into().unwrap(),
+                        address: curr_addr,
+                    })
+                });
+                frames.push((name, line));
+            }
+        }
+        // addr2line can't read split units, so tell at least which function owns curr_addr.
+        // Inlined calls can't be told apart from their caller this way.
+        if frames.is_empty() {
+            if let Some(func) = self.get_function_data_from_addr(curr_addr) {
+                frames.push((func.name.clone(), self.get_line_from_addr(curr_addr)));
+            }
+        }
+        frames
     }
 
     #[allow(dead_code)]
+    pub fn get_function_data_from_addr(&self, curr_addr: usize) -> Option<&Function> {
+        self.file_for_addr(curr_addr)?
+            .functions
+            .iter()
+            .find(|func| func.address <= curr_addr && curr_addr < func.address + func.text_length)
+    }
+
+    /// Finds the call that returns to return_address, to work out the values the callee was
+    /// entered with.
+    pub fn get_call_site(&self, return_address: usize) -> Option<&CallSite> {
+        self.file_for_addr(return_address)?
+            .call_sites
+            .iter()
+            .find(|call_site| call_site.return_address == return_address)
+    }
+
+    /// Looks up the variable called name as seen from curr_addr: a local variable or parameter of
+    /// the function executing there, or else a global variable.
+    pub fn get_variable_at(&self, name: &str, curr_addr: usize) -> Option<&Variable> {
+        if let Some(var) = self
+            .get_function_data_from_addr(curr_addr)
+            .and_then(|func| func.get_variable(name, curr_addr))
+        {
+            return Some(var);
+        }
+        let indexes = self.variables_by_name.get(name)?;
+        indexes
+            .iter()
+            .flat_map(|index| self.file(*index).global_variables.iter())
+            .find(|var| var.name == name)
+    }
+
+    /// Returns the canonical frame address of the frame executing at pc, based on the call frame
+    /// information. Returns None if there is no unwind information covering pc.
+    pub fn get_cfa(&self, pc: usize, state: &dyn FrameState) -> Option<usize> {
+        self.unwind(pc, state).map(|(cfa, _saved_rbp)| cfa)
+    }
+
+    /// Returns the canonical frame address of the frame executing at pc, and the address where
+    /// the caller's rbp is saved (if the frame has saved it).
+    pub fn unwind(&self, pc: usize, state: &dyn FrameState) -> Option<(usize, Option<usize>)> {
+        if let Some((library, debug_data)) = self.get_library_for_addr(pc) {
+            return debug_data.unwind(pc - library.base, state);
+        }
+        let (address, data) = self.eh_frame.as_ref()?;
+        gimli_wrapper::find_cfa(data, *address, pc, state)
+    }
+
+    /// Dumps everything known about the binary, for `deet --dump-dwarf`.
     pub fn print(&self) {
-        for file in &self.files {
+        for file in self.files() {
             println!("------");
             println!("{}", file.name);
             println!("------");
@@ -132,11 +745,25 @@ impl DwarfData {
                     "  * {} (declared on line {}, located at {:#x}, {} bytes long)",
                     func.name, func.line_number, func.address, func.text_length
                 );
+                for addr in &func.inlined_addresses {
+                    println!("    * Inlined at {:#x}", addr);
+                }
                 for var in &func.variables {
                     println!(
-                        "    * Variable: {} ({}, located at {}, declared at line {})",
-                        var.name, var.entity_type.name, var.location, var.line_number
+                        "    * {}: {} ({}, located at {}, declared at line {})",
+                        if var.is_parameter {
+                            "Parameter"
+                        } else {
+                            "Variable"
+                        },
+                        var.name,
+                        var.entity_type.name,
+                        var.location,
+                        var.line_number
                     );
+                    for (low_pc, high_pc) in var.scope.iter().flatten() {
+                        println!("      (in block {:#x}-{:#x})", low_pc, high_pc);
+                    }
                 }
             }
 
@@ -148,36 +775,193 @@ impl DwarfData {
     }
 }
 
+/// A compilation unit, which is only parsed once something in it is needed
+struct Unit {
+    offset: usize, // Offset of the unit header in .debug_info
+    name: String,
+    files: Vec<String>, // Full paths of the source files in its line table
+    file: OnceCell<File>,
+}
+
+/// A function symbol from the ELF symbol table
+#[derive(Debug, Clone)]
+struct Symbol {
+    name: String,
+    address: usize,
+    size: usize,
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Type {
     pub name: String,
     pub size: usize,
+    pub kind: TypeKind,
+    pub rust: bool, // Declared in a Rust compilation unit, so its values are shown Rust-style
 }
 
 impl Type {
     pub fn new(name: String, size: usize) -> Self {
         Type {
-            name: name,
-            size: size,
+            name,
+            size,
+            kind: TypeKind::Base,
+            rust: false,
+        }
+    }
+
+    /// Returns the member called name, if this is a struct that has one.
+    pub fn member(&self, name: &str) -> Option<&Member> {
+        match &self.kind {
+            TypeKind::Struct(members, _) => members.iter().find(|member| member.name == name),
+            _ => None,
         }
     }
 }
 
+/// What a type is made of, beyond its name and size
+#[derive(Debug, Clone)]
+pub enum TypeKind {
+    Base,
+    Pointer(Option<Box<Type>>), // The pointed-to type, if known (None for void, for instance)
+    Struct(Vec<Member>, Vec<Type>), // Members and template type parameters
+    Array(Box<Type>, usize),    // Element type and number of elements
+    Enum(Vec<(String, i64)>),   // Enumerators of a C-style enum
+    /// A Rust enum with data: the (offset, size) of the discriminant and the variants, each with
+    /// the discriminant value it's used for (None for the variant used for any other value)
+    Variants(Option<(usize, usize)>, Vec<(Option<u64>, Member)>),
+}
+
+impl Default for TypeKind {
+    fn default() -> Self {
+        TypeKind::Base
+    }
+}
+
+/// A member of a struct, or a variant of a Rust enum
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    pub offset: usize, // Offset of the member from the start of the struct
+    pub member_type: Type,
+}
+
 #[derive(Clone)]
 pub enum Location {
     Address(usize),
     FramePointerOffset(isize),
+    /// Any other DWARF expression, evaluated when the inferior is stopped
+    Expression(Expression),
+    /// (low_pc, high_pc, expression) entries. Outside of these ranges the variable is optimized
+    /// out.
+    List(Vec<(usize, usize, Expression)>),
+}
+
+/// The bytecode of a DWARF expression, along with what's needed to evaluate it
+#[derive(Debug, Clone)]
+pub struct Expression {
+    pub encoding: gimli::Encoding,
+    pub bytes: Vec<u8>,
+    // Base types that typed operations (such as DW_OP_convert) refer to, by offset in the unit
+    pub base_types: Vec<(usize, gimli::ValueType)>,
+}
+
+impl Location {
+    /// Works out where the object lives when the inferior is stopped at pc. Returns the pieces
+    /// making up the object along with their sizes in bytes (None if a single piece makes up the
+    /// whole object). An empty vector means the object is not available at pc.
+    pub fn evaluate(&self, pc: usize, state: &dyn FrameState) -> Vec<(Piece, Option<usize>)> {
+        let pieces = match self {
+            Location::Address(addr) => Some(vec![(Piece::Memory(*addr), None)]),
+            Location::FramePointerOffset(offset) => state
+                .frame_base()
+                .map(|base| vec![(Piece::Memory((base as isize + offset) as usize), None)]),
+            Location::Expression(expression) => {
+                gimli_wrapper::evaluate_expression(expression, state)
+            }
+            Location::List(entries) => entries
+                .iter()
+                .find(|(low_pc, high_pc, _)| *low_pc <= pc && pc < *high_pc)
+                .and_then(|(_, _, expression)| {
+                    gimli_wrapper::evaluate_expression(expression, state)
+                }),
+        };
+        pieces.unwrap_or_default()
+    }
+
+    /// Moves the addresses in this location by delta (a wrapping offset).
+    fn relocate(&mut self, delta: usize) {
+        match self {
+            Location::Address(addr) => *addr = addr.wrapping_add(delta),
+            Location::FramePointerOffset(_) => {}
+            Location::Expression(expression) => expression.relocate(delta),
+            Location::List(entries) => {
+                for (low_pc, high_pc, expression) in entries.iter_mut() {
+                    *low_pc = low_pc.wrapping_add(delta);
+                    *high_pc = high_pc.wrapping_add(delta);
+                    expression.relocate(delta);
+                }
+            }
+        }
+    }
+
+    /// Describes where the object lives, for `info scope` and `info address`.
+    pub fn describe(&self) -> String {
+        match self {
+            Location::Address(addr) => format!("static storage at address {:#x}", addr),
+            Location::FramePointerOffset(offset) => {
+                format!("a variable at frame base offset {}", offset)
+            }
+            Location::Expression(..) => "a variable computed by a DWARF expression".to_string(),
+            Location::List(entries) => format!(
+                "a variable in different places over {} address ranges",
+                entries.len()
+            ),
+        }
+    }
 }
 
 impl fmt::Display for Location {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
+        match self {
             Location::Address(addr) => write!(f, "Address({:#x})", addr),
             Location::FramePointerOffset(offset) => write!(f, "FramePointerOffset({})", offset),
+            Location::Expression(expression) => {
+                write!(f, "Expression({} bytes)", expression.bytes.len())
+            }
+            Location::List(entries) => write!(f, "LocationList({} entries)", entries.len()),
         }
     }
 }
 
+impl Expression {
+    fn relocate(&mut self, delta: usize) {
+        gimli_wrapper::relocate_expression(self.encoding, &mut self.bytes, delta);
+    }
+}
+
+/// Where a piece of an object lives, once its location has been evaluated
+#[derive(Debug, Clone)]
+pub enum Piece {
+    Memory(usize),
+    Register(u16), // DWARF register number
+    Value(u64),
+    Bytes(Vec<u8>),
+    OptimizedOut,
+}
+
+/// The state of a stopped frame that DWARF locations are evaluated against.
+pub trait FrameState {
+    /// Returns the value of a register, using DWARF register numbering.
+    fn register(&self, register: u16) -> Option<u64>;
+    fn read_memory(&self, addr: usize, len: usize) -> Option<Vec<u8>>;
+    /// Returns the value of the function's DW_AT_frame_base.
+    fn frame_base(&self) -> Option<usize>;
+    /// Returns the canonical frame address.
+    fn cfa(&self) -> Option<usize>;
+    /// Returns the value a register had on entry to the function, for DW_OP_entry_value.
+    fn entry_value(&self, register: u16) -> Option<u64>;
+}
+
 impl fmt::Debug for Location {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt::Display::fmt(self, f)
@@ -190,16 +974,84 @@ pub struct Variable {
     pub name: String,
     pub entity_type: Type,
     pub location: Location,
-    pub line_number: usize, // Line number in source file
+    pub line_number: usize,                 // Line number in source file
+    pub is_parameter: bool,                 // DW_TAG_formal_parameter rather than DW_TAG_variable
+    pub scope: Option<Vec<(usize, usize)>>, // Address ranges of the innermost enclosing lexical block
+}
+
+impl Variable {
+    /// Returns true if the variable is visible when the instruction pointer is at curr_addr.
+    /// Variables outside of any lexical block are visible throughout their function.
+    pub fn in_scope(&self, curr_addr: usize) -> bool {
+        match &self.scope {
+            Some(ranges) => ranges
+                .iter()
+                .any(|&(low_pc, high_pc)| low_pc <= curr_addr && curr_addr < high_pc),
+            None => true,
+        }
+    }
+
+    fn relocate(&mut self, delta: usize) {
+        self.location.relocate(delta);
+        for (low_pc, high_pc) in self.scope.iter_mut().flatten() {
+            *low_pc = low_pc.wrapping_add(delta);
+            *high_pc = high_pc.wrapping_add(delta);
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct Function {
     pub name: String,
+    pub linkage_name: Option<String>, // Demangled, e.g. the full path of a Rust function
     pub address: usize,
     pub text_length: usize,
     pub line_number: usize, // Line number in source file
     pub variables: Vec<Variable>,
+    pub frame_base: Option<Location>,
+    pub inlined_addresses: Vec<usize>, // Entry points of every copy inlined into other functions
+    pub return_type: Option<Type>,     // None for void functions
+}
+
+impl Function {
+    /// Returns true if name refers to this function, by its name or its full (linkage) name.
+    pub fn is_called(&self, name: &str) -> bool {
+        self.name == name || self.linkage_name.as_deref() == Some(name)
+    }
+
+    /// Returns the variables of this function that are in scope at curr_addr.
+    pub fn variables_in_scope(&self, curr_addr: usize) -> impl Iterator<Item = &Variable> {
+        self.variables
+            .iter()
+            .filter(move |var| var.in_scope(curr_addr))
+    }
+
+    /// Looks up a variable by name at curr_addr. If the name is shadowed, the variable from the
+    /// innermost lexical block wins.
+    pub fn get_variable(&self, name: &str, curr_addr: usize) -> Option<&Variable> {
+        self.variables_in_scope(curr_addr)
+            .filter(|var| var.name == name)
+            .min_by_key(|var| match &var.scope {
+                Some(ranges) => ranges
+                    .iter()
+                    .map(|(low_pc, high_pc)| high_pc - low_pc)
+                    .sum(),
+                None => usize::MAX,
+            })
+    }
+
+    fn relocate(&mut self, delta: usize) {
+        self.address = self.address.wrapping_add(delta);
+        for addr in self.inlined_addresses.iter_mut() {
+            *addr = addr.wrapping_add(delta);
+        }
+        for var in self.variables.iter_mut() {
+            var.relocate(delta);
+        }
+        if let Some(frame_base) = self.frame_base.as_mut() {
+            frame_base.relocate(delta);
+        }
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -208,6 +1060,79 @@ pub struct File {
     pub global_variables: Vec<Variable>,
     pub functions: Vec<Function>,
     pub lines: Vec<Line>,
+    pub prologue_ends: Vec<usize>, // Addresses of line table rows flagged prologue_end
+    pub call_sites: Vec<CallSite>,
+}
+
+impl File {
+    /// Returns the address of the first instruction after func's prologue. This is the address
+    /// flagged prologue_end in the line table if the compiler emitted one, or else the address of
+    /// the function's second line table row (the first row covers the prologue itself).
+    pub fn get_prologue_end(&self, func: &Function) -> usize {
+        let in_body =
+            |addr: &usize| func.address < *addr && *addr < func.address + func.text_length;
+        if let Some(addr) = self.prologue_ends.iter().filter(|addr| in_body(addr)).min() {
+            return *addr;
+        }
+        self.lines
+            .iter()
+            .map(|line| line.address)
+            .filter(|addr| in_body(addr))
+            .min()
+            .unwrap_or(func.address)
+    }
+
+    /// Moves every address in the unit by delta (a wrapping offset), for DwarfData::relocate.
+    fn relocate(&mut self, delta: usize) {
+        if delta == 0 {
+            return;
+        }
+        for func in self.functions.iter_mut() {
+            func.relocate(delta);
+        }
+        for var in self.global_variables.iter_mut() {
+            var.relocate(delta);
+        }
+        for line in self.lines.iter_mut() {
+            line.address = line.address.wrapping_add(delta);
+        }
+        for addr in self.prologue_ends.iter_mut() {
+            *addr = addr.wrapping_add(delta);
+        }
+        for call_site in self.call_sites.iter_mut() {
+            call_site.return_address = call_site.return_address.wrapping_add(delta);
+            for (_, value) in call_site.parameters.iter_mut() {
+                value.relocate(delta);
+            }
+        }
+    }
+}
+
+/// A call made by a function (DW_TAG_call_site), describing the values it passed in registers
+/// so that the callee can find out what its parameters were on entry.
+#[derive(Debug, Clone)]
+pub struct CallSite {
+    pub return_address: usize,
+    pub parameters: Vec<(u16, Expression)>, // (register, DW_AT_call_value)
+}
+
+impl CallSite {
+    /// Works out the values passed in registers, given the state of the calling frame.
+    pub fn register_values(&self, caller: &dyn FrameState) -> Vec<(u16, u64)> {
+        self.parameters
+            .iter()
+            .filter_map(|(register, value)| {
+                // The value is what the expression leaves on the stack, which reads as an address
+                let value = match gimli_wrapper::evaluate_expression(value, caller)?.first()? {
+                    (Piece::Memory(value), _) => *value as u64,
+                    (Piece::Value(value), _) => *value,
+                    (Piece::Register(number), _) => caller.register(*number)?,
+                    _ => return None,
+                };
+                Some((*register, value))
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -223,4 +1148,10 @@ impl fmt::Display for Line {
     }
 }
 
-
+/// Returns whether path names file, which may be given in full or by any trailing part of it
+/// (e.g. `main.c` or `src/main.c`). A relative path also matches full paths ending in it.
+fn file_matches(path: &str, file: &str) -> bool {
+    path == file
+        || path.ends_with(&format!("/{}", file.trim_start_matches("./")))
+        || (!path.starts_with('/') && file.ends_with(&format!("/{}", path)))
+}
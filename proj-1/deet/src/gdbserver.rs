@@ -0,0 +1,747 @@
+//! A minimal GDB Remote Serial Protocol server, so that the Inferior can be driven by gdb, lldb or
+//! an IDE instead of deet's own command line. Start it with `deet --gdbserver :PORT <target>` and
+//! connect with `target remote :PORT` from gdb.
+//!
+//! The protocol is described at https://sourceware.org/gdb/onlinedocs/gdb/Remote-Protocol.html.
+
+use crate::inferior::{Inferior, Status};
+use nix::sys::signal::{self, Signal};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+/// Registers in the order of the amd64 'g' packet (without a target description): the general
+/// purpose registers and rip are 8 bytes wide, eflags and the segment registers 4 bytes wide.
+const NUM_GPRS: usize = 17;
+const NUM_REGISTERS: usize = 24;
+
+/// Signals are numbered differently in the remote protocol than on Linux (see gdb's
+/// include/gdb/signals.def). Index i holds the Linux signal with remote protocol number i.
+const GDB_SIGNALS: [Option<Signal>; 33] = [
+    None,
+    Some(Signal::SIGHUP),
+    Some(Signal::SIGINT),
+    Some(Signal::SIGQUIT),
+    Some(Signal::SIGILL),
+    Some(Signal::SIGTRAP),
+    Some(Signal::SIGABRT),
+    None, // SIGEMT
+    Some(Signal::SIGFPE),
+    Some(Signal::SIGKILL),
+    Some(Signal::SIGBUS),
+    Some(Signal::SIGSEGV),
+    Some(Signal::SIGSYS),
+    Some(Signal::SIGPIPE),
+    Some(Signal::SIGALRM),
+    Some(Signal::SIGTERM),
+    Some(Signal::SIGURG),
+    Some(Signal::SIGSTOP),
+    Some(Signal::SIGTSTP),
+    Some(Signal::SIGCONT),
+    Some(Signal::SIGCHLD),
+    Some(Signal::SIGTTIN),
+    Some(Signal::SIGTTOU),
+    Some(Signal::SIGIO),
+    Some(Signal::SIGXCPU),
+    Some(Signal::SIGXFSZ),
+    Some(Signal::SIGVTALRM),
+    Some(Signal::SIGPROF),
+    Some(Signal::SIGWINCH),
+    None, // SIGLOST
+    Some(Signal::SIGUSR1),
+    Some(Signal::SIGUSR2),
+    Some(Signal::SIGPWR),
+];
+
+fn to_gdb_signal(sig: Signal) -> u8 {
+    GDB_SIGNALS
+        .iter()
+        .position(|s| *s == Some(sig))
+        .unwrap_or(143) as u8 // GDB_SIGNAL_UNKNOWN
+}
+
+fn from_gdb_signal(num: u8) -> Option<Signal> {
+    *GDB_SIGNALS.get(num as usize)?
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_hex(hex: &str) -> Option<usize> {
+    usize::from_str_radix(hex, 16).ok()
+}
+
+/// Parses the "addr,length" prefix shared by the memory and breakpoint packets.
+fn parse_addr_len(args: &str) -> Option<(usize, usize)> {
+    let mut parts = args.splitn(2, ',');
+    let addr = parse_hex(parts.next()?)?;
+    let len = parse_hex(parts.next()?)?;
+    Some((addr, len))
+}
+
+/// Escapes binary data for the 'x' packet reply: '#', '$', '}' and '*' are sent as '}' followed
+/// by the byte xor 0x20.
+fn escape_binary(bytes: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(bytes.len());
+    for byte in bytes {
+        match byte {
+            b'#' | b'$' | b'}' | b'*' => {
+                escaped.push(b'}');
+                escaped.push(byte ^ 0x20);
+            }
+            _ => escaped.push(*byte),
+        }
+    }
+    escaped
+}
+
+fn unescape_binary(bytes: &[u8]) -> Vec<u8> {
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+    while let Some(byte) = iter.next() {
+        if *byte == b'}' {
+            if let Some(next) = iter.next() {
+                unescaped.push(next ^ 0x20);
+            }
+        } else {
+            unescaped.push(*byte);
+        }
+    }
+    unescaped
+}
+
+fn get_register(regs: &libc::user_regs_struct, regno: usize) -> Option<u64> {
+    Some(match regno {
+        0 => regs.rax,
+        1 => regs.rbx,
+        2 => regs.rcx,
+        3 => regs.rdx,
+        4 => regs.rsi,
+        5 => regs.rdi,
+        6 => regs.rbp,
+        7 => regs.rsp,
+        8 => regs.r8,
+        9 => regs.r9,
+        10 => regs.r10,
+        11 => regs.r11,
+        12 => regs.r12,
+        13 => regs.r13,
+        14 => regs.r14,
+        15 => regs.r15,
+        16 => regs.rip,
+        17 => regs.eflags,
+        18 => regs.cs,
+        19 => regs.ss,
+        20 => regs.ds,
+        21 => regs.es,
+        22 => regs.fs,
+        23 => regs.gs,
+        _ => return None,
+    })
+}
+
+fn set_register(regs: &mut libc::user_regs_struct, regno: usize, value: u64) -> Option<()> {
+    let reg = match regno {
+        0 => &mut regs.rax,
+        1 => &mut regs.rbx,
+        2 => &mut regs.rcx,
+        3 => &mut regs.rdx,
+        4 => &mut regs.rsi,
+        5 => &mut regs.rdi,
+        6 => &mut regs.rbp,
+        7 => &mut regs.rsp,
+        8 => &mut regs.r8,
+        9 => &mut regs.r9,
+        10 => &mut regs.r10,
+        11 => &mut regs.r11,
+        12 => &mut regs.r12,
+        13 => &mut regs.r13,
+        14 => &mut regs.r14,
+        15 => &mut regs.r15,
+        16 => &mut regs.rip,
+        17 => &mut regs.eflags,
+        18 => &mut regs.cs,
+        19 => &mut regs.ss,
+        20 => &mut regs.ds,
+        21 => &mut regs.es,
+        22 => &mut regs.fs,
+        23 => &mut regs.gs,
+        _ => return None,
+    };
+    *reg = value;
+    Some(())
+}
+
+fn register_size(regno: usize) -> usize {
+    if regno < NUM_GPRS {
+        8
+    } else {
+        4
+    }
+}
+
+/// Encodes a register value the way the remote protocol expects it: target byte order, i.e.
+/// little endian.
+fn encode_register(value: u64, size: usize) -> String {
+    to_hex(&value.to_le_bytes()[..size])
+}
+
+fn decode_register(hex: &str) -> Option<u64> {
+    let bytes = from_hex(hex)?;
+    if bytes.len() > 8 {
+        return None;
+    }
+    let mut value = 0;
+    for (i, byte) in bytes.iter().enumerate() {
+        value |= (*byte as u64) << (8 * i);
+    }
+    Some(value)
+}
+
+/// The checksum of a packet: the sum of its bytes, modulo 256.
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Frames data as a packet: `$data#checksum`.
+fn frame_packet(data: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(data.len() + 4);
+    packet.push(b'$');
+    packet.extend_from_slice(data);
+    packet.extend_from_slice(format!("#{:02x}", checksum(data)).as_bytes());
+    packet
+}
+
+/// Reads the rest of a packet whose '$' has been read, returning its data and whether its
+/// checksum is right. Returns None if the stream ends first.
+fn read_packet_body<R: Read>(stream: &mut R) -> io::Result<Option<(Vec<u8>, bool)>> {
+    let mut byte = [0u8; 1];
+    let mut data = Vec::new();
+    loop {
+        if stream.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        if byte[0] == b'#' {
+            break;
+        }
+        data.push(byte[0]);
+    }
+    let mut sum = [0u8; 2];
+    stream.read_exact(&mut sum)?;
+    let expected = std::str::from_utf8(&sum)
+        .ok()
+        .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    let valid = expected == Some(checksum(&data));
+    Ok(Some((data, valid)))
+}
+
+/// What to do with the connection after handling a packet.
+enum Action {
+    Reply(Vec<u8>),
+    Resume { step: bool, signal: Option<Signal> },
+    Exit,
+}
+
+fn reply(data: &str) -> Action {
+    Action::Reply(data.as_bytes().to_vec())
+}
+
+struct GdbServer {
+    stream: TcpStream,
+    inferior: Inferior,
+    last_status: Status,
+    no_ack_mode: bool,
+}
+
+impl GdbServer {
+    /// Reads the next packet from gdb, acknowledging it unless we are in no-ack mode. Returns
+    /// None when gdb disconnects. A lone ^C (0x03) outside of a packet is ignored here, since the
+    /// inferior is already stopped.
+    fn read_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut byte = [0u8; 1];
+        loop {
+            if self.stream.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] != b'$' {
+                // '+' and '-' acknowledgements and stray interrupts
+                continue;
+            }
+            let (data, valid) = match read_packet_body(&mut self.stream)? {
+                Some(body) => body,
+                None => return Ok(None),
+            };
+            if self.no_ack_mode {
+                return Ok(Some(data));
+            }
+            if valid {
+                self.stream.write_all(b"+")?;
+                return Ok(Some(data));
+            }
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    fn send_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        let packet = frame_packet(data);
+        loop {
+            self.stream.write_all(&packet)?;
+            if self.no_ack_mode {
+                return Ok(());
+            }
+            let mut ack = [0u8; 1];
+            loop {
+                if self.stream.read(&mut ack)? == 0 {
+                    return Ok(());
+                }
+                if ack[0] == b'+' || ack[0] == b'-' {
+                    break;
+                }
+            }
+            if ack[0] == b'+' {
+                return Ok(());
+            }
+        }
+    }
+
+    fn stop_reply(&self) -> String {
+        match self.last_status {
+            Status::Stopped(signal, _) => format!(
+                "T{:02x}thread:{:x};",
+                to_gdb_signal(signal),
+                self.inferior.pid().as_raw()
+            ),
+            Status::Exited(exit_code) => format!("W{:02x}", exit_code),
+            Status::Signaled(signal) => format!("X{:02x}", to_gdb_signal(signal)),
+            Status::SyscallEntry(number) => format!(
+                "T05syscall_entry:{:x};thread:{:x};",
+                number,
+                self.inferior.pid().as_raw()
+            ),
+            Status::SyscallExit(number, _) => format!(
+                "T05syscall_return:{:x};thread:{:x};",
+                number,
+                self.inferior.pid().as_raw()
+            ),
+        }
+    }
+
+    fn read_registers(&self) -> Result<String, nix::Error> {
+        let regs = self.inferior.get_registers()?;
+        let mut hex = String::new();
+        for regno in 0..NUM_REGISTERS {
+            hex.push_str(&encode_register(
+                get_register(&regs, regno).unwrap(),
+                register_size(regno),
+            ));
+        }
+        Ok(hex)
+    }
+
+    fn write_registers(&mut self, hex: &str) -> Option<()> {
+        let mut regs = self.inferior.get_registers().ok()?;
+        let mut offset = 0;
+        for regno in 0..NUM_REGISTERS {
+            let len = 2 * register_size(regno);
+            if offset + len > hex.len() {
+                break;
+            }
+            // 'x' marks a register whose value gdb doesn't know; leave it alone
+            let value = hex.get(offset..offset + len)?;
+            if !value.starts_with('x') {
+                set_register(&mut regs, regno, decode_register(value)?)?;
+            }
+            offset += len;
+        }
+        self.inferior.set_registers(regs).ok()
+    }
+
+    fn handle_packet(&mut self, packet: &[u8]) -> Action {
+        let text = String::from_utf8_lossy(packet).to_string();
+        let (command, args) = match text.chars().next() {
+            Some(c) => (c, &text[c.len_utf8()..]),
+            None => return reply(""),
+        };
+        match command {
+            '?' => reply(&self.stop_reply()),
+            'g' => match self.read_registers() {
+                Ok(hex) => reply(&hex),
+                Err(_) => reply("E01"),
+            },
+            'G' => match self.write_registers(args) {
+                Some(_) => reply("OK"),
+                None => reply("E01"),
+            },
+            'p' => {
+                let regs = self.inferior.get_registers();
+                match (parse_hex(args), regs) {
+                    (Some(regno), Ok(regs)) => match get_register(&regs, regno) {
+                        Some(value) => reply(&encode_register(value, register_size(regno))),
+                        None => reply("E01"),
+                    },
+                    _ => reply("E01"),
+                }
+            }
+            'P' => {
+                let mut parts = args.splitn(2, '=');
+                let regno = parts.next().and_then(parse_hex);
+                let value = parts.next().and_then(decode_register);
+                let regs = self.inferior.get_registers();
+                match (regno, value, regs) {
+                    (Some(regno), Some(value), Ok(mut regs)) => {
+                        match set_register(&mut regs, regno, value) {
+                            Some(_) if self.inferior.set_registers(regs).is_ok() => reply("OK"),
+                            _ => reply("E01"),
+                        }
+                    }
+                    _ => reply("E01"),
+                }
+            }
+            'm' | 'x' => match parse_addr_len(args) {
+                Some((addr, len)) => match self.inferior.read_memory(addr, len) {
+                    Ok(bytes) if command == 'm' => reply(&to_hex(&bytes)),
+                    Ok(bytes) => {
+                        let mut data = vec![b'b'];
+                        data.extend(escape_binary(&bytes));
+                        Action::Reply(data)
+                    }
+                    Err(_) => reply("E01"),
+                },
+                None => reply("E01"),
+            },
+            'M' | 'X' => {
+                let colon = match packet.iter().position(|byte| *byte == b':') {
+                    Some(colon) => colon,
+                    None => return reply("E01"),
+                };
+                let header = String::from_utf8_lossy(&packet[1..colon]).to_string();
+                let data = if command == 'M' {
+                    from_hex(&String::from_utf8_lossy(&packet[colon + 1..]))
+                } else {
+                    Some(unescape_binary(&packet[colon + 1..]))
+                };
+                match (parse_addr_len(&header), data) {
+                    (Some((addr, len)), Some(data)) if data.len() == len => {
+                        match self.inferior.write_memory(addr, &data) {
+                            Ok(_) => reply("OK"),
+                            Err(_) => reply("E01"),
+                        }
+                    }
+                    _ => reply("E01"),
+                }
+            }
+            'Z' | 'z' => {
+                // Only software breakpoints (type 0) are supported
+                if !args.starts_with("0,") {
+                    return reply("");
+                }
+                let addr = match parse_addr_len(&args[2..]) {
+                    Some((addr, _kind)) => addr,
+                    None => return reply("E01"),
+                };
+                let result = if command == 'Z' {
+                    self.inferior.set_breakpoint(addr)
+                } else {
+                    self.inferior.remove_breakpoint(addr)
+                };
+                match result {
+                    Ok(_) => reply("OK"),
+                    Err(_) => reply("E01"),
+                }
+            }
+            'c' | 's' | 'C' | 'S' => {
+                // Optional arguments: [sig][;addr]
+                let mut parts = args.splitn(2, ';');
+                let signal = if command == 'C' || command == 'S' {
+                    parts
+                        .next()
+                        .and_then(|sig| u8::from_str_radix(sig, 16).ok())
+                        .and_then(from_gdb_signal)
+                } else {
+                    None
+                };
+                let addr = if command == 'c' || command == 's' {
+                    Some(args)
+                } else {
+                    parts.next()
+                };
+                if let Some(addr) = addr.and_then(parse_hex) {
+                    if let Ok(mut regs) = self.inferior.get_registers() {
+                        regs.rip = addr as u64;
+                        let _ = self.inferior.set_registers(regs);
+                    }
+                }
+                Action::Resume {
+                    step: command == 's' || command == 'S',
+                    signal,
+                }
+            }
+            'v' => self.handle_v_packet(&text),
+            'q' => self.handle_query(&text),
+            // serve() switches off acknowledgements once the reply has been sent
+            'Q' if text == "QStartNoAckMode" => reply("OK"),
+            // We only have one thread, so thread selection always succeeds
+            'H' | 'T' => reply("OK"),
+            'D' => {
+                let _ = self.send_packet(b"OK");
+                let _ = nix::sys::ptrace::detach(self.inferior.pid(), None);
+                Action::Exit
+            }
+            'k' => {
+                let _ = self.inferior.kill();
+                Action::Exit
+            }
+            _ => reply(""),
+        }
+    }
+
+    fn handle_v_packet(&mut self, text: &str) -> Action {
+        if text == "vCont?" {
+            return reply("vCont;c;C;s;S");
+        }
+        if text.starts_with("vCont;") {
+            // We only have one thread, so the first action applies to it
+            let action = text["vCont;".len()..].split(';').next().unwrap_or("");
+            let action = action.split(':').next().unwrap_or("");
+            let signal = action
+                .get(1..)
+                .and_then(|sig| u8::from_str_radix(sig, 16).ok())
+                .and_then(from_gdb_signal);
+            return match action.chars().next() {
+                Some('c') => Action::Resume {
+                    step: false,
+                    signal: None,
+                },
+                Some('C') => Action::Resume {
+                    step: false,
+                    signal,
+                },
+                Some('s') => Action::Resume {
+                    step: true,
+                    signal: None,
+                },
+                Some('S') => Action::Resume { step: true, signal },
+                _ => reply("E01"),
+            };
+        }
+        if text == "vKill" || text.starts_with("vKill;") {
+            let _ = self.send_packet(b"OK");
+            let _ = self.inferior.kill();
+            return Action::Exit;
+        }
+        // Includes vMustReplyEmpty
+        reply("")
+    }
+
+    fn handle_query(&mut self, text: &str) -> Action {
+        let pid = self.inferior.pid().as_raw();
+        if text.starts_with("qSupported") {
+            reply("PacketSize=4000;QStartNoAckMode+;vContSupported+")
+        } else if text == "qAttached" {
+            // We started the process, so gdb should kill it rather than detach when it quits
+            reply("0")
+        } else if text == "qC" {
+            reply(&format!("QC{:x}", pid))
+        } else if text == "qfThreadInfo" {
+            reply(&format!("m{:x}", pid))
+        } else if text == "qsThreadInfo" {
+            reply("l")
+        } else {
+            reply("")
+        }
+    }
+
+    /// Resumes the inferior and waits for it to stop, forwarding a ^C from gdb to the inferior
+    /// as a SIGINT.
+    fn resume(&mut self, step: bool, signal: Option<Signal>) -> Result<Status, nix::Error> {
+        if step {
+            return self.inferior.step(signal);
+        }
+        if let Some(status) = self.inferior.resume(signal, false)? {
+            return Ok(status);
+        }
+        let _ = self.stream.set_nonblocking(true);
+        let status = loop {
+            if let Some(status) = self.inferior.try_wait()? {
+                break status;
+            }
+            let mut byte = [0u8; 1];
+            match self.stream.read(&mut byte) {
+                Ok(1) if byte[0] == 0x03 => {
+                    signal::kill(self.inferior.pid(), Signal::SIGINT)?;
+                }
+                _ => std::thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        let _ = self.stream.set_nonblocking(false);
+        Ok(status)
+    }
+
+    fn serve(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.read_packet()? {
+            match self.handle_packet(&packet) {
+                Action::Reply(data) => {
+                    self.send_packet(&data)?;
+                    if packet == b"QStartNoAckMode" {
+                        self.no_ack_mode = true;
+                    }
+                }
+                Action::Resume { step, signal } => {
+                    let status = match self.resume(step, signal) {
+                        Ok(status) => status,
+                        Err(err) => {
+                            println!("Error resuming subprocess -> {}", err);
+                            self.send_packet(b"E01")?;
+                            continue;
+                        }
+                    };
+                    self.last_status = status;
+                    let stop_reply = self.stop_reply();
+                    self.send_packet(stop_reply.as_bytes())?;
+                    match self.last_status {
+                        Status::Exited(_) | Status::Signaled(_) => return Ok(()),
+                        _ => {}
+                    }
+                }
+                Action::Exit => return Ok(()),
+            }
+        }
+        // gdb went away without killing or detaching from the inferior
+        let _ = self.inferior.kill();
+        Ok(())
+    }
+}
+
+/// Starts target under ptrace and serves it to a single gdb client connecting on addr (e.g.
+/// ":1234", which listens on localhost, or "0.0.0.0:1234").
+pub fn run(addr: &str, target: &str, args: &[String]) {
+    let addr = if addr.starts_with(':') {
+        format!("127.0.0.1{}", addr)
+    } else {
+        addr.to_string()
+    };
+    let listener = match TcpListener::bind(&addr) {
+        Ok(listener) => listener,
+        Err(err) => {
+            println!("Could not listen on {}: {}", addr, err);
+            std::process::exit(1);
+        }
+    };
+
+    let inferior = match Inferior::new(target, args, &vec![]) {
+        Some(inferior) => inferior,
+        None => {
+            println!("Error starting subprocess");
+            std::process::exit(1);
+        }
+    };
+    println!("Process {} created; pid = {}", target, inferior.pid());
+    println!("Listening on {}", addr);
+
+    let stream = match listener.accept() {
+        Ok((stream, peer)) => {
+            println!("Remote debugging from {}", peer);
+            stream
+        }
+        Err(err) => {
+            println!("Error accepting connection: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let _ = stream.set_nodelay(true);
+
+    let mut server = GdbServer {
+        stream,
+        inferior,
+        last_status: Status::Stopped(Signal::SIGTRAP, 0),
+        no_ack_mode: false,
+    };
+    if let Err(err) = server.serve() {
+        println!("Connection error: {}", err);
+        let _ = server.inferior.kill();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_packets_with_checksum() {
+        assert_eq!(frame_packet(b""), b"$#00".to_vec());
+        assert_eq!(frame_packet(b"OK"), b"$OK#9a".to_vec());
+        // The sum wraps around
+        assert_eq!(checksum(&[0xff, 0x02]), 0x01);
+    }
+
+    #[test]
+    fn reads_packet_bodies() {
+        let mut stream = &b"qSupported#37"[..];
+        assert_eq!(
+            read_packet_body(&mut stream).unwrap(),
+            Some((b"qSupported".to_vec(), true))
+        );
+        let mut stream = &b"g#00"[..];
+        assert_eq!(
+            read_packet_body(&mut stream).unwrap(),
+            Some((b"g".to_vec(), false))
+        );
+        let mut stream = &b"m4000"[..];
+        assert_eq!(read_packet_body(&mut stream).unwrap(), None);
+        // A framed packet reads back once its '$' is consumed
+        let packet = frame_packet(b"m401000,4");
+        let mut stream = &packet[1..];
+        assert_eq!(
+            read_packet_body(&mut stream).unwrap(),
+            Some((b"m401000,4".to_vec(), true))
+        );
+    }
+
+    #[test]
+    fn escapes_binary_data() {
+        let data = b"a#b$c}d*e";
+        let escaped = escape_binary(data);
+        assert_eq!(escaped, b"a}\x03b}\x04c}]d}\x0ae".to_vec());
+        assert_eq!(unescape_binary(&escaped), data.to_vec());
+        assert_eq!(escape_binary(b"plain"), b"plain".to_vec());
+        // A trailing escape character has nothing to escape
+        assert_eq!(unescape_binary(b"ab}"), b"ab".to_vec());
+    }
+
+    #[test]
+    fn converts_hex() {
+        assert_eq!(to_hex(&[0x00, 0xab, 0x10]), "00ab10");
+        assert_eq!(from_hex("00ab10"), Some(vec![0x00, 0xab, 0x10]));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+        assert_eq!(parse_addr_len("401000,10"), Some((0x401000, 0x10)));
+        assert_eq!(parse_addr_len("401000"), None);
+    }
+
+    #[test]
+    fn encodes_registers_little_endian() {
+        assert_eq!(encode_register(0x401136, 8), "3611400000000000");
+        assert_eq!(encode_register(0x246, 4), "46020000");
+        assert_eq!(decode_register("3611400000000000"), Some(0x401136));
+        assert_eq!(decode_register("000000000000000000"), None);
+    }
+
+    #[test]
+    fn numbers_signals_like_gdb() {
+        assert_eq!(to_gdb_signal(Signal::SIGTRAP), 5);
+        assert_eq!(to_gdb_signal(Signal::SIGUSR1), 30);
+        assert_eq!(from_gdb_signal(11), Some(Signal::SIGSEGV));
+        assert_eq!(from_gdb_signal(7), None);
+    }
+}
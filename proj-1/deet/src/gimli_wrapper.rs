@@ -5,242 +5,1735 @@
 //!
 //! This code is a huge mess. Please don't read it unless you're trying to do an extension :)
 
-use gimli;
 use gimli::{UnitOffset, UnitSectionOffset};
-use object::Object;
+use object::{Object, ObjectSection};
 use std::borrow;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{
+    CallSite, Expression, File, FrameState, Function, Line, Location, Member, Piece, Type,
+    TypeKind, Variable,
+};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
-use std::{io, path};
+use std::{fs, io, path};
 
-pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<Vec<File>, Error> {
+/// Name and address indexes over the compilation units of a binary, which are built without
+/// parsing the units themselves so that units can be loaded on demand.
+#[derive(Debug, Default)]
+pub struct Index {
+    // (.debug_info offset, name, files in its line table) of every compilation unit
+    pub units: Vec<(usize, String, Vec<String>)>,
+    pub functions: HashMap<String, Vec<usize>>, // Function name -> indexes into units
+    pub variables: HashMap<String, Vec<usize>>, // Global variable name -> indexes into units
+    pub ranges: Vec<(usize, usize, usize)>,     // (low_pc, high_pc, unit index), sorted by low_pc
+}
+
+impl Index {
+    fn add_name(names: &mut HashMap<String, Vec<usize>>, name: String, unit: usize) {
+        let units = names.entry(name).or_default();
+        if !units.contains(&unit) {
+            units.push(unit);
+        }
+    }
+}
+
+/// Returns the contents of the section called name, decompressed if need be.
+pub fn section_data<'data>(
+    object: &object::File<'data>,
+    name: &str,
+) -> Option<borrow::Cow<'data, [u8]>> {
+    object.section_by_name(name)?.uncompressed_data().ok()
+}
+
+fn load_dwarf<'data>(
+    object: &object::File<'data>,
+) -> Result<gimli::Dwarf<borrow::Cow<'data, [u8]>>, gimli::Error> {
     // Load a section and return as `Cow<[u8]>`.
     let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
-        Ok(object
-            .section_data_by_name(id.name())
-            .unwrap_or(borrow::Cow::Borrowed(&[][..])))
+        Ok(section_data(object, id.name()).unwrap_or(borrow::Cow::Borrowed(&[][..])))
     };
     // Load a supplementary section. We don't have a supplementary object file,
     // so always return an empty slice.
     let load_section_sup = |_| Ok(borrow::Cow::Borrowed(&[][..]));
 
     // Load all of the sections.
-    let dwarf_cow = gimli::Dwarf::load(&load_section, &load_section_sup)?;
+    gimli::Dwarf::load(&load_section, &load_section_sup)
+}
+
+/// Builds the indexes of the binary at path. Addresses come from .debug_aranges and names from
+/// .debug_names when the binary has them; otherwise the units' DIEs are skimmed for them.
+pub fn load_index(
+    object: &object::File,
+    endian: gimli::RunTimeEndian,
+    path: &str,
+) -> Result<Index, Error> {
+    use gimli::Section;
+
+    let dwarf_cow = load_dwarf(object)?;
+    let dwarf = dwarf_cow.borrow(|section| gimli::EndianSlice::new(section, endian));
+    // A name table that can't be read is ignored, leaving its units to the DIE scan
+    let names = section_data(object, ".debug_names")
+        .and_then(|data| read_debug_names(&data, endian, &dwarf.debug_str).ok())
+        .unwrap_or_default();
+
+    // (low_pc, high_pc, unit offset) from .debug_aranges
+    let mut aranges: Vec<(usize, usize, usize)> = Vec::new();
+    if let Some(data) = section_data(object, ".debug_aranges") {
+        let debug_aranges = gimli::DebugAranges::new(&data, endian);
+        let mut iter = debug_aranges.items();
+        while let Some(arange) = iter.next()? {
+            let low_pc: usize = arange.address().try_into().unwrap();
+            let length: usize = arange.length().try_into().unwrap();
+            aranges.push((low_pc, low_pc + length, arange.debug_info_offset().0));
+        }
+    }
+
+    let mut index = Index::default();
+    // Map from unit offsets to indexes into index.units
+    let mut unit_indexes: HashMap<usize, usize> = HashMap::new();
+    let mut next_offset = 0;
+    while next_offset < dwarf.debug_info.reader().len() {
+        let offset = next_offset;
+        let (length, header) = unit_header_at(&dwarf, offset)?;
+        next_offset += length;
+        let (header, dwo_id) = match header {
+            Some(header) => header,
+            None => continue,
+        };
+        let unit = dwarf.unit(header)?;
+        let mut entries = unit.entries();
+        let root = match entries.next_dfs()? {
+            // Type and partial units only matter through references from compile units
+            Some((_, entry))
+                if entry.tag() == gimli::DW_TAG_compile_unit
+                    || entry.tag() == gimli::DW_TAG_skeleton_unit =>
+            {
+                entry
+            }
+            _ => continue,
+        };
+        let unit_index = index.units.len();
+        if !aranges.iter().any(|arange| arange.2 == offset) {
+            for (low_pc, high_pc) in get_ranges(root, &unit, &dwarf)? {
+                index.ranges.push((low_pc, high_pc, unit_index));
+            }
+        }
+
+        // Units in a name table only need their name read here
+        let listed = names.units.contains(&offset);
+        // (name, function names, global variable names)
+        let unit_names = with_unit_dies(&dwarf, &unit, dwo_id, path, endian, |dwarf, unit| {
+            let mut entries = unit.entries();
+            let name = match entries.next_dfs()? {
+                Some((_, root)) => get_unit_name(root, unit, dwarf),
+                None => None,
+            };
+            let mut functions = Vec::new();
+            let mut variables = Vec::new();
+            // Only the names of functions and global variables are needed, so skip the rest
+            let mut depth = 0;
+            // Depths of the subprograms enclosing the current entry
+            let mut subprograms: Vec<isize> = Vec::new();
+            // Functions defined in other units, which have code (inlined or not) in this one
+            let mut foreign_functions: Vec<usize> = Vec::new();
+            while let Some((delta_depth, entry)) = entries.next_dfs()? {
+                if listed {
+                    break;
+                }
+                depth += delta_depth;
+                while subprograms
+                    .last()
+                    .map_or(false, |subprogram| *subprogram >= depth)
+                {
+                    subprograms.pop();
+                }
+                if entry.tag() == gimli::DW_TAG_subprogram
+                    || entry.tag() == gimli::DW_TAG_inlined_subroutine
+                {
+                    if let Ok(Some(gimli::AttributeValue::DebugInfoRef(origin))) =
+                        entry.attr_value(gimli::DW_AT_abstract_origin)
+                    {
+                        foreign_functions.push(origin.0);
+                    }
+                }
+                let names = match entry.tag() {
+                    gimli::DW_TAG_subprogram => {
+                        subprograms.push(depth);
+                        &mut functions
+                    }
+                    gimli::DW_TAG_variable if subprograms.is_empty() => &mut variables,
+                    _ => continue,
+                };
+                if let Some(name) = get_name(entry, unit, dwarf) {
+                    names.push(name);
+                }
+                // Rust functions can also be looked up by their full path, as in backtraces
+                if let Some(name) = get_linkage_name(entry, unit, dwarf) {
+                    names.push(name);
+                }
+            }
+            for (name, linkage_name) in read_foreign_names(dwarf, &foreign_functions)?.values() {
+                functions.push(name.clone());
+                functions.extend(linkage_name.clone());
+            }
+            Ok((name, functions, variables))
+        });
+        // A missing .dwo file is reported once the unit is needed, under the file's name
+        let (name, functions, variables) = match unit_names {
+            Ok(unit_names) => unit_names,
+            Err(Error::SplitDwarfNotFound(dwo_name)) => (Some(dwo_name), Vec::new(), Vec::new()),
+            Err(_) => Default::default(),
+        };
+        let name = name
+            .or_else(|| get_unit_name(root, &unit, &dwarf))
+            .unwrap_or_else(|| "<unknown>".to_string());
+        for name in functions {
+            Index::add_name(&mut index.functions, name, unit_index);
+        }
+        for name in variables {
+            Index::add_name(&mut index.variables, name, unit_index);
+        }
+        // The line table stays with the skeleton unit in the binary
+        let files = get_line_files(&unit, &dwarf).unwrap_or_default();
+        index.units.push((offset, name, files));
+        unit_indexes.insert(offset, unit_index);
+    }
+
+    for (low_pc, high_pc, offset) in aranges {
+        if let Some(&unit_index) = unit_indexes.get(&offset) {
+            index.ranges.push((low_pc, high_pc, unit_index));
+        }
+    }
+    // Code discarded by the linker keeps its ranges, relocated to address 0
+    index
+        .ranges
+        .retain(|range| range.0 != 0 && range.0 < range.1);
+    index.ranges.sort();
+    for (name, offset) in names.functions {
+        if let Some(&unit_index) = unit_indexes.get(&offset) {
+            Index::add_name(&mut index.functions, name, unit_index);
+        }
+    }
+    for (name, offset) in names.variables {
+        if let Some(&unit_index) = unit_indexes.get(&offset) {
+            Index::add_name(&mut index.variables, name, unit_index);
+        }
+    }
+    Ok(index)
+}
+
+/// The names of functions and variables in compile units from .debug_names
+#[derive(Default)]
+struct NameIndex {
+    units: Vec<usize>, // .debug_info offsets of the units the tables cover
+    functions: Vec<(String, usize)>, // (name, unit offset)
+    variables: Vec<(String, usize)>, // (name, unit offset)
+}
+
+/// Reads the name index of .debug_names (DWARF 5, section 6.1.1).
+fn read_debug_names(
+    data: &[u8],
+    endian: gimli::RunTimeEndian,
+    debug_str: &gimli::DebugStr<gimli::EndianSlice<gimli::RunTimeEndian>>,
+) -> Result<NameIndex, gimli::Error> {
+    use gimli::Reader;
+
+    let mut index = NameIndex::default();
+    let mut section = gimli::EndianSlice::new(data, endian);
+    // There may be a name index per unit, or one for the whole binary
+    while !section.is_empty() {
+        let (length, format) = section.read_initial_length()?;
+        let mut table = section.split(length)?;
+        let version = table.read_u16()?;
+        if version != 5 {
+            return Err(gimli::Error::UnknownVersion(version.into()));
+        }
+        table.read_u16()?; // Padding
+        let comp_unit_count = table.read_u32()? as usize;
+        let local_type_unit_count = table.read_u32()? as usize;
+        let foreign_type_unit_count = table.read_u32()? as usize;
+        let bucket_count = table.read_u32()? as usize;
+        let name_count = table.read_u32()? as usize;
+        let abbrev_table_size = table.read_u32()? as usize;
+        let augmentation_string_size = table.read_u32()? as usize;
+        table.skip(augmentation_string_size)?;
+        let mut comp_units = Vec::new();
+        for _ in 0..comp_unit_count {
+            comp_units.push(table.read_offset(format)?);
+        }
+        index.units.extend(comp_units.iter().cloned());
+        let word_size = format.word_size() as usize;
+        table.skip(local_type_unit_count * word_size + foreign_type_unit_count * 8)?;
+        // The hash table only speeds up lookups of a single name
+        let hash_count = if bucket_count > 0 { name_count } else { 0 };
+        table.skip((bucket_count + hash_count) * 4)?;
+        let mut string_offsets = table.split(name_count * word_size)?;
+        let mut entry_offsets = table.split(name_count * word_size)?;
+        let mut abbrevs_data = table.split(abbrev_table_size)?;
+        let entry_pool = table;
+
+        // Abbreviation code -> (tag, [(index attribute, form)])
+        let mut abbrevs = HashMap::new();
+        loop {
+            let code = abbrevs_data.read_uleb128()?;
+            if code == 0 {
+                break;
+            }
+            let tag = gimli::DwTag(abbrevs_data.read_uleb128_u16()?);
+            let mut attributes = Vec::new();
+            loop {
+                let idx = gimli::DwIdx(abbrevs_data.read_uleb128_u16()?);
+                let form = gimli::DwForm(abbrevs_data.read_uleb128_u16()?);
+                if idx.0 == 0 && form.0 == 0 {
+                    break;
+                }
+                attributes.push((idx, form));
+            }
+            abbrevs.insert(code, (tag, attributes));
+        }
+
+        for _ in 0..name_count {
+            let name_offset = gimli::DebugStrOffset(string_offsets.read_offset(format)?);
+            let name = debug_str
+                .get_str(name_offset)?
+                .to_string_lossy()
+                .into_owned();
+            let mut entries = entry_pool;
+            entries.skip(entry_offsets.read_offset(format)?)?;
+            loop {
+                let code = entries.read_uleb128()?;
+                if code == 0 {
+                    break;
+                }
+                let (tag, attributes) = abbrevs
+                    .get(&code)
+                    .ok_or(gimli::Error::UnknownAbbreviation)?;
+                // A single unit in the table needs no DW_IDX_compile_unit
+                let mut comp_unit = if comp_unit_count == 1 { Some(0) } else { None };
+                for (idx, form) in attributes {
+                    let value = read_index_value(&mut entries, *form, format)?;
+                    match *idx {
+                        gimli::DW_IDX_compile_unit => comp_unit = Some(value as usize),
+                        gimli::DW_IDX_type_unit => comp_unit = None,
+                        _ => {}
+                    }
+                }
+                let unit = match comp_unit.and_then(|comp_unit| comp_units.get(comp_unit)) {
+                    Some(offset) => *offset,
+                    None => continue,
+                };
+                let names = match *tag {
+                    gimli::DW_TAG_subprogram | gimli::DW_TAG_inlined_subroutine => {
+                        &mut index.functions
+                    }
+                    gimli::DW_TAG_variable => &mut index.variables,
+                    _ => continue,
+                };
+                // Mangled linkage names are indexed by their demangled path
+                if let Some(demangled) = demangle(&name) {
+                    names.push((demangled, unit));
+                }
+                names.push((name.clone(), unit));
+            }
+        }
+    }
+    Ok(index)
+}
+
+/// Reads an attribute of a .debug_names entry.
+fn read_index_value<R: gimli::Reader<Offset = usize>>(
+    input: &mut R,
+    form: gimli::DwForm,
+    format: gimli::Format,
+) -> Result<u64, gimli::Error> {
+    Ok(match form {
+        gimli::DW_FORM_flag_present => 1,
+        gimli::DW_FORM_flag | gimli::DW_FORM_data1 | gimli::DW_FORM_ref1 => input.read_u8()?.into(),
+        gimli::DW_FORM_data2 | gimli::DW_FORM_ref2 => input.read_u16()?.into(),
+        gimli::DW_FORM_data4 | gimli::DW_FORM_ref4 => input.read_u32()?.into(),
+        gimli::DW_FORM_data8 | gimli::DW_FORM_ref8 | gimli::DW_FORM_ref_sig8 => input.read_u64()?,
+        gimli::DW_FORM_udata | gimli::DW_FORM_ref_udata => input.read_uleb128()?,
+        gimli::DW_FORM_sdata => input.read_sleb128()? as u64,
+        gimli::DW_FORM_ref_addr | gimli::DW_FORM_sec_offset | gimli::DW_FORM_strp => {
+            input.read_offset(format)? as u64
+        }
+        _ => return Err(gimli::Error::UnknownForm),
+    })
+}
+
+/// Parses the header of the unit at offset in .debug_info, returning the size of the unit along
+/// with the header and DWO id of compilation units (None for type units). gimli's own parser
+/// turns down the DWARF 5 skeleton and split unit types.
+fn unit_header_at<R: Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    offset: usize,
+) -> Result<(usize, Option<SplitUnitHeader<R>>), Error> {
+    use gimli::Section;
+
+    let mut input = dwarf.debug_info.reader().clone();
+    input.skip(offset)?;
+    let (unit_length, format) = input.read_initial_length()?;
+    let length = format.initial_length_size() as usize + unit_length;
+    let mut rest = input.split(unit_length)?;
+    let version = rest.read_u16()?;
+    let mut dwo_id = None;
+    let (address_size, abbrev_offset) = match version {
+        2..=4 => {
+            let abbrev_offset = rest.read_offset(format)?;
+            (rest.read_u8()?, abbrev_offset)
+        }
+        5 => {
+            let unit_type = gimli::DwUt(rest.read_u8()?);
+            let address_size = rest.read_u8()?;
+            let abbrev_offset = rest.read_offset(format)?;
+            match unit_type {
+                gimli::DW_UT_compile | gimli::DW_UT_partial => {}
+                gimli::DW_UT_skeleton | gimli::DW_UT_split_compile => {
+                    dwo_id = Some(rest.read_u64()?);
+                }
+                _ => return Ok((length, None)),
+            }
+            (address_size, abbrev_offset)
+        }
+        _ => return Err(gimli::Error::UnknownVersion(version.into()).into()),
+    };
+    let encoding = gimli::Encoding {
+        format,
+        version,
+        address_size,
+    };
+    let header = gimli::UnitHeader::new(
+        encoding,
+        unit_length,
+        gimli::DebugAbbrevOffset(abbrev_offset),
+        rest,
+    );
+    let header = gimli::CompilationUnitHeader::new(header, gimli::DebugInfoOffset(offset));
+    Ok((length, Some((header, dwo_id))))
+}
+
+/// Reads the names (see get_name and get_linkage_name) of the named DIEs at offsets in
+/// .debug_info, which may be in any unit. Link-time optimization refers to functions from the
+/// units they were written in with DW_FORM_ref_addr.
+fn read_foreign_names<R: Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    offsets: &[usize],
+) -> Result<HashMap<usize, (String, Option<String>)>, Error> {
+    use gimli::Section;
+
+    let mut names = HashMap::new();
+    let mut unit_offset = 0;
+    while !offsets.is_empty() && unit_offset < dwarf.debug_info.reader().len() {
+        let (length, header) = unit_header_at(dwarf, unit_offset)?;
+        let unit_end = unit_offset + length;
+        let in_unit = offsets
+            .iter()
+            .filter(|offset| unit_offset <= **offset && **offset < unit_end);
+        if let Some((header, _)) = header {
+            let unit = dwarf.unit(header)?;
+            for offset in in_unit {
+                let entry = unit.entry(gimli::UnitOffset(offset - unit_offset))?;
+                if let Some(name) = get_name(&entry, &unit, dwarf) {
+                    let linkage_name = get_linkage_name(&entry, &unit, dwarf);
+                    names.insert(*offset, (name, linkage_name));
+                }
+            }
+        }
+        unit_offset = unit_end;
+    }
+    Ok(names)
+}
+
+/// Finds the compilation unit holding the DIE at offset in .debug_info.
+fn unit_containing<R: Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    offset: usize,
+) -> Result<Option<gimli::Unit<R>>, Error> {
+    let mut unit_offset = 0;
+    while unit_offset <= offset {
+        let (length, header) = unit_header_at(dwarf, unit_offset)?;
+        if offset < unit_offset + length {
+            return Ok(match header {
+                Some((header, _)) => Some(dwarf.unit(header)?),
+                None => None,
+            });
+        }
+        unit_offset += length;
+    }
+    Ok(None)
+}
+
+/// A compilation unit header, with the DWO id of skeleton and split units
+type SplitUnitHeader<R> = (gimli::CompilationUnitHeader<R>, Option<u64>);
+
+/// The (offset, size) of a unit within each section of a .dwp package
+type Contributions = Vec<(gimli::SectionId, (usize, usize))>;
+
+/// The file holding the DIEs of a split DWARF unit
+struct SplitFile {
+    data: memmap::Mmap,
+    contributions: Contributions, // Empty unless the file is a .dwp package
+}
+
+impl SplitFile {
+    /// Finds the split unit of the binary at path that was written to dwo_name: in the .dwp
+    /// package next to the binary, or in dwo_name itself, looked for in the compilation
+    /// directory and then next to the binary.
+    fn open(
+        path: &str,
+        comp_dir: Option<String>,
+        dwo_name: &str,
+        dwo_id: Option<u64>,
+        endian: gimli::RunTimeEndian,
+    ) -> Result<SplitFile, Error> {
+        let map = |path: &path::Path| -> io::Result<memmap::Mmap> {
+            let file = fs::File::open(path)?;
+            unsafe { memmap::Mmap::map(&file) }
+        };
+        let dwp_path = format!("{}.dwp", path);
+        if let (Some(dwo_id), Ok(data)) = (dwo_id, map(path::Path::new(&dwp_path))) {
+            let contributions = object::File::parse(&data).ok().and_then(|object| {
+                let cu_index = section_data(&object, ".debug_cu_index")?;
+                read_cu_index(&cu_index, endian, dwo_id).ok()?
+            });
+            if let Some(contributions) = contributions {
+                return Ok(SplitFile {
+                    data,
+                    contributions,
+                });
+            }
+        }
+        let binary_dir = path::Path::new(path)
+            .parent()
+            .unwrap_or(path::Path::new("."));
+        let mut candidates = vec![binary_dir.join(dwo_name)];
+        if let Some(comp_dir) = comp_dir {
+            candidates.insert(0, path::Path::new(&comp_dir).join(dwo_name));
+        }
+        if let Some(file_name) = path::Path::new(dwo_name).file_name() {
+            candidates.push(binary_dir.join(file_name));
+        }
+        for candidate in candidates {
+            if let Ok(data) = map(&candidate) {
+                return Ok(SplitFile {
+                    data,
+                    contributions: Vec::new(),
+                });
+            }
+        }
+        Err(Error::SplitDwarfNotFound(dwo_name.to_string()))
+    }
+}
+
+/// Looks up the unit with the given DWO id in the .debug_cu_index section of a .dwp package,
+/// returning its contribution to each section. Handles both the GNU (version 2) and DWARF 5
+/// formats.
+fn read_cu_index(
+    data: &[u8],
+    endian: gimli::RunTimeEndian,
+    dwo_id: u64,
+) -> Result<Option<Contributions>, gimli::Error> {
+    use gimli::Reader;
+
+    let mut input = gimli::EndianSlice::new(data, endian);
+    // Version 2 is a 4-byte field; DWARF 5 has a 2-byte version and 2 bytes of padding
+    let mut version_2 = input;
+    let version = if version_2.read_u32()? == 2 {
+        input = version_2;
+        2
+    } else {
+        let version = input.read_u16()?;
+        input.read_u16()?;
+        version
+    };
+    if version != 2 && version != 5 {
+        return Err(gimli::Error::UnknownVersion(version.into()));
+    }
+    let column_count = input.read_u32()? as usize;
+    let unit_count = input.read_u32()? as usize;
+    let slot_count = input.read_u32()? as usize;
+    let mut signatures = input.split(slot_count * 8)?;
+    let mut rows = input.split(slot_count * 4)?;
+    let mut row = None;
+    for _ in 0..slot_count {
+        let signature = signatures.read_u64()?;
+        let index = rows.read_u32()? as usize;
+        if signature == dwo_id && index != 0 {
+            row = Some(index - 1);
+            break;
+        }
+    }
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let mut columns = Vec::new();
+    for _ in 0..column_count {
+        columns.push(input.read_u32()?);
+    }
+    let table_size = unit_count * column_count * 4;
+    let mut offsets = input.split(table_size)?;
+    let mut sizes = input.split(table_size)?;
+    offsets.skip(row * column_count * 4)?;
+    sizes.skip(row * column_count * 4)?;
+    let mut contributions = Vec::new();
+    for column in columns {
+        let offset = offsets.read_u32()? as usize;
+        let size = sizes.read_u32()? as usize;
+        let id = match (column, version) {
+            (1, _) => gimli::SectionId::DebugInfo,
+            (3, _) => gimli::SectionId::DebugAbbrev,
+            (4, _) => gimli::SectionId::DebugLine,
+            (5, 2) => gimli::SectionId::DebugLoc,
+            (5, _) => gimli::SectionId::DebugLocLists,
+            (6, _) => gimli::SectionId::DebugStrOffsets,
+            (8, 5) => gimli::SectionId::DebugRngLists,
+            _ => continue,
+        };
+        contributions.push((id, (offset, size)));
+    }
+    Ok(Some(contributions))
+}
+
+/// Calls f with the DIEs of a compilation unit. For a split DWARF skeleton unit, those are in the
+/// split unit in its .dwo file (or .dwp package) rather than in unit itself.
+fn with_unit_dies<'a, T, F>(
+    dwarf: &gimli::Dwarf<gimli::EndianSlice<'a, gimli::RunTimeEndian>>,
+    unit: &gimli::Unit<gimli::EndianSlice<'a, gimli::RunTimeEndian>>,
+    dwo_id: Option<u64>,
+    path: &str,
+    endian: gimli::RunTimeEndian,
+    f: F,
+) -> Result<T, Error>
+where
+    F: FnOnce(
+        &gimli::Dwarf<gimli::EndianSlice<gimli::RunTimeEndian>>,
+        &gimli::Unit<gimli::EndianSlice<gimli::RunTimeEndian>>,
+    ) -> Result<T, Error>,
+{
+    let mut dwo_name = None;
+    let mut dwo_id = dwo_id;
+    let mut addr_base = unit.addr_base;
+    let mut entries = unit.entries();
+    if let Some((_, root)) = entries.next_dfs()? {
+        let mut attrs = root.attrs();
+        while let Some(attr) = attrs.next()? {
+            // DWARF 4 split units use GNU extensions of the attributes DWARF 5 adopted
+            match (attr.name(), attr.value()) {
+                (gimli::DW_AT_dwo_name, _) | (gimli::DW_AT_GNU_dwo_name, _) => {
+                    if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, unit, dwarf) {
+                        dwo_name = Some(name);
+                    }
+                }
+                (gimli::DW_AT_GNU_dwo_id, value) => {
+                    dwo_id = value.udata_value().or(dwo_id);
+                }
+                (gimli::DW_AT_GNU_addr_base, gimli::AttributeValue::SecOffset(offset)) => {
+                    addr_base = gimli::DebugAddrBase(offset);
+                }
+                _ => {}
+            }
+        }
+    }
+    let dwo_name = match dwo_name {
+        Some(dwo_name) => dwo_name,
+        None => return f(dwarf, unit),
+    };
+    let comp_dir = unit
+        .comp_dir
+        .map(|comp_dir| comp_dir.to_string_lossy().into_owned());
+    let split = SplitFile::open(path, comp_dir, &dwo_name, dwo_id, endian)?;
+    let split_object =
+        object::File::parse(&split.data).map_err(|e| Error::Object(e.to_string()))?;
+    let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
+        let data = section_data(&split_object, &format!("{}.dwo", id.name()))
+            .unwrap_or(borrow::Cow::Borrowed(&[][..]));
+        // The sections of a package hold every unit in it
+        let range = match split
+            .contributions
+            .iter()
+            .find(|(section, _)| *section == id)
+        {
+            Some((_, (offset, size))) => *offset..*offset + *size,
+            None => return Ok(data),
+        };
+        Ok(match data {
+            borrow::Cow::Borrowed(data) => borrow::Cow::Borrowed(data.get(range).unwrap_or(&[])),
+            borrow::Cow::Owned(data) => borrow::Cow::Owned(data.get(range).unwrap_or(&[]).to_vec()),
+        })
+    };
+    let split_cow = gimli::Dwarf::load(&load_section, &|_| Ok(borrow::Cow::Borrowed(&[][..])))?;
+    let mut split_dwarf = split_cow.borrow(|section| gimli::EndianSlice::new(section, endian));
+    // Addresses are only in the binary, where the linker could relocate them
+    split_dwarf.debug_addr = dwarf.debug_addr;
+    let (header, _) = unit_header_at(&split_dwarf, 0)?
+        .1
+        .ok_or(Error::Gimli(gimli::Error::MissingUnitDie))?;
+    let mut split_unit = split_dwarf.unit(header)?;
+    split_unit.addr_base = addr_base;
+    // Relative paths in the split unit are relative to the skeleton's compilation directory
+    split_unit.comp_dir = unit.comp_dir;
+    let encoding = split_unit.encoding();
+    if encoding.version >= 5 {
+        // Split units leave out the bases of their sections, which start after the header
+        let initial_length_size = encoding.format.initial_length_size() as usize;
+        if split_unit.str_offsets_base.0 == 0 {
+            split_unit.str_offsets_base = gimli::DebugStrOffsetsBase(initial_length_size + 4);
+        }
+        if split_unit.loclists_base.0 == 0 {
+            split_unit.loclists_base = gimli::DebugLocListsBase(initial_length_size + 8);
+        }
+        if split_unit.rnglists_base.0 == 0 {
+            split_unit.rnglists_base = gimli::DebugRngListsBase(initial_length_size + 8);
+        }
+    }
+    f(&split_dwarf, &split_unit)
+}
+
+/// Returns the address of an attribute holding one, as an address or an index into .debug_addr.
+fn get_address<R: Reader>(
+    value: gimli::AttributeValue<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<usize> {
+    match value {
+        gimli::AttributeValue::Addr(addr) => Some(addr.try_into().unwrap()),
+        gimli::AttributeValue::DebugAddrIndex(index) => {
+            Some(dwarf.address(unit, index).ok()?.try_into().unwrap())
+        }
+        _ => None,
+    }
+}
+
+/// Returns the address ranges covered by entry, from its DW_AT_low_pc and DW_AT_high_pc or
+/// DW_AT_ranges. (gimli's own die_ranges only understands plain addresses.)
+fn get_ranges<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Result<Vec<(usize, usize)>, Error> {
+    let mut low_pc = None;
+    let mut high_pc = None;
+    let mut ranges = Vec::new();
+    let mut attrs = entry.attrs();
+    while let Some(attr) = attrs.next()? {
+        match attr.name() {
+            gimli::DW_AT_low_pc => low_pc = get_address(attr.value(), unit, dwarf),
+            gimli::DW_AT_high_pc => high_pc = Some(attr.value()),
+            gimli::DW_AT_ranges => {
+                if let Some(offset) = dwarf.attr_ranges_offset(unit, attr.value())? {
+                    let mut iter = dwarf.ranges(unit, offset)?;
+                    while let Some(range) = iter.next()? {
+                        ranges.push((
+                            range.begin.try_into().unwrap(),
+                            range.end.try_into().unwrap(),
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    // DW_AT_high_pc is either an address or an offset from DW_AT_low_pc
+    if let (Some(low_pc), Some(high_pc)) = (low_pc, high_pc) {
+        let high_pc = match high_pc.udata_value() {
+            Some(offset) => Some(low_pc + offset as usize),
+            None => get_address(high_pc, unit, dwarf),
+        };
+        if let Some(high_pc) = high_pc {
+            ranges.push((low_pc, high_pc));
+        }
+    }
+    Ok(ranges)
+}
+
+/// Returns the DW_AT_name of entry, if it has one.
+fn get_name<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<String> {
+    match get_attr_value(&entry.attr(gimli::DW_AT_name).ok()??, unit, dwarf) {
+        Ok(DebugValue::Str(name)) => Some(name),
+        _ => None,
+    }
+}
+
+/// Returns the demangled DW_AT_linkage_name of an entry, such as `main::area` for a Rust function
+/// (None if it has none, or it isn't mangled by a scheme we know).
+fn get_linkage_name<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<String> {
+    let attr = entry
+        .attr(gimli::DW_AT_linkage_name)
+        .ok()?
+        .or(entry.attr(gimli::DW_AT_MIPS_linkage_name).ok()?)?;
+    match get_attr_value(&attr, unit, dwarf) {
+        Ok(DebugValue::Str(name)) => demangle(&name),
+        _ => None,
+    }
+}
+
+/// Demangles a Rust (legacy or v0) or C++ symbol name.
+fn demangle(name: &str) -> Option<String> {
+    addr2line::demangle(name, gimli::DW_LANG_Rust)
+        .or_else(|| addr2line::demangle(name, gimli::DW_LANG_C_plus_plus))
+}
+
+/// Returns the name of a compilation unit: the path of its source file. rustc appends the name
+/// of the codegen unit, as in "src/main.rs/@/main.5d2a1e0c-cgu.0", which is dropped.
+fn get_unit_name<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<String> {
+    let name = get_name(entry, unit, dwarf)?;
+    Some(match name.find("/@/") {
+        Some(end) => name[..end].to_string(),
+        None => name,
+    })
+}
+
+/// Returns the full path of a file in a line table: file names are relative to their directory,
+/// which is relative to the compilation directory (like addr2line reports them).
+fn get_file_path<R: Reader>(
+    file: &gimli::FileEntry<R>,
+    header: &gimli::LineProgramHeader<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Result<String, Error> {
+    let mut path = path::PathBuf::new();
+    if let Some(comp_dir) = &unit.comp_dir {
+        path.push(comp_dir.to_string_lossy()?.as_ref());
+    }
+    if let Some(dir) = file.directory(header) {
+        path.push(dwarf.attr_string(unit, dir)?.to_string_lossy()?.as_ref());
+    }
+    path.push(
+        dwarf
+            .attr_string(unit, file.path_name())?
+            .to_string_lossy()?
+            .as_ref(),
+    );
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Returns the full paths of the source files in the line table of unit.
+fn get_line_files<R: Reader>(
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Result<Vec<String>, Error> {
+    let header = match &unit.line_program {
+        Some(program) => program.header(),
+        None => return Ok(Vec::new()),
+    };
+    let mut files = Vec::new();
+    for file in header.file_names() {
+        let path = get_file_path(file, header, unit, dwarf)?;
+        if !files.contains(&path) {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Parses the compilation unit whose header is at offset in .debug_info. path is the binary's
+/// path, which split DWARF files are looked up next to.
+pub fn load_unit(
+    object: &object::File,
+    endian: gimli::RunTimeEndian,
+    path: &str,
+    offset: usize,
+) -> Result<File, Error> {
+    let dwarf_cow = load_dwarf(object)?;
 
     // Borrow a `Cow<[u8]>` to create an `EndianSlice`.
     let borrow_section: &dyn for<'a> Fn(
         &'a borrow::Cow<[u8]>,
     ) -> gimli::EndianSlice<'a, gimli::RunTimeEndian> =
-        &|section| gimli::EndianSlice::new(&*section, endian);
+        &|section| gimli::EndianSlice::new(section, endian);
 
     // Create `EndianSlice`s for all of the sections.
     let dwarf = dwarf_cow.borrow(&borrow_section);
 
-    // Define a mapping from type offsets to type structs
-    let mut offset_to_type: HashMap<usize, Type> = HashMap::new();
+    let (header, dwo_id) = unit_header_at(&dwarf, offset)?
+        .1
+        .ok_or(Error::Gimli(gimli::Error::UnsupportedUnitType))?;
+    let unit = dwarf.unit(header)?;
+    // The line table stays with the skeleton unit in the binary
+    with_unit_dies(
+        &dwarf,
+        &unit,
+        dwo_id,
+        path,
+        endian,
+        |split_dwarf, split_unit| parse_unit(split_dwarf, split_unit, &dwarf, &unit),
+    )
+}
 
+/// Reads the DIEs of unit, along with the line table of line_unit (which differs from unit for
+/// split DWARF).
+fn parse_unit<R: Reader, S: Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    unit: &gimli::Unit<R>,
+    line_dwarf: &gimli::Dwarf<S>,
+    line_unit: &gimli::Unit<S>,
+) -> Result<File, Error> {
     let mut compilation_units: Vec<File> = Vec::new();
 
-    // Iterate over the compilation units.
-    let mut iter = dwarf.units();
-    while let Some(header) = iter.next()? {
-        let unit = dwarf.unit(header)?;
+    // Map from .debug_info offsets of subprogram DIEs to (file index, function index), used to
+    // resolve DW_AT_abstract_origin references once the whole unit has been read
+    let mut function_offsets: HashMap<usize, (usize, usize)> = HashMap::new();
+    // (abstract origin offset, entry address) for every DW_TAG_inlined_subroutine
+    let mut inlined_entries: Vec<(usize, usize)> = Vec::new();
+    // (abstract origin offset, (file index, function index)) for out-of-line copies of inline
+    // functions, which carry no name of their own
+    let mut concrete_functions: Vec<(usize, (usize, usize))> = Vec::new();
 
-        // Iterate over the Debugging Information Entries (DIEs) in the unit.
-        let mut depth = 0;
-        let mut entries = unit.entries();
-        while let Some((delta_depth, entry)) = entries.next_dfs()? {
-            depth += delta_depth;
-            // Update the offset_to_type mapping for types
-            // Update the variable list for formal params/variables
-            match entry.tag() {
-                gimli::DW_TAG_compile_unit => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    compilation_units.push(File {
-                        name,
-                        global_variables: Vec::new(),
-                        functions: Vec::new(),
-                        lines: Vec::new(),
-                    });
-                }
-                gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
+    // Types are often defined after the entries that refer to them, so collect them first
+    let mut types = read_types(unit, dwarf)?;
+
+    // Iterate over the Debugging Information Entries (DIEs) in the unit.
+    let mut depth = 0;
+    // Stack of (depth, address ranges) for the lexical blocks enclosing the current entry
+    let mut blocks: Vec<(isize, Vec<(usize, usize)>)> = Vec::new();
+    // Depths of the subprograms enclosing the current entry. Variables outside of any are global
+    // (in Rust, they may be nested in namespaces).
+    let mut subprograms: Vec<isize> = Vec::new();
+    // Depths of the inlined subroutines enclosing the current entry, whose variables are left out
+    let mut inlined_subroutines: Vec<isize> = Vec::new();
+    // Depth of the call site whose parameters come next, if it has a return address
+    let mut call_site: Option<isize> = None;
+    // Units holding the abstract instances of inlined functions, other than this one
+    let mut origin_units = Vec::new();
+    let mut entries = unit.entries();
+    while let Some((delta_depth, entry)) = entries.next_dfs()? {
+        depth += delta_depth;
+        while blocks.last().map_or(false, |block| block.0 >= depth) {
+            blocks.pop();
+        }
+        while subprograms
+            .last()
+            .map_or(false, |subprogram| *subprogram >= depth)
+        {
+            subprograms.pop();
+        }
+        while inlined_subroutines
+            .last()
+            .map_or(false, |inlined| *inlined >= depth)
+        {
+            inlined_subroutines.pop();
+        }
+        // Update the variable list for formal params/variables
+        match entry.tag() {
+            gimli::DW_TAG_compile_unit => {
+                let name =
+                    get_unit_name(entry, unit, dwarf).unwrap_or_else(|| "<unknown>".to_string());
+                compilation_units.push(File {
+                    name,
+                    global_variables: Vec::new(),
+                    functions: Vec::new(),
+                    lines: Vec::new(),
+                    prologue_ends: Vec::new(),
+                    call_sites: Vec::new(),
+                });
+            }
+            gimli::DW_TAG_subprogram => {
+                subprograms.push(depth);
+                let mut func: Function = Default::default();
+                let mut abstract_origin: Option<usize> = None;
+                let mut attrs = entry.attrs();
+                while let Some(attr) = attrs.next()? {
+                    let val = get_attr_value(&attr, unit, dwarf);
+                    //println!("   {}: {:?}", attr.name(), val);
+                    match attr.name() {
+                        gimli::DW_AT_name => {
+                            if let Ok(DebugValue::Str(name)) = val {
+                                func.name = name;
+                            }
                         }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
-                        } else {
-                            // TODO: report error?
-                            0
+                        gimli::DW_AT_linkage_name | gimli::DW_AT_MIPS_linkage_name => {
+                            if let Ok(DebugValue::Str(name)) = val {
+                                func.linkage_name = demangle(&name);
+                            }
                         }
-                    } else {
-                        // TODO: report error?
-                        0
-                    };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
-                }
-                gimli::DW_TAG_subprogram => {
-                    let mut func: Function = Default::default();
-                    let mut attrs = entry.attrs();
-                    while let Some(attr) = attrs.next()? {
-                        let val = get_attr_value(&attr, &unit, &dwarf);
-                        //println!("   {}: {:?}", attr.name(), val);
-                        match attr.name() {
-                            gimli::DW_AT_name => {
-                                if let Ok(DebugValue::Str(name)) = val {
-                                    func.name = name;
-                                }
+                        gimli::DW_AT_high_pc => {
+                            if let Ok(DebugValue::Uint(high_pc)) = val {
+                                func.text_length = high_pc.try_into().unwrap();
                             }
-                            gimli::DW_AT_high_pc => {
-                                if let Ok(DebugValue::Uint(high_pc)) = val {
-                                    func.text_length = high_pc.try_into().unwrap();
-                                }
+                        }
+                        gimli::DW_AT_low_pc => {
+                            //println!("low pc {:?}", attr.value());
+                            if let Ok(DebugValue::Uint(low_pc)) = val {
+                                func.address = low_pc.try_into().unwrap();
                             }
-                            gimli::DW_AT_low_pc => {
-                                //println!("low pc {:?}", attr.value());
-                                if let Ok(DebugValue::Uint(low_pc)) = val {
-                                    func.address = low_pc.try_into().unwrap();
-                                }
+                        }
+                        gimli::DW_AT_decl_line => match val {
+                            Ok(DebugValue::Uint(line_number)) => {
+                                func.line_number = line_number.try_into().unwrap()
                             }
-                            gimli::DW_AT_decl_line => {
-                                if let Ok(DebugValue::Uint(line_number)) = val {
-                                    func.line_number = line_number.try_into().unwrap();
-                                }
+                            // A line shared through the abbreviation (DW_FORM_implicit_const)
+                            Ok(DebugValue::Int(line_number)) => {
+                                func.line_number = line_number.try_into().unwrap_or(0)
                             }
                             _ => {}
+                        },
+                        gimli::DW_AT_frame_base => {
+                            func.frame_base = get_location(&attr, unit, dwarf);
                         }
+                        gimli::DW_AT_type => {
+                            if let Ok(DebugValue::Size(offset)) = val {
+                                // Types from other units aren't known; take them to be
+                                // register-sized
+                                func.return_type = Some(match types.get(offset) {
+                                    Some(dtype) => dtype,
+                                    None => Type::new("<unknown>".to_string(), 8),
+                                });
+                            }
+                        }
+                        gimli::DW_AT_abstract_origin => {
+                            if let Ok(DebugValue::Size(offset)) = val {
+                                abstract_origin = Some(offset);
+                            }
+                        }
+                        _ => {}
                     }
-                    compilation_units.last_mut().unwrap().functions.push(func);
-                }
-                gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
-                    let mut name = String::new();
-                    let mut entity_type: Option<Type> = None;
-                    let mut location: Option<Location> = None;
-                    let mut line_number = 0;
-                    let mut attrs = entry.attrs();
-                    while let Some(attr) = attrs.next()? {
-                        let val = get_attr_value(&attr, &unit, &dwarf);
-                        //println!("   {}: {:?}", attr.name(), val);
-                        match attr.name() {
-                            gimli::DW_AT_name => {
-                                if let Ok(DebugValue::Str(attr_name)) = val {
-                                    name = attr_name;
-                                }
+                }
+                let file_index = compilation_units.len() - 1;
+                let file = compilation_units.last_mut().unwrap();
+                let index = (file_index, file.functions.len());
+                function_offsets.insert(get_section_offset(entry, unit), index);
+                if let Some(origin) = abstract_origin {
+                    concrete_functions.push((origin, index));
+                }
+                file.functions.push(func);
+            }
+            gimli::DW_TAG_inlined_subroutine => {
+                inlined_subroutines.push(depth);
+                let mut abstract_origin: Option<usize> = None;
+                let mut entry_pc: Option<usize> = None;
+                let mut attrs = entry.attrs();
+                while let Some(attr) = attrs.next()? {
+                    match (attr.name(), attr.value()) {
+                        (gimli::DW_AT_abstract_origin, _) => {
+                            if let Ok(DebugValue::Size(offset)) = get_attr_value(&attr, unit, dwarf)
+                            {
+                                abstract_origin = Some(offset);
                             }
-                            gimli::DW_AT_type => {
-                                if let Ok(DebugValue::Size(offset)) = val {
-                                    if let Some(dtype) = offset_to_type.get(&offset).clone() {
-                                        entity_type = Some(dtype.clone());
-                                    }
-                                }
+                        }
+                        (gimli::DW_AT_entry_pc, value) => {
+                            entry_pc = get_address(value, unit, dwarf).or(entry_pc);
+                        }
+                        _ => {}
+                    }
+                }
+                // Without DW_AT_entry_pc, the instance is entered at its lowest address
+                if entry_pc.is_none() {
+                    entry_pc = get_ranges(entry, unit, dwarf)?
+                        .iter()
+                        .map(|range| range.0)
+                        .min();
+                }
+                if let (Some(origin), Some(entry_pc)) = (abstract_origin, entry_pc) {
+                    inlined_entries.push((origin, entry_pc));
+                }
+            }
+            gimli::DW_TAG_lexical_block => {
+                let ranges = get_ranges(entry, unit, dwarf)?;
+                if !ranges.is_empty() {
+                    blocks.push((depth, ranges));
+                }
+            }
+            gimli::DW_TAG_call_site | gimli::DW_TAG_GNU_call_site => {
+                // The GNU extension gives the return address as DW_AT_low_pc
+                let return_address = entry
+                    .attr_value(gimli::DW_AT_call_return_pc)?
+                    .or(entry.attr_value(gimli::DW_AT_low_pc)?)
+                    .and_then(|value| get_address(value, unit, dwarf));
+                call_site = None;
+                if let Some(return_address) = return_address {
+                    compilation_units
+                        .last_mut()
+                        .unwrap()
+                        .call_sites
+                        .push(CallSite {
+                            return_address,
+                            parameters: Vec::new(),
+                        });
+                    call_site = Some(depth);
+                }
+            }
+            gimli::DW_TAG_call_site_parameter | gimli::DW_TAG_GNU_call_site_parameter
+                if call_site == Some(depth - 1) =>
+            {
+                // Only parameters passed in registers can be asked for by DW_OP_entry_value
+                let mut register: Option<u16> = None;
+                let mut value: Option<Expression> = None;
+                let mut attrs = entry.attrs();
+                while let Some(attr) = attrs.next()? {
+                    match (attr.name(), attr.value()) {
+                        (gimli::DW_AT_location, gimli::AttributeValue::Exprloc(data)) => {
+                            let mut pc = data.0;
+                            if let Ok(gimli::Operation::Register { register: number }) =
+                                gimli::Operation::parse(&mut pc, unit.encoding())
+                            {
+                                register = Some(number.0).filter(|_| pc.is_empty());
                             }
-                            gimli::DW_AT_location => {
-                                if let Some(loc) = get_location(&attr, &unit) {
-                                    location = Some(loc);
-                                }
+                        }
+                        (gimli::DW_AT_call_value, gimli::AttributeValue::Exprloc(data))
+                        | (
+                            gimli::DW_AT_GNU_call_site_value,
+                            gimli::AttributeValue::Exprloc(data),
+                        ) => {
+                            value = read_expression(&data.0, unit);
+                        }
+                        _ => {}
+                    }
+                }
+                if let (Some(register), Some(value)) = (register, value) {
+                    let file = compilation_units.last_mut().unwrap();
+                    let call_site = file.call_sites.last_mut().unwrap();
+                    call_site.parameters.push((register, value));
+                }
+            }
+            gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
+                let mut name = String::new();
+                let mut entity_type: Option<Type> = None;
+                let mut location: Option<Location> = None;
+                let mut line_number = 0;
+                let mut abstract_origin: Option<usize> = None;
+                let mut attrs = entry.attrs();
+                while let Some(attr) = attrs.next()? {
+                    let val = get_attr_value(&attr, unit, dwarf);
+                    //println!("   {}: {:?}", attr.name(), val);
+                    match attr.name() {
+                        gimli::DW_AT_name => {
+                            if let Ok(DebugValue::Str(attr_name)) = val {
+                                name = attr_name;
                             }
-                            gimli::DW_AT_decl_line => {
-                                if let Ok(DebugValue::Uint(num)) = val {
-                                    line_number = num;
-                                }
+                        }
+                        gimli::DW_AT_type => {
+                            if let Ok(DebugValue::Size(offset)) = val {
+                                entity_type = types.get(offset);
+                            }
+                        }
+                        gimli::DW_AT_location => {
+                            if let Some(loc) = get_location(&attr, unit, dwarf) {
+                                location = Some(loc);
                             }
+                        }
+                        gimli::DW_AT_decl_line => match val {
+                            Ok(DebugValue::Uint(num)) => line_number = num,
+                            // A line shared through the abbreviation (DW_FORM_implicit_const)
+                            Ok(DebugValue::Int(num)) => line_number = num.try_into().unwrap_or(0),
                             _ => {}
+                        },
+                        gimli::DW_AT_abstract_origin => {
+                            if let Ok(DebugValue::Size(offset)) = val {
+                                abstract_origin = Some(offset);
+                            }
                         }
+                        _ => {}
                     }
-                    if entity_type.is_some() && location.is_some() {
-                        let var = Variable {
-                            name,
-                            entity_type: entity_type.unwrap(),
-                            location: location.unwrap(),
-                            line_number: line_number.try_into().unwrap(),
-                        };
-                        if depth == 1 {
-                            compilation_units
-                                .last_mut()
-                                .unwrap()
-                                .global_variables
-                                .push(var);
-                        } else if depth > 1 {
-                            compilation_units
-                                .last_mut()
-                                .unwrap()
-                                .functions
-                                .last_mut()
-                                .unwrap()
-                                .variables
-                                .push(var);
+                }
+                // Out-of-line copies of inline functions leave the rest of their variables'
+                // descriptions to the abstract instance
+                if let Some(origin) = abstract_origin.filter(|_| inlined_subroutines.is_empty()) {
+                    let origin =
+                        read_variable_origin(origin, unit, dwarf, &mut types, &mut origin_units);
+                    if let Ok(Some((origin_name, origin_type, origin_line))) = origin {
+                        if name.is_empty() {
+                            name = origin_name;
+                        }
+                        entity_type = entity_type.or(origin_type);
+                        if line_number == 0 {
+                            line_number = origin_line;
                         }
                     }
                 }
-                // NOTE: :You may consider supporting other types by extending this
-                // match statement
-                _ => {}
+                let local = !subprograms.is_empty();
+                if location.is_none() && local && !name.is_empty() {
+                    // Optimizing compilers omit the location of locals that never live
+                    // anywhere; an empty location list reports them as optimized out
+                    location = Some(Location::List(Vec::new()));
+                }
+                if let (Some(entity_type), Some(location)) = (entity_type, location) {
+                    let var = Variable {
+                        name,
+                        entity_type,
+                        location,
+                        line_number: line_number.try_into().unwrap(),
+                        is_parameter: entry.tag() == gimli::DW_TAG_formal_parameter,
+                        scope: blocks.last().map(|block| block.1.clone()),
+                    };
+                    if !local {
+                        compilation_units
+                            .last_mut()
+                            .unwrap()
+                            .global_variables
+                            .push(var);
+                    } else {
+                        compilation_units
+                            .last_mut()
+                            .unwrap()
+                            .functions
+                            .last_mut()
+                            .unwrap()
+                            .variables
+                            .push(var);
+                    }
+                }
             }
+            // NOTE: :You may consider supporting other types by extending this
+            // match statement
+            _ => {}
         }
+    }
 
-        // Get line numbers
-        if let Some(program) = unit.line_program.clone() {
-            // Iterate over the line program rows.
-            let mut rows = program.rows();
-            while let Some((header, row)) = rows.next_row()? {
-                if !row.end_sequence() {
-                    // Determine the path. Real applications should cache this for performance.
-                    let mut path = path::PathBuf::new();
-                    if let Some(file) = row.file(header) {
-                        if let Some(dir) = file.directory(header) {
-                            path.push(dwarf.attr_string(&unit, dir)?.to_string_lossy().as_ref());
-                        }
-                        path.push(
-                            dwarf
-                                .attr_string(&unit, file.path_name())?
-                                .to_string_lossy()
-                                .as_ref(),
-                        );
-                    }
+    // Get line numbers. Every row is kept, including those for code from headers (or inlined
+    // from other files), under the full path of its file.
+    if let (Some(program), Some(file)) =
+        (line_unit.line_program.clone(), compilation_units.last_mut())
+    {
+        // Iterate over the line program rows.
+        let mut rows = program.rows();
+        while let Some((header, row)) = rows.next_row()? {
+            // Only statements are places to stop at; the other rows just refine locations
+            if row.end_sequence() || !row.is_stmt() {
+                continue;
+            }
+            let path = match row.file(header) {
+                Some(entry) => get_file_path(entry, header, line_unit, line_dwarf)?,
+                None => continue,
+            };
+
+            // Determine line/column. DWARF line/column is never 0, so we use that
+            // but other applications may want to display this differently.
+            let line = row.line().unwrap_or(0);
+
+            file.lines.push(Line {
+                file: path,
+                number: line.try_into().unwrap(),
+                address: row.address().try_into().unwrap(),
+            });
+            if row.prologue_end() {
+                file.prologue_ends.push(row.address().try_into().unwrap());
+            }
+        }
+    }
+
+    // Resolve inlined instances and out-of-line copies to the functions they were inlined from.
+    // Origins in other units only have their names read.
+    let foreign_origins: Vec<usize> = concrete_functions
+        .iter()
+        .map(|(origin, _)| *origin)
+        .chain(inlined_entries.iter().map(|(origin, _)| *origin))
+        .filter(|origin| !function_offsets.contains_key(origin))
+        .collect();
+    let foreign_names = read_foreign_names(dwarf, &foreign_origins).unwrap_or_default();
+    for (origin, index) in concrete_functions {
+        let (name, linkage_name) = match function_offsets.get(&origin) {
+            Some(&(file, func)) => {
+                let origin = &compilation_units[file].functions[func];
+                (origin.name.clone(), origin.linkage_name.clone())
+            }
+            None => match foreign_names.get(&origin) {
+                Some(names) => names.clone(),
+                None => continue,
+            },
+        };
+        let concrete = &mut compilation_units[index.0].functions[index.1];
+        if concrete.name.is_empty() {
+            concrete.name = name;
+        }
+        if concrete.linkage_name.is_none() {
+            concrete.linkage_name = linkage_name;
+        }
+    }
+    for (origin, entry_pc) in inlined_entries {
+        let (file, func) = match function_offsets.get(&origin) {
+            Some(&index) => index,
+            // A function inlined from another unit gets an entry here, without code of its own
+            None => match foreign_names.get(&origin) {
+                Some((name, linkage_name)) => {
+                    let file_index = compilation_units.len() - 1;
+                    let functions = &mut compilation_units[file_index].functions;
+                    functions.push(Function {
+                        name: name.clone(),
+                        linkage_name: linkage_name.clone(),
+                        ..Default::default()
+                    });
+                    let index = (file_index, functions.len() - 1);
+                    function_offsets.insert(origin, index);
+                    index
+                }
+                None => continue,
+            },
+        };
+        compilation_units[file].functions[func]
+            .inlined_addresses
+            .push(entry_pc);
+    }
+    compilation_units
+        .pop()
+        .ok_or(Error::Gimli(gimli::Error::MissingUnitDie))
+}
+
+/// Reads the name, type and declaration line of the variable DIE at offset in .debug_info, which
+/// is in the abstract instance of an inline function. types are the types of unit; those of
+/// other units (with link-time optimization) are read into origin_units as they're needed.
+fn read_variable_origin<R: Reader>(
+    offset: usize,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+    types: &mut TypeTable,
+    origin_units: &mut Vec<(gimli::Unit<R>, TypeTable)>,
+) -> Result<Option<(String, Option<Type>, u64)>, Error> {
+    let section_offset = UnitSectionOffset::DebugInfoOffset(gimli::DebugInfoOffset(offset));
+    let (unit, types) = if section_offset.to_unit_offset(unit).is_some() {
+        (unit, types)
+    } else {
+        let index = origin_units
+            .iter()
+            .position(|(unit, _)| section_offset.to_unit_offset(unit).is_some());
+        let index = match index {
+            Some(index) => index,
+            None => {
+                let unit = match unit_containing(dwarf, offset)? {
+                    Some(unit) => unit,
+                    None => return Ok(None),
+                };
+                let types = read_types(&unit, dwarf)?;
+                origin_units.push((unit, types));
+                origin_units.len() - 1
+            }
+        };
+        let (unit, types) = &mut origin_units[index];
+        (&*unit, types)
+    };
+    let entry = match section_offset.to_unit_offset(unit) {
+        Some(unit_offset) => unit.entry(unit_offset)?,
+        None => return Ok(None),
+    };
+    let name = get_name(&entry, unit, dwarf).unwrap_or_default();
+    let entity_type = match entry.attr(gimli::DW_AT_type)? {
+        Some(attr) => match get_attr_value(&attr, unit, dwarf)? {
+            DebugValue::Size(offset) => types.get(offset),
+            _ => None,
+        },
+        None => None,
+    };
+    let line_number = entry
+        .attr(gimli::DW_AT_decl_line)?
+        .and_then(|attr| attr.udata_value())
+        .unwrap_or(0);
+    Ok(Some((name, entity_type, line_number)))
+}
 
-                    // Get the File
-                    let file = compilation_units
-                        .iter_mut()
-                        .find(|f| f.name == path.as_os_str().to_str().unwrap());
+/// A type DIE as read from a unit, referring to other types by their offsets
+struct TypeEntry {
+    tag: gimli::DwTag,
+    name: Option<String>,
+    size: Option<usize>,
+    target: Option<usize>,                // DW_AT_type
+    members: Vec<(String, usize, usize)>, // (name, type, offset in the struct)
+    template_params: Vec<usize>,
+    enumerators: Vec<(String, i64)>,
+    counts: Vec<usize>, // Number of elements in each dimension of an array
+    discriminant: Option<usize>, // Member DIE holding the discriminant of a Rust enum
+    variants: Vec<(Option<u64>, String, usize, usize)>, // (discriminant value, name, type, offset)
+}
 
-                    // Determine line/column. DWARF line/column is never 0, so we use that
-                    // but other applications may want to display this differently.
-                    let line = row.line().unwrap_or(0);
+/// The types declared in a unit, which are resolved into Types as variables refer to them
+struct TypeTable {
+    entries: HashMap<usize, TypeEntry>,
+    members: HashMap<usize, (usize, usize)>, // Member DIE -> (type, offset in the struct)
+    resolved: HashMap<usize, Type>,
+    rust: bool, // Whether this is a Rust unit
+}
 
-                    if let Some(file) = file {
-                        file.lines.push(Line {
-                            file: file.name.clone(),
-                            number: line.try_into().unwrap(),
-                            address: row.address().try_into().unwrap(),
+impl TypeTable {
+    /// Returns the type whose DIE is at offset.
+    fn get(&mut self, offset: usize) -> Option<Type> {
+        if let Some(resolved) = self.resolved.get(&offset) {
+            return Some(resolved.clone());
+        }
+        let resolved = self.resolve(offset, false)?;
+        self.resolved.insert(offset, resolved.clone());
+        Some(resolved)
+    }
+
+    /// Builds the type whose DIE is at offset, along with the types it's made of. Pointers
+    /// within a pointed-to type are left unresolved, so that self-referential types stay finite.
+    fn resolve(&self, offset: usize, pointed_to: bool) -> Option<Type> {
+        let entry = self.entries.get(&offset)?;
+        let mut resolved = Type {
+            name: self.name_of(Some(offset)),
+            size: entry.size.unwrap_or(0),
+            kind: TypeKind::Base,
+            rust: self.rust,
+        };
+        match entry.tag {
+            gimli::DW_TAG_pointer_type
+            | gimli::DW_TAG_reference_type
+            | gimli::DW_TAG_rvalue_reference_type => {
+                let pointee = match entry.target {
+                    Some(target) if !pointed_to => self.resolve(target, true),
+                    _ => None,
+                };
+                resolved.size = entry.size.unwrap_or(std::mem::size_of::<usize>());
+                resolved.kind = TypeKind::Pointer(pointee.map(Box::new));
+            }
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_union_type | gimli::DW_TAG_class_type => {
+                let member = |(name, member_type, offset): &(String, usize, usize)| {
+                    Some(Member {
+                        name: name.clone(),
+                        offset: *offset,
+                        member_type: self.resolve(*member_type, pointed_to)?,
+                    })
+                };
+                if entry.variants.is_empty() {
+                    let members = entry.members.iter().filter_map(member).collect();
+                    let template_params = entry
+                        .template_params
+                        .iter()
+                        .filter_map(|param| self.resolve(*param, pointed_to))
+                        .collect();
+                    resolved.kind = TypeKind::Struct(members, template_params);
+                } else {
+                    let discriminant = entry
+                        .discriminant
+                        .and_then(|discriminant| self.members.get(&discriminant))
+                        .map(|(discriminant_type, offset)| {
+                            let size = self
+                                .entries
+                                .get(discriminant_type)
+                                .and_then(|entry| entry.size);
+                            (*offset, size.unwrap_or(0))
                         });
+                    let variants = entry
+                        .variants
+                        .iter()
+                        .filter_map(|(value, name, variant_type, offset)| {
+                            Some((*value, member(&(name.clone(), *variant_type, *offset))?))
+                        })
+                        .collect();
+                    resolved.kind = TypeKind::Variants(discriminant, variants);
+                }
+            }
+            gimli::DW_TAG_enumeration_type => {
+                resolved.kind = TypeKind::Enum(entry.enumerators.clone());
+            }
+            gimli::DW_TAG_array_type => {
+                // Arrays of arrays are made from the innermost dimension outwards
+                let mut element = self.resolve(entry.target?, pointed_to)?;
+                for (dimension, count) in entry.counts.iter().enumerate().rev() {
+                    let name = if dimension == 0 {
+                        resolved.name.clone()
+                    } else if self.rust {
+                        format!("[{}; {}]", element.name, count)
+                    } else {
+                        let counts: Vec<String> = entry.counts[dimension..]
+                            .iter()
+                            .map(|count| format!("[{}]", count))
+                            .collect();
+                        format!("{} {}", self.name_of(entry.target), counts.concat())
+                    };
+                    element = Type {
+                        name,
+                        size: element.size * count,
+                        kind: TypeKind::Array(Box::new(element), *count),
+                        rust: self.rust,
+                    };
+                }
+                return Some(element);
+            }
+            gimli::DW_TAG_typedef | gimli::DW_TAG_const_type | gimli::DW_TAG_volatile_type => {
+                if let Some(target) = entry
+                    .target
+                    .and_then(|target| self.resolve(target, pointed_to))
+                {
+                    return Some(Type {
+                        name: resolved.name,
+                        ..target
+                    });
+                }
+            }
+            _ => {}
+        }
+        Some(resolved)
+    }
+
+    /// Returns the name of the type whose DIE is at offset (void if there is none), spelling out
+    /// the names of unnamed types such as pointers and arrays.
+    fn name_of(&self, offset: Option<usize>) -> String {
+        let entry = match offset.and_then(|offset| self.entries.get(&offset)) {
+            Some(entry) => entry,
+            None => return "void".to_string(),
+        };
+        // C needs the keyword with the name of a struct, union or enum; Rust doesn't
+        let keyword = match entry.tag {
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_class_type => "struct",
+            gimli::DW_TAG_union_type => "union",
+            gimli::DW_TAG_enumeration_type => "enum",
+            _ => "",
+        };
+        match (&entry.name, entry.tag) {
+            (Some(name), _) if keyword.is_empty() || self.rust => name.clone(),
+            (Some(name), _) => format!("{} {}", keyword, name),
+            (None, gimli::DW_TAG_pointer_type) => {
+                match entry.target.and_then(|target| self.entries.get(&target)) {
+                    Some(target) if target.tag == gimli::DW_TAG_subroutine_type => {
+                        format!("{} (*)()", self.name_of(target.target))
+                    }
+                    _ => format!("{} *", self.name_of(entry.target)),
+                }
+            }
+            (None, gimli::DW_TAG_reference_type) => format!("{} &", self.name_of(entry.target)),
+            (None, gimli::DW_TAG_const_type) => format!("const {}", self.name_of(entry.target)),
+            (None, gimli::DW_TAG_volatile_type) => {
+                format!("volatile {}", self.name_of(entry.target))
+            }
+            (None, gimli::DW_TAG_array_type) if self.rust => entry
+                .counts
+                .iter()
+                .rev()
+                .fold(self.name_of(entry.target), |element, count| {
+                    format!("[{}; {}]", element, count)
+                }),
+            (None, gimli::DW_TAG_array_type) => {
+                let counts: Vec<String> = entry
+                    .counts
+                    .iter()
+                    .map(|count| format!("[{}]", count))
+                    .collect();
+                format!("{} {}", self.name_of(entry.target), counts.concat())
+            }
+            (None, gimli::DW_TAG_subroutine_type) => {
+                format!("{} ()", self.name_of(entry.target))
+            }
+            (None, _) if !keyword.is_empty() => format!("{} {{...}}", keyword),
+            (None, _) => "<unknown>".to_string(),
+        }
+    }
+}
+
+/// Reads the DIEs describing the types of a unit, along with the members, variants and
+/// enumerators that make them up.
+fn read_types<R: Reader>(
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Result<TypeTable, Error> {
+    let mut types = TypeTable {
+        entries: HashMap::new(),
+        members: HashMap::new(),
+        resolved: HashMap::new(),
+        rust: false,
+    };
+    // Discriminant values of the DW_TAG_variant DIEs seen so far
+    let mut variant_values: HashMap<usize, u64> = HashMap::new();
+    // (depth, offset, tag, name) of the entries enclosing the current one. The name is kept for
+    // namespaces and types, which Rust type names are qualified with.
+    let mut parents: Vec<(isize, usize, gimli::DwTag, Option<String>)> = Vec::new();
+    let mut depth = 0;
+    let mut entries = unit.entries();
+    while let Some((delta_depth, entry)) = entries.next_dfs()? {
+        depth += delta_depth;
+        while parents.last().map_or(false, |parent| parent.0 >= depth) {
+            parents.pop();
+        }
+        let mut name: Option<String> = None;
+        let mut size: Option<usize> = None;
+        let mut target: Option<usize> = None;
+        let mut location = 0;
+        let mut value: Option<gimli::AttributeValue<R>> = None;
+        let mut attrs = entry.attrs();
+        while let Some(attr) = attrs.next()? {
+            match attr.name() {
+                gimli::DW_AT_name => {
+                    if let Ok(DebugValue::Str(attr_name)) = get_attr_value(&attr, unit, dwarf) {
+                        name = Some(attr_name);
+                    }
+                }
+                gimli::DW_AT_byte_size => {
+                    size = attr.value().udata_value().map(|size| size as usize);
+                }
+                // DW_AT_discr refers to the member holding the discriminant
+                gimli::DW_AT_type | gimli::DW_AT_discr => {
+                    if let Ok(DebugValue::Size(offset)) = get_attr_value(&attr, unit, dwarf) {
+                        target = Some(offset);
+                    }
+                }
+                gimli::DW_AT_data_member_location => {
+                    location = match attr.value() {
+                        // Older compilers describe the location as DW_OP_plus_uconst
+                        gimli::AttributeValue::Exprloc(expression) => {
+                            match expression.operations(unit.encoding()).next() {
+                                Ok(Some(gimli::Operation::PlusConstant { value })) => {
+                                    value as usize
+                                }
+                                _ => 0,
+                            }
+                        }
+                        value => value.udata_value().unwrap_or(0) as usize,
+                    };
+                }
+                gimli::DW_AT_const_value
+                | gimli::DW_AT_discr_value
+                | gimli::DW_AT_count
+                | gimli::DW_AT_upper_bound => value = Some(attr.value()),
+                gimli::DW_AT_language => {
+                    if let gimli::AttributeValue::Language(gimli::DW_LANG_Rust) = attr.value() {
+                        types.rust = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let offset = get_section_offset(entry, unit);
+        let tag = entry.tag();
+        let parent = parents.last().map(|parent| (parent.1, parent.2));
+        let scope_name = match tag {
+            gimli::DW_TAG_namespace
+            | gimli::DW_TAG_structure_type
+            | gimli::DW_TAG_union_type
+            | gimli::DW_TAG_class_type
+            | gimli::DW_TAG_enumeration_type => name.clone(),
+            _ => None,
+        };
+        match tag {
+            gimli::DW_TAG_base_type
+            | gimli::DW_TAG_pointer_type
+            | gimli::DW_TAG_reference_type
+            | gimli::DW_TAG_rvalue_reference_type
+            | gimli::DW_TAG_structure_type
+            | gimli::DW_TAG_union_type
+            | gimli::DW_TAG_class_type
+            | gimli::DW_TAG_enumeration_type
+            | gimli::DW_TAG_array_type
+            | gimli::DW_TAG_typedef
+            | gimli::DW_TAG_const_type
+            | gimli::DW_TAG_volatile_type
+            | gimli::DW_TAG_subroutine_type => {
+                // Rust types are named relative to the namespaces they're declared in
+                if let (Some(type_name), Some(_)) = (&name, &scope_name) {
+                    if types.rust {
+                        let mut path: Vec<&str> = parents
+                            .iter()
+                            .filter_map(|parent| parent.3.as_deref())
+                            .collect();
+                        path.push(type_name);
+                        name = Some(path.join("::"));
+                    }
+                }
+                types.entries.insert(
+                    offset,
+                    TypeEntry {
+                        tag,
+                        name,
+                        size,
+                        target,
+                        members: Vec::new(),
+                        template_params: Vec::new(),
+                        enumerators: Vec::new(),
+                        counts: Vec::new(),
+                        discriminant: None,
+                        variants: Vec::new(),
+                    },
+                );
+            }
+            gimli::DW_TAG_member => {
+                let member_type = match target {
+                    Some(member_type) => member_type,
+                    None => continue,
+                };
+                types.members.insert(offset, (member_type, location));
+                let name = name.unwrap_or_default();
+                match parent {
+                    Some((variant, gimli::DW_TAG_variant)) if parents.len() >= 3 => {
+                        // The member of a variant holds its data. The enum is the parent of the
+                        // variant part the variant is in.
+                        let value = variant_values.get(&variant).cloned();
+                        let enum_offset = parents[parents.len() - 3].1;
+                        if let Some(enum_entry) = types.entries.get_mut(&enum_offset) {
+                            enum_entry
+                                .variants
+                                .push((value, name, member_type, location));
+                        }
+                    }
+                    Some((parent, _)) => {
+                        if let Some(parent) = types.entries.get_mut(&parent) {
+                            parent.members.push((name, member_type, location));
+                        }
+                    }
+                    None => {}
+                }
+            }
+            gimli::DW_TAG_variant_part => {
+                if let (Some((parent, _)), Some(discriminant)) = (parent, target) {
+                    if let Some(parent) = types.entries.get_mut(&parent) {
+                        parent.discriminant = Some(discriminant);
+                    }
+                }
+            }
+            gimli::DW_TAG_variant => {
+                if let Some(value) = value.and_then(|value| value.udata_value()) {
+                    variant_values.insert(offset, value);
+                }
+            }
+            gimli::DW_TAG_template_type_parameter => {
+                if let (Some((parent, _)), Some(param)) = (parent, target) {
+                    if let Some(parent) = types.entries.get_mut(&parent) {
+                        parent.template_params.push(param);
+                    }
+                }
+            }
+            gimli::DW_TAG_enumerator => {
+                let enum_value = match value {
+                    Some(gimli::AttributeValue::Sdata(enum_value)) => Some(enum_value),
+                    Some(enum_value) => enum_value.udata_value().map(|value| value as i64),
+                    None => None,
+                };
+                if let (Some((parent, _)), Some(enum_value)) = (parent, enum_value) {
+                    if let Some(parent) = types.entries.get_mut(&parent) {
+                        parent
+                            .enumerators
+                            .push((name.unwrap_or_default(), enum_value));
                     }
                 }
             }
+            gimli::DW_TAG_subrange_type => {
+                // DW_AT_count, or else DW_AT_upper_bound (the last index)
+                let count = match (entry.attr_value(gimli::DW_AT_count)?, value) {
+                    (Some(count), _) => count.udata_value(),
+                    (None, Some(upper_bound)) => upper_bound.udata_value().map(|bound| bound + 1),
+                    (None, None) => None,
+                };
+                if let Some((parent, _)) = parent {
+                    if let Some(parent) = types.entries.get_mut(&parent) {
+                        parent.counts.push(count.unwrap_or(0) as usize);
+                    }
+                }
+            }
+            _ => {}
         }
+        parents.push((depth, offset, tag, scope_name));
+    }
+    Ok(types)
+}
+
+fn get_section_offset<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+) -> usize {
+    match entry.offset().to_unit_section_offset(unit) {
+        UnitSectionOffset::DebugInfoOffset(offset) => offset.0,
+        UnitSectionOffset::DebugTypesOffset(offset) => offset.0,
     }
-    Ok(compilation_units)
 }
 
 #[derive(Debug, Clone)]
@@ -254,33 +1747,27 @@ pub enum DebugValue {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
-    GimliError(gimli::Error),
-    Addr2lineError(addr2line::gimli::Error),
-    ObjectError(String),
-    IoError,
+    Gimli(gimli::Error),
+    Object(String),
+    SplitDwarfNotFound(String), // Name of the missing .dwo file
+    Io,
 }
 
 impl From<gimli::Error> for Error {
     fn from(err: gimli::Error) -> Self {
-        Error::GimliError(err)
-    }
-}
-
-impl From<addr2line::gimli::Error> for Error {
-    fn from(err: addr2line::gimli::Error) -> Self {
-        Error::Addr2lineError(err)
+        Error::Gimli(err)
     }
 }
 
 impl From<io::Error> for Error {
     fn from(_: io::Error) -> Self {
-        Error::IoError
+        Error::Io
     }
 }
 
 impl From<std::fmt::Error> for Error {
     fn from(_: std::fmt::Error) -> Self {
-        Error::IoError
+        Error::Io
     }
 }
 
@@ -291,25 +1778,289 @@ impl<'input, Endian> Reader for gimli::EndianSlice<'input, Endian> where
 
 trait Reader: gimli::Reader<Offset = usize> + Send + Sync {}
 
-fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) -> Option<Location> {
-    if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
-        let encoding = unit.encoding();
-        let mut pc = data.0.clone();
-        if pc.len() > 0 {
-            if let Ok(op) = gimli::Operation::parse(&mut pc, encoding) {
-                match op {
-                    gimli::Operation::FrameOffset { offset } => {
-                        return Some(Location::FramePointerOffset(offset.try_into().unwrap()));
-                    }
-                    gimli::Operation::Address { address } => {
-                        return Some(Location::Address(address.try_into().unwrap()));
+// DW_OP_GNU_addr_index, the pre-standard DW_OP_addrx
+const DW_OP_GNU_ADDR_INDEX: u8 = 0xfb;
+
+fn get_location<R: Reader>(
+    attr: &gimli::Attribute<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<Location> {
+    match attr.value() {
+        gimli::AttributeValue::Exprloc(ref data) => {
+            let encoding = unit.encoding();
+            let mut pc = data.0.clone();
+            // gimli doesn't know the GNU extension DWARF 4 split units use for DW_OP_addrx
+            if pc.len() > 0 && pc.read_u8().ok()? == DW_OP_GNU_ADDR_INDEX {
+                let index = gimli::DebugAddrIndex(pc.read_uleb128().ok()? as usize);
+                if pc.len() == 0 {
+                    let address = dwarf.address(unit, index).ok()?;
+                    return Some(Location::Address(address.try_into().unwrap()));
+                }
+            }
+            let mut pc = data.0.clone();
+            if pc.len() > 0 {
+                if let Ok(op) = gimli::Operation::parse(&mut pc, encoding) {
+                    // Keep the simple forms emitted by unoptimized builds readable
+                    if pc.len() == 0 {
+                        match op {
+                            gimli::Operation::FrameOffset { offset } => {
+                                return Some(Location::FramePointerOffset(
+                                    offset.try_into().unwrap(),
+                                ));
+                            }
+                            gimli::Operation::Address { address } => {
+                                return Some(Location::Address(address.try_into().unwrap()));
+                            }
+                            gimli::Operation::AddressIndex { index } => {
+                                let address = dwarf.address(unit, index).ok()?;
+                                return Some(Location::Address(address.try_into().unwrap()));
+                            }
+                            _ => {}
+                        }
                     }
-                    _ => {}
                 }
             }
+            Some(Location::Expression(read_expression(&data.0, unit)?))
+        }
+        gimli::AttributeValue::LocationListsRef(_)
+        | gimli::AttributeValue::DebugLocListsIndex(_) => {
+            let offset = dwarf.attr_locations_offset(unit, attr.value()).ok()??;
+            let mut entries = Vec::new();
+            let mut locations = dwarf.locations(unit, offset).ok()?;
+            while let Some(entry) = locations.next().ok()? {
+                entries.push((
+                    entry.range.begin.try_into().unwrap(),
+                    entry.range.end.try_into().unwrap(),
+                    read_expression(&entry.data.0, unit)?,
+                ));
+            }
+            Some(Location::List(entries))
+        }
+        _ => None,
+    }
+}
+
+/// Reads the DWARF expression in data, along with the base types its typed operations refer to.
+fn read_expression<R: Reader>(data: &R, unit: &gimli::Unit<R>) -> Option<Expression> {
+    let mut base_types: Vec<(usize, gimli::ValueType)> = Vec::new();
+    // gimli converts signed integers to floating point as if they were unsigned, so the types of
+    // such conversions are left out (making the value unavailable rather than wrong)
+    let mut unsupported = Vec::new();
+    // Whether the last operation left a signed integer on the stack
+    let mut signed = false;
+    let mut pc = data.clone();
+    while !pc.is_empty() {
+        let (base_type, convert) = match gimli::Operation::parse(&mut pc, unit.encoding()) {
+            Ok(gimli::Operation::Convert { base_type }) => (base_type, true),
+            Ok(gimli::Operation::Deref { base_type, .. })
+            | Ok(gimli::Operation::RegisterOffset { base_type, .. })
+            | Ok(gimli::Operation::TypedLiteral { base_type, .. })
+            | Ok(gimli::Operation::Reinterpret { base_type }) => (base_type, false),
+            Ok(_) => {
+                signed = false;
+                continue;
+            }
+            // Evaluation stops at the same place
+            Err(_) => break,
+        };
+        // Offset 0 stands for the generic type
+        let value_type = if base_type.0 == 0 {
+            Some(gimli::ValueType::Generic)
+        } else if let Some((_, value_type)) =
+            base_types.iter().find(|(offset, _)| *offset == base_type.0)
+        {
+            Some(*value_type)
+        } else {
+            let value_type = unit
+                .entry(base_type)
+                .and_then(|entry| gimli::ValueType::from_entry(&entry))
+                .unwrap_or(None);
+            if let Some(value_type) = value_type {
+                base_types.push((base_type.0, value_type));
+            }
+            value_type
+        };
+        use gimli::ValueType::*;
+        if convert && signed && matches!(value_type, Some(F32) | Some(F64)) {
+            unsupported.push(base_type.0);
+        }
+        signed = matches!(value_type, Some(I8) | Some(I16) | Some(I32) | Some(I64));
+    }
+    base_types.retain(|(offset, _)| !unsupported.contains(offset));
+    Some(Expression {
+        encoding: unit.encoding(),
+        bytes: data.to_slice().ok()?.to_vec(),
+        base_types,
+    })
+}
+
+/// Adds delta (a wrapping offset) to the operand of every DW_OP_addr in a DWARF expression, for
+/// programs loaded somewhere other than their link-time address.
+pub fn relocate_expression(encoding: gimli::Encoding, bytes: &mut [u8], delta: usize) {
+    let mut operands = Vec::new();
+    let mut pc = gimli::EndianSlice::new(&bytes[..], gimli::RunTimeEndian::default());
+    while !pc.is_empty() {
+        let offset = bytes.len() - pc.len();
+        match gimli::Operation::parse(&mut pc, encoding) {
+            Ok(gimli::Operation::Address { .. }) => operands.push(offset + 1),
+            Ok(_) => {}
+            // Anything after an operation gimli can't parse is left alone
+            Err(_) => break,
         }
     }
-    None
+    let size = encoding.address_size as usize;
+    for offset in operands {
+        let operand = &mut bytes[offset..offset + size];
+        let mut address: u64 = 0;
+        for (i, byte) in operand.iter().enumerate() {
+            address |= (*byte as u64) << (8 * i);
+        }
+        let address = address.wrapping_add(delta as u64);
+        for (i, byte) in operand.iter_mut().enumerate() {
+            *byte = (address >> (8 * i)) as u8;
+        }
+    }
+}
+
+/// Evaluates a DWARF expression against the state of a stopped frame. Returns the pieces making up
+/// the object, or None if the expression needs something we can't provide (e.g. TLS, or an entry
+/// value the caller didn't record) or reads an unavailable register.
+pub fn evaluate_expression(
+    expression: &Expression,
+    state: &dyn FrameState,
+) -> Option<Vec<(Piece, Option<usize>)>> {
+    let encoding = expression.encoding;
+    let bytes = gimli::EndianSlice::new(&expression.bytes, gimli::RunTimeEndian::default());
+    let mut evaluation = gimli::Expression(bytes).evaluation(encoding);
+    let mut result = evaluation.evaluate().ok()?;
+    loop {
+        result = match result {
+            gimli::EvaluationResult::Complete => break,
+            gimli::EvaluationResult::RequiresMemory {
+                address,
+                size,
+                base_type,
+                ..
+            } => {
+                let mut value: u64 = 0;
+                let memory = state.read_memory(address.try_into().unwrap(), size as usize)?;
+                for (i, byte) in memory.iter().enumerate() {
+                    value |= (*byte as u64) << (8 * i);
+                }
+                evaluation
+                    .resume_with_memory(typed_value(expression, base_type, value)?)
+                    .ok()?
+            }
+            gimli::EvaluationResult::RequiresRegister {
+                register,
+                base_type,
+            } => {
+                let value = state.register(register.0)?;
+                evaluation
+                    .resume_with_register(typed_value(expression, base_type, value)?)
+                    .ok()?
+            }
+            gimli::EvaluationResult::RequiresBaseType(base_type) => {
+                let value = typed_value(expression, base_type, 0)?;
+                evaluation.resume_with_base_type(value.value_type()).ok()?
+            }
+            // Optimized code describes parameters whose registers have been reused by the values
+            // the registers held on entry
+            gimli::EvaluationResult::RequiresEntryValue(entry_expression) => {
+                let mut pc = entry_expression.0;
+                let value = match gimli::Operation::parse(&mut pc, encoding) {
+                    Ok(gimli::Operation::Register { register }) if pc.is_empty() => {
+                        state.entry_value(register.0)?
+                    }
+                    _ => return None,
+                };
+                evaluation
+                    .resume_with_entry_value(gimli::Value::Generic(value))
+                    .ok()?
+            }
+            gimli::EvaluationResult::RequiresFrameBase => evaluation
+                .resume_with_frame_base(state.frame_base()? as u64)
+                .ok()?,
+            gimli::EvaluationResult::RequiresCallFrameCfa => evaluation
+                .resume_with_call_frame_cfa(state.cfa()? as u64)
+                .ok()?,
+            gimli::EvaluationResult::RequiresRelocatedAddress(address) => {
+                evaluation.resume_with_relocated_address(address).ok()?
+            }
+            _ => return None,
+        };
+    }
+
+    let mut pieces = Vec::new();
+    for piece in evaluation.result() {
+        let size = piece.size_in_bits.map(|bits| (bits / 8) as usize);
+        let location = match piece.location {
+            gimli::Location::Empty => Piece::OptimizedOut,
+            gimli::Location::Register { register } => Piece::Register(register.0),
+            gimli::Location::Address { address } => Piece::Memory(address.try_into().unwrap()),
+            gimli::Location::Value { value } => Piece::Value(match value {
+                gimli::Value::F32(value) => value.to_bits().into(),
+                gimli::Value::F64(value) => value.to_bits(),
+                value => value.to_u64(!0).ok()?,
+            }),
+            gimli::Location::Bytes { value } => Piece::Bytes(value.slice().to_vec()),
+            gimli::Location::ImplicitPointer { .. } => Piece::OptimizedOut,
+        };
+        pieces.push((location, size));
+    }
+    Some(pieces)
+}
+
+/// Makes a value of the base type at offset base_type (in expression's unit) out of the raw
+/// contents of a register or memory.
+fn typed_value(
+    expression: &Expression,
+    base_type: UnitOffset<usize>,
+    bits: u64,
+) -> Option<gimli::Value> {
+    if base_type.0 == 0 {
+        return Some(gimli::Value::Generic(bits));
+    }
+    let (_, value_type) = expression
+        .base_types
+        .iter()
+        .find(|(offset, _)| *offset == base_type.0)?;
+    Some(match value_type {
+        gimli::ValueType::F32 => gimli::Value::F32(f32::from_bits(bits as u32)),
+        gimli::ValueType::F64 => gimli::Value::F64(f64::from_bits(bits)),
+        value_type => gimli::Value::from_u64(*value_type, bits).ok()?,
+    })
+}
+
+/// Computes the canonical frame address for pc using the call frame information in .eh_frame.
+/// Also returns the address the caller's rbp was saved at, if the frame has saved it yet.
+pub fn find_cfa(
+    eh_frame: &[u8],
+    eh_frame_address: u64,
+    pc: usize,
+    state: &dyn FrameState,
+) -> Option<(usize, Option<usize>)> {
+    use gimli::UnwindSection;
+
+    let eh_frame = gimli::EhFrame::new(eh_frame, gimli::RunTimeEndian::default());
+    let bases = gimli::BaseAddresses::default().set_eh_frame(eh_frame_address);
+    let mut ctx = gimli::UninitializedUnwindContext::new();
+    let row = eh_frame
+        .unwind_info_for_address(&bases, &mut ctx, pc as u64, gimli::EhFrame::cie_from_offset)
+        .ok()?;
+    let cfa = match row.cfa() {
+        gimli::CfaRule::RegisterAndOffset { register, offset } => {
+            (state.register(register.0)? as i64 + offset) as usize
+        }
+        gimli::CfaRule::Expression(_) => return None,
+    };
+    // rbp is the only callee-saved register the unwinder needs
+    let saved_rbp = match row.register(gimli::Register(6)) {
+        gimli::RegisterRule::Offset(offset) => Some((cfa as i64 + offset) as usize),
+        _ => None,
+    };
+    Some((cfa, saved_rbp))
 }
 
 // based on dwarf_dump.rs
@@ -327,16 +2078,12 @@ fn get_attr_value<R: Reader>(
             dump_exprloc(w, unit.encoding(), data)?;
             Ok(DebugValue::Str(w.to_string()))
         }
-        gimli::AttributeValue::UnitRef(offset) => {
-            match offset.to_unit_section_offset(unit) {
-                UnitSectionOffset::DebugInfoOffset(goff) => {
-                    Ok(DebugValue::Size(goff.0))
-                }
-                UnitSectionOffset::DebugTypesOffset(goff) => {
-                    Ok(DebugValue::Size(goff.0))
-                }
-            }
-        }
+        gimli::AttributeValue::UnitRef(offset) => match offset.to_unit_section_offset(unit) {
+            UnitSectionOffset::DebugInfoOffset(goff) => Ok(DebugValue::Size(goff.0)),
+            UnitSectionOffset::DebugTypesOffset(goff) => Ok(DebugValue::Size(goff.0)),
+        },
+        // References to other units
+        gimli::AttributeValue::DebugInfoRef(offset) => Ok(DebugValue::Size(offset.0)),
         gimli::AttributeValue::DebugStrRef(offset) => {
             if let Ok(s) = dwarf.debug_str.get_str(offset) {
                 Ok(DebugValue::Str(format!("{}", s.to_string_lossy()?)))
@@ -344,8 +2091,17 @@ fn get_attr_value<R: Reader>(
                 Ok(DebugValue::Str(format!("<.debug_str+0x{:08x}>", offset.0)))
             }
         }
+        // DWARF 5 forms that index into .debug_str_offsets or refer to .debug_line_str
+        gimli::AttributeValue::DebugStrOffsetsIndex(_)
+        | gimli::AttributeValue::DebugLineStrRef(_) => {
+            let s = dwarf.attr_string(unit, value)?;
+            Ok(DebugValue::Str(format!("{}", s.to_string_lossy()?)))
+        }
         gimli::AttributeValue::Sdata(data) => Ok(DebugValue::Int(data)),
         gimli::AttributeValue::Addr(data) => Ok(DebugValue::Uint(data)),
+        gimli::AttributeValue::DebugAddrIndex(index) => {
+            Ok(DebugValue::Uint(dwarf.address(unit, index)?))
+        }
         gimli::AttributeValue::Udata(data) => Ok(DebugValue::Uint(data)),
 
         gimli::AttributeValue::String(s) => {
@@ -356,9 +2112,7 @@ fn get_attr_value<R: Reader>(
             dump_file_index(w, value, unit, dwarf)?;
             Ok(DebugValue::Str(w.to_string()))
         }
-        _ => {
-            Ok(DebugValue::NoVal)
-        }
+        _ => Ok(DebugValue::NoVal),
     }
 }
 
@@ -612,3 +2366,78 @@ fn dump_op<R: Reader, W: Write>(
     };
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32s(values: &[u32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn looks_up_units_in_cu_index() {
+        // DWARF 5: version, padding, 3 columns, 2 units, 4 slots
+        let mut data = vec![5, 0, 0, 0];
+        data.extend(u32s(&[3, 2, 4]));
+        for signature in &[0u64, 0x1111, 0, 0x2222] {
+            data.extend(&signature.to_le_bytes());
+        }
+        data.extend(u32s(&[0, 1, 0, 2]));
+        // .debug_info, .debug_abbrev and .debug_rnglists
+        data.extend(u32s(&[1, 3, 8]));
+        data.extend(u32s(&[0, 0, 0, 0x40, 0x20, 0x10])); // Offsets
+        data.extend(u32s(&[0x40, 0x20, 0x10, 0x50, 0x30, 0x18])); // Sizes
+        let endian = gimli::RunTimeEndian::Little;
+        assert_eq!(
+            read_cu_index(&data, endian, 0x2222).unwrap(),
+            Some(vec![
+                (gimli::SectionId::DebugInfo, (0x40, 0x50)),
+                (gimli::SectionId::DebugAbbrev, (0x20, 0x30)),
+                (gimli::SectionId::DebugRngLists, (0x10, 0x18)),
+            ])
+        );
+        assert_eq!(read_cu_index(&data, endian, 0x3333).unwrap(), None);
+    }
+
+    #[test]
+    fn reads_gnu_cu_index() {
+        // Version 2: .debug_info, .debug_loc and a column the format doesn't define
+        let mut data = u32s(&[2, 3, 1, 1]);
+        data.extend(&0x1111u64.to_le_bytes());
+        data.extend(u32s(&[1, 1, 5, 8, 0x10, 0x20, 0x30, 0x40, 0x50, 0x60]));
+        assert_eq!(
+            read_cu_index(&data, gimli::RunTimeEndian::Little, 0x1111).unwrap(),
+            Some(vec![
+                (gimli::SectionId::DebugInfo, (0x10, 0x40)),
+                (gimli::SectionId::DebugLoc, (0x20, 0x50)),
+            ])
+        );
+        // Neither version
+        let data = u32s(&[4, 0, 0, 0]);
+        assert!(read_cu_index(&data, gimli::RunTimeEndian::Little, 0x1111).is_err());
+    }
+
+    #[test]
+    fn reads_names_from_debug_names() {
+        let endian = gimli::RunTimeEndian::Little;
+        let debug_str = gimli::DebugStr::new(b"\0main\0counter\0", endian);
+        // Two units, no hash table, two names and two abbreviations
+        let mut table = vec![5, 0, 0, 0];
+        table.extend(u32s(&[2, 0, 0, 0, 2, 13, 0]));
+        // Unit offsets, name offsets in .debug_str and entry offsets
+        table.extend(u32s(&[0, 0x40, 1, 6, 0, 3]));
+        // DW_TAG_subprogram and DW_TAG_variable, each with a DW_IDX_compile_unit as data1
+        table.extend(&[1, 0x2e, 1, 0x0b, 0, 0, 2, 0x34, 1, 0x0b, 0, 0, 0]);
+        table.extend(&[1, 0, 0, 2, 1, 0]); // Entries
+        let mut data = u32s(&[table.len() as u32]);
+        data.extend(table);
+        let index = read_debug_names(&data, endian, &debug_str).unwrap();
+        assert_eq!(index.units, vec![0, 0x40]);
+        assert_eq!(index.functions, vec![("main".to_string(), 0)]);
+        assert_eq!(index.variables, vec![("counter".to_string(), 0x40)]);
+    }
+}
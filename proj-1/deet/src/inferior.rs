@@ -1,12 +1,19 @@
-use crate::dwarf_data::{DwarfData, Line};
+use crate::dwarf_data::{DwarfData, FrameState, Function, Line, Piece, Type, TypeKind, Variable};
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use nix::pty::openpty;
 use nix::sys::ptrace;
 use nix::sys::signal;
+use nix::sys::termios;
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
 use nix::unistd::Pid;
 use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io;
 use std::mem::size_of;
+use std::os::unix::io::FromRawFd;
 use std::os::unix::process::CommandExt;
-use std::process::{Child, Command};
+use std::process::{Command, Stdio};
 
 pub enum Status {
     /// Indicates inferior stopped. Contains the signal that stopped the process, as well as the
@@ -19,70 +26,756 @@ pub enum Status {
     /// Indicates the inferior exited due to a signal. Contains the signal that killed the
     /// process.
     Signaled(signal::Signal),
+
+    /// Indicates inferior stopped on entry to a syscall. Contains the syscall number.
+    SyscallEntry(u64),
+
+    /// Indicates inferior stopped on return from a syscall. Contains the syscall number and its
+    /// return value.
+    SyscallExit(u64, i64),
 }
 
 pub struct Inferior {
-    child: Child,
+    /// The process being debugged. This starts out as the process we spawned, but restarting
+    /// from a checkpoint switches to a copy of the checkpoint.
+    pid: Pid,
+    breakpoints_mapping: HashMap<usize, u8>,
+    /// Whether the last syscall stop was a syscall entry (so the next one will be its exit)
+    in_syscall: bool,
+    /// Saved copies of the process, by checkpoint number (None once deleted)
+    checkpoints: Vec<Option<Checkpoint>>,
+    /// Master side of the pseudo-terminal the output goes to, if captured (see
+    /// Launch::capture_output)
+    output: Option<File>,
+}
+
+/// A suspended copy of the inferior, forked from it when the checkpoint was made
+pub struct Checkpoint {
+    pub pid: Pid,
+    pub rip: usize,
+    /// The breakpoints installed in the copy's memory
     breakpoints_mapping: HashMap<usize, u8>,
 }
 
+/// A frame of a backtrace
+pub struct Frame {
+    pub address: usize,
+    /// Name of the function, if known
+    pub function: Option<String>,
+    /// Offset of address into the function, for functions known only from the symbol table
+    pub offset: Option<usize>,
+    pub line: Option<Line>,
+    /// Whether this is an inlined call within the next (outer) frame
+    pub inlined: bool,
+    /// Shared library containing address, if any
+    pub library: Option<String>,
+}
+
+/// The details shown by `info frame`
+pub struct FrameInfo {
+    pub cfa: usize,
+    pub rip: usize,
+    pub function: Option<String>,
+    pub line: Option<Line>,
+    pub saved_rip: usize,
+    pub caller_cfa: usize,
+    pub caller_function: Option<String>,
+    pub caller_line: Option<Line>,
+    pub rbp: usize,
+}
+
+/// The registers of a stopped inferior, along with the frame addresses derived from them.
+pub struct StoppedFrame<'a> {
+    inferior: &'a Inferior,
+    regs: libc::user_regs_struct,
+    fpregs: Option<libc::user_fpregs_struct>,
+    cfa: Option<usize>,
+    frame_base: Option<usize>,
+    entry_values: Vec<(u16, u64)>, // Registers' values on entry to the function, from the caller
+}
+
+impl FrameState for StoppedFrame<'_> {
+    fn register(&self, register: u16) -> Option<u64> {
+        let regs = &self.regs;
+        // See the x86-64 System V ABI, figure 3.36 (DWARF Register Number Mapping)
+        Some(match register {
+            0 => regs.rax,
+            1 => regs.rdx,
+            2 => regs.rcx,
+            3 => regs.rbx,
+            4 => regs.rsi,
+            5 => regs.rdi,
+            6 => regs.rbp,
+            7 => regs.rsp,
+            8 => regs.r8,
+            9 => regs.r9,
+            10 => regs.r10,
+            11 => regs.r11,
+            12 => regs.r12,
+            13 => regs.r13,
+            14 => regs.r14,
+            15 => regs.r15,
+            16 => regs.rip,
+            17..=32 => {
+                // Only the low 64 bits of the xmm registers, which is where scalar floating
+                // point values live
+                let xmm = &self.fpregs.as_ref()?.xmm_space;
+                let index = 4 * (register - 17) as usize;
+                xmm[index] as u64 | (xmm[index + 1] as u64) << 32
+            }
+            _ => return None,
+        })
+    }
+
+    fn read_memory(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        self.inferior.read_memory(addr, len).ok()
+    }
+
+    fn frame_base(&self) -> Option<usize> {
+        self.frame_base
+    }
+
+    fn cfa(&self) -> Option<usize> {
+        self.cfa
+    }
+
+    fn entry_value(&self, register: u16) -> Option<u64> {
+        self.entry_values
+            .iter()
+            .find(|(number, _)| *number == register)
+            .map(|(_, value)| *value)
+    }
+}
+
+/// Works out the DW_AT_frame_base of func in frame (the canonical frame address if func doesn't
+/// say).
+fn evaluate_frame_base(func: Option<&Function>, frame: &StoppedFrame) -> Option<usize> {
+    let location = match func.and_then(|func| func.frame_base.as_ref()) {
+        Some(location) => location,
+        None => return frame.cfa,
+    };
+    match location.evaluate(frame.regs.rip as usize, frame).first() {
+        Some((Piece::Memory(addr), _)) => Some(*addr),
+        Some((Piece::Register(register), _)) => {
+            frame.register(*register).map(|value| value as usize)
+        }
+        Some((Piece::Value(value), _)) => Some(*value as usize),
+        _ => None,
+    }
+}
+
+/// si_code of the SIGTRAP raised by an int3 instruction
+const SI_KERNEL: i32 = 0x80;
+
+/// clone(2) syscall number, used to fork checkpoints
+const SYS_CLONE: u64 = 56;
+
+/// The syscall instruction
+const SYSCALL_INSTRUCTION: [u8; 2] = [0x0f, 0x05];
+
+/// How to start an inferior: its arguments, and the environment it runs in.
+#[derive(Clone, Default)]
+pub struct Launch {
+    pub args: Vec<String>,
+    /// File to read stdin from
+    pub stdin: Option<String>,
+    /// Files to write stdout and stderr to, and whether to append to them
+    pub stdout: Option<(String, bool)>,
+    pub stderr: Option<(String, bool)>,
+    /// Send stderr wherever stdout goes (2>&1)
+    pub stderr_to_stdout: bool,
+    /// The whole environment, if not inherited from deet
+    pub environment: Option<Vec<(OsString, OsString)>>,
+    pub cwd: Option<String>,
+    /// Terminal for whichever of stdin, stdout and stderr aren't redirected
+    pub tty: Option<String>,
+    /// Send output that isn't redirected (and has no tty) to a pseudo-terminal of our own,
+    /// rather than to our stdout, so that it can be told apart from ours
+    pub capture_output: bool,
+}
+
+impl Launch {
+    /// Parses the arguments of `run`, taking out shell-style redirections of the standard
+    /// streams: `< in`, `> out`, `>> out`, `2> err`, `2>> err` and `2>&1`. The file name may
+    /// be attached to the operator (`>out`).
+    pub fn from_args(words: &[String]) -> Result<Launch, String> {
+        let mut launch = Launch::default();
+        let mut words = words.iter();
+        while let Some(word) = words.next() {
+            if word == "2>&1" {
+                launch.stderr_to_stdout = true;
+                continue;
+            }
+            // Longer operators first, so that >> isn't taken for >
+            let op = match ["2>>", "2>", ">>", ">", "<"]
+                .iter()
+                .find(|op| word.starts_with(*op))
+            {
+                Some(op) => *op,
+                None => {
+                    launch.args.push(word.clone());
+                    continue;
+                }
+            };
+            let path = if word.len() > op.len() {
+                word[op.len()..].to_string()
+            } else {
+                match words.next() {
+                    Some(path) => path.clone(),
+                    None => return Err(format!("Missing file name after {}", op)),
+                }
+            };
+            match op {
+                "<" => launch.stdin = Some(path),
+                ">" | ">>" => launch.stdout = Some((path, op == ">>")),
+                _ => {
+                    launch.stderr = Some((path, op == "2>>"));
+                    launch.stderr_to_stdout = false;
+                }
+            }
+        }
+        Ok(launch)
+    }
+
+    /// Opens an output file for a redirection.
+    fn open_output(path: &str, append: bool) -> io::Result<File> {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path, err)))
+    }
+
+    /// Opens a pseudo-terminal that passes output through untouched (no \r added to line
+    /// endings). Returns its (master, slave) sides.
+    fn open_pty() -> Result<(File, File), nix::Error> {
+        let pty = openpty(None, None)?;
+        let (master, slave) =
+            unsafe { (File::from_raw_fd(pty.master), File::from_raw_fd(pty.slave)) };
+        for fd in &[pty.master, pty.slave] {
+            fcntl(*fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+        }
+        let mut termios = termios::tcgetattr(pty.slave)?;
+        termios::cfmakeraw(&mut termios);
+        termios::tcsetattr(pty.slave, termios::SetArg::TCSANOW, &termios)?;
+        Ok((master, slave))
+    }
+
+    /// Hooks the standard streams of command up to the redirections and terminal. Returns the
+    /// master side of the pseudo-terminal the output goes to, if it's being captured.
+    fn redirect(&self, command: &mut Command) -> io::Result<Option<File>> {
+        let tty = match &self.tty {
+            Some(path) => Some(
+                OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(path)
+                    .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path, err)))?,
+            ),
+            None => None,
+        };
+        // A terminal rather than a pipe, so that the output is line-buffered as usual
+        let (output, pty) = if self.capture_output && tty.is_none() {
+            let (master, slave) =
+                Launch::open_pty().map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+            (Some(master), Some(slave))
+        } else {
+            (None, None)
+        };
+        // Where output that isn't redirected goes, if not to our own stdout
+        let sink = tty.as_ref().or(pty.as_ref());
+        let stdin = match &self.stdin {
+            Some(path) => Some(
+                File::open(path)
+                    .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path, err)))?,
+            ),
+            None => tty.as_ref().map(File::try_clone).transpose()?,
+        };
+        let stdout = match &self.stdout {
+            Some((path, append)) => Some(Launch::open_output(path, *append)?),
+            None => sink.map(File::try_clone).transpose()?,
+        };
+        let stderr = match &self.stderr {
+            Some((path, append)) => Some(Launch::open_output(path, *append)?),
+            None if self.stderr_to_stdout => match &stdout {
+                Some(stdout) => Some(stdout.try_clone()?),
+                // Our own stdout, which may not be where our stderr goes
+                None => match unsafe { libc::dup(libc::STDOUT_FILENO) } {
+                    -1 => return Err(io::Error::last_os_error()),
+                    fd => Some(unsafe { File::from_raw_fd(fd) }),
+                },
+            },
+            None => sink.map(File::try_clone).transpose()?,
+        };
+        if let Some(stdin) = stdin {
+            command.stdin(Stdio::from(stdin));
+        }
+        if let Some(stdout) = stdout {
+            command.stdout(Stdio::from(stdout));
+        }
+        if let Some(stderr) = stderr {
+            command.stderr(Stdio::from(stderr));
+        }
+        Ok(output)
+    }
+}
+
+/// personality(2) flag disabling address space randomization (from <sys/personality.h>)
+const ADDR_NO_RANDOMIZE: std::os::raw::c_ulong = 0x0040000;
+
 /// This function calls ptrace with PTRACE_TRACEME to enable debugging on a process. You should use
 /// pre_exec with Command to call this in the child process.
 fn child_traceme() -> Result<(), std::io::Error> {
+    // Like gdb, turn off address space randomization, so that shared libraries (and their
+    // breakpoints) land at the same addresses on every run
+    unsafe { libc::personality(ADDR_NO_RANDOMIZE) };
     ptrace::traceme().or(Err(std::io::Error::new(
         std::io::ErrorKind::Other,
         "ptrace TRACEME failed",
     )))
 }
 
+/// nix doesn't wrap PTRACE_GETFPREGS, so call it directly. Returns None on failure.
+fn getfpregs(pid: Pid) -> Option<libc::user_fpregs_struct> {
+    let mut fpregs: libc::user_fpregs_struct = unsafe { std::mem::zeroed() };
+    let ret = unsafe {
+        libc::ptrace(
+            libc::PTRACE_GETFPREGS,
+            pid.as_raw(),
+            std::ptr::null_mut::<libc::c_void>(),
+            &mut fpregs as *mut libc::user_fpregs_struct as *mut libc::c_void,
+        )
+    };
+    if ret < 0 {
+        None
+    } else {
+        Some(fpregs)
+    }
+}
+
+/// Like getfpregs, for PTRACE_SETFPREGS.
+fn setfpregs(pid: Pid, fpregs: &libc::user_fpregs_struct) -> Result<(), nix::Error> {
+    let ret = unsafe {
+        libc::ptrace(
+            libc::PTRACE_SETFPREGS,
+            pid.as_raw(),
+            std::ptr::null_mut::<libc::c_void>(),
+            fpregs as *const libc::user_fpregs_struct as *mut libc::c_void,
+        )
+    };
+    nix::errno::Errno::result(ret).map(drop)
+}
+
 fn align_addr_to_word(addr: usize) -> usize {
     addr & (-(size_of::<usize>() as isize) as usize)
 }
 
+/// Most elements of an array, Vec or string that are shown, like gdb's `set print elements`
+const MAX_ELEMENTS: usize = 200;
+
+/// Formats the raw bytes of a value according to its type. Memory the value points to (such as
+/// the contents of a Rust String or Vec) is read with read_memory.
+pub fn format_value(
+    entity_type: &Type,
+    bytes: &[u8],
+    read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+) -> String {
+    if entity_type.rust {
+        if let Some(value) = format_rust_value(entity_type, bytes, read_memory) {
+            return value;
+        }
+    }
+    match &entity_type.kind {
+        TypeKind::Base => format_base_value(entity_type, bytes),
+        TypeKind::Pointer(pointee) => {
+            let addr = read_uint(bytes) as usize;
+            // Show C strings along with their address
+            match pointee {
+                Some(pointee)
+                    if !entity_type.rust && pointee.size == 1 && pointee.name.contains("char") =>
+                {
+                    match read_c_string(addr, read_memory) {
+                        Some(text) => format!("{:#x} {}", addr, text),
+                        None => format!("{:#x}", addr),
+                    }
+                }
+                _ => format!("{:#x}", addr),
+            }
+        }
+        TypeKind::Struct(..) => format_struct(&entity_type.name, entity_type, bytes, read_memory),
+        TypeKind::Array(element, count) => {
+            let elements = (0..*count.min(&MAX_ELEMENTS))
+                .map(|index| {
+                    let element_bytes = member_bytes(bytes, index * element.size, element.size);
+                    format_value(element, element_bytes, read_memory)
+                })
+                .collect();
+            format_elements(elements, *count, entity_type.rust)
+        }
+        TypeKind::Enum(enumerators) => {
+            let value = read_int(bytes);
+            match enumerators
+                .iter()
+                .find(|(_, enum_value)| *enum_value == value)
+            {
+                Some((name, _)) => name.clone(),
+                None => value.to_string(),
+            }
+        }
+        TypeKind::Variants(discriminant, variants) => {
+            let value =
+                discriminant.map(|(offset, size)| read_uint(member_bytes(bytes, offset, size)));
+            // The variant without a discriminant value is used for any other value
+            let variant = variants
+                .iter()
+                .find(|(variant_value, _)| variant_value.is_some() && *variant_value == value)
+                .or_else(|| {
+                    variants
+                        .iter()
+                        .find(|(variant_value, _)| variant_value.is_none())
+                });
+            match variant {
+                Some((_, member)) => {
+                    let variant_bytes = member_bytes(bytes, member.offset, member.member_type.size);
+                    format_struct(
+                        &member.name,
+                        &member.member_type,
+                        variant_bytes,
+                        read_memory,
+                    )
+                }
+                None => format!("<invalid discriminant {}>", value.unwrap_or(0)),
+            }
+        }
+    }
+}
+
+/// Formats a struct (or a variant of a Rust enum, which is a struct of its own) as
+/// `{x = 1, y = 2}` in C, or `Point {x: 1, y: 2}`, `Some(1)` or `(1, 2)` in Rust.
+fn format_struct(
+    name: &str,
+    entity_type: &Type,
+    bytes: &[u8],
+    read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+) -> String {
+    let members = match &entity_type.kind {
+        TypeKind::Struct(members, _) => members,
+        _ => return format_value(entity_type, bytes, read_memory),
+    };
+    let values: Vec<(&str, String)> = members
+        .iter()
+        .map(|member| {
+            let member_bytes = member_bytes(bytes, member.offset, member.member_type.size);
+            let value = format_value(&member.member_type, member_bytes, read_memory);
+            (member.name.as_str(), value)
+        })
+        .collect();
+    if !entity_type.rust {
+        let values: Vec<String> = values
+            .iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect();
+        return format!("{{{}}}", values.join(", "));
+    }
+    // The members of tuples and tuple structs are named __0, __1 and so on
+    if values.is_empty() {
+        name.to_string()
+    } else if values.iter().all(|(name, _)| name.starts_with("__")) {
+        let values: Vec<&str> = values.iter().map(|(_, value)| value.as_str()).collect();
+        if name.starts_with('(') {
+            format!("({})", values.join(", "))
+        } else {
+            format!("{}({})", name, values.join(", "))
+        }
+    } else {
+        let values: Vec<String> = values
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect();
+        format!("{} {{{}}}", name, values.join(", "))
+    }
+}
+
+/// Formats the Rust standard library types whose contents live elsewhere in memory: String,
+/// Vec, string slices and slices. Returns None for any other type.
+fn format_rust_value(
+    entity_type: &Type,
+    bytes: &[u8],
+    read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+) -> Option<String> {
+    let name = entity_type.name.as_str();
+    let (data, len, element) = if name == "alloc::string::String" {
+        let vec = entity_type.member("vec")?;
+        let vec_bytes = member_bytes(bytes, vec.offset, vec.member_type.size);
+        let (data, len, _) = vec_contents(&vec.member_type, vec_bytes)?;
+        return Some(format_str(data, len, read_memory));
+    } else if name.starts_with("alloc::vec::Vec<") {
+        vec_contents(entity_type, bytes)?
+    } else if name == "&str"
+        || name == "&mut str"
+        || name.starts_with("&[")
+        || name.starts_with("&mut [")
+    {
+        let data_ptr = entity_type.member("data_ptr")?;
+        let length = entity_type.member("length")?;
+        let data = read_uint(member_bytes(
+            bytes,
+            data_ptr.offset,
+            data_ptr.member_type.size,
+        ));
+        let len = read_uint(member_bytes(bytes, length.offset, length.member_type.size));
+        if name.ends_with("str") {
+            return Some(format_str(data as usize, len as usize, read_memory));
+        }
+        match &data_ptr.member_type.kind {
+            TypeKind::Pointer(Some(element)) => (data as usize, len as usize, &**element),
+            _ => return None,
+        }
+    } else {
+        return None;
+    };
+    let mut elements = Vec::new();
+    for index in 0..len.min(MAX_ELEMENTS) {
+        let element_bytes = match read_memory(data + index * element.size, element.size) {
+            Some(element_bytes) => element_bytes,
+            None => return Some(format!("<error reading memory at {:#x}>", data)),
+        };
+        elements.push(format_value(element, &element_bytes, read_memory));
+    }
+    let elements = format_elements(elements, len, true);
+    Some(if name.starts_with("alloc::vec::Vec<") {
+        format!("vec!{}", elements)
+    } else {
+        elements
+    })
+}
+
+/// Returns the address of the data of a Rust Vec, its length and the type of its elements.
+fn vec_contents<'a>(vec: &'a Type, bytes: &[u8]) -> Option<(usize, usize, &'a Type)> {
+    let element = match &vec.kind {
+        TypeKind::Struct(_, template_params) => template_params.first()?,
+        _ => return None,
+    };
+    let len = vec.member("len")?;
+    let len = read_uint(member_bytes(bytes, len.offset, len.member_type.size));
+    // The pointer to the data is buried in the buffer type, whose layout varies between
+    // versions of Rust
+    let buf = vec.member("buf")?;
+    let data = buf.offset + find_pointer(&buf.member_type)?;
+    let data = read_uint(member_bytes(bytes, data, size_of::<usize>()));
+    Some((data as usize, len as usize, element))
+}
+
+/// Returns the offset of the first pointer in a type, looking through the members of structs.
+fn find_pointer(entity_type: &Type) -> Option<usize> {
+    match &entity_type.kind {
+        TypeKind::Pointer(_) => Some(0),
+        TypeKind::Struct(members, _) => members
+            .iter()
+            .find_map(|member| Some(member.offset + find_pointer(&member.member_type)?)),
+        _ => None,
+    }
+}
+
+/// Formats len bytes of UTF-8 at data as a string literal.
+fn format_str(
+    data: usize,
+    len: usize,
+    read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+) -> String {
+    match read_memory(data, len.min(MAX_ELEMENTS)) {
+        Some(text) if len > MAX_ELEMENTS => format!("{:?}...", String::from_utf8_lossy(&text)),
+        Some(text) => format!("{:?}", String::from_utf8_lossy(&text)),
+        None => format!("<error reading memory at {:#x}>", data),
+    }
+}
+
+/// Reads the NUL-terminated string at addr and formats it as a string literal.
+fn read_c_string(
+    addr: usize,
+    read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+) -> Option<String> {
+    let mut text = Vec::new();
+    while text.len() < MAX_ELEMENTS {
+        let byte = *read_memory(addr + text.len(), 1)?.first()?;
+        if byte == 0 {
+            return Some(format!("{:?}", String::from_utf8_lossy(&text)));
+        }
+        text.push(byte);
+    }
+    Some(format!("{:?}...", String::from_utf8_lossy(&text)))
+}
+
+/// Formats the elements of an array as `[1, 2, 3]` in Rust or `{1, 2, 3}` in C, noting when
+/// there were more than could be shown.
+fn format_elements(elements: Vec<String>, count: usize, rust: bool) -> String {
+    let mut elements = elements.join(", ");
+    if count > MAX_ELEMENTS {
+        elements.push_str("...");
+    }
+    if rust {
+        format!("[{}]", elements)
+    } else {
+        format!("{{{}}}", elements)
+    }
+}
+
+/// Returns the bytes of the size-byte member at offset (fewer if the value is cut short).
+fn member_bytes(bytes: &[u8], offset: usize, size: usize) -> &[u8] {
+    let start = offset.min(bytes.len());
+    &bytes[start..(offset + size).min(bytes.len())]
+}
+
+/// Reads a little-endian unsigned integer of up to 8 bytes.
+fn read_uint(bytes: &[u8]) -> u64 {
+    let mut raw: u64 = 0;
+    for (i, byte) in bytes.iter().take(size_of::<u64>()).enumerate() {
+        raw |= (*byte as u64) << (8 * i);
+    }
+    raw
+}
+
+/// Reads a little-endian signed integer of up to 8 bytes.
+fn read_int(bytes: &[u8]) -> i64 {
+    let bits = 8 * bytes.len().min(size_of::<u64>());
+    if bits == 0 {
+        return 0;
+    }
+    let shift = 64 - bits;
+    ((read_uint(bytes) << shift) as i64) >> shift
+}
+
+/// Formats the raw bytes of a value of a base type.
+fn format_base_value(entity_type: &Type, bytes: &[u8]) -> String {
+    let raw = read_uint(bytes);
+    let bits = 8 * bytes.len().min(size_of::<u64>());
+    let name = entity_type.name.as_str();
+    // Rust's unsigned types are u8 to u128 and usize
+    let rust_unsigned =
+        name == "usize" || (name.starts_with('u') && name[1..].parse::<u32>().is_ok());
+    if (name == "float" || name == "f32") && bytes.len() == 4 {
+        format!("{}", f32::from_bits(raw as u32))
+    } else if (name == "double" || name == "f64") && bytes.len() == 8 {
+        format!("{}", f64::from_bits(raw))
+    } else if name == "_Bool" || name == "bool" {
+        format!("{}", raw != 0)
+    } else if name == "char" && bytes.len() == 4 {
+        // A Rust char is a Unicode scalar value
+        match std::char::from_u32(raw as u32) {
+            Some(c) => format!("{} {:?}", raw, c),
+            None => format!("{}", raw),
+        }
+    } else if name.contains("char") && bytes.len() == 1 {
+        format!("{} {:?}", raw as i8, raw as u8 as char)
+    } else if name.contains("unsigned") || rust_unsigned || bits == 0 {
+        format!("{}", raw)
+    } else {
+        // Sign-extend from the width of the type
+        format!("{}", read_int(bytes))
+    }
+}
+
 impl Inferior {
     /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
     /// an error is encountered.
-    pub fn new(target: &str, args: &Vec<String>, breakpoints: &Vec<usize>) -> Option<Inferior> {
-        // TODO: implement me!
+    pub fn new(target: &str, args: &[String], breakpoints: &Vec<usize>) -> Option<Inferior> {
+        let launch = Launch {
+            args: args.to_vec(),
+            ..Default::default()
+        };
+        Inferior::launch(target, &launch, breakpoints).ok()
+    }
+
+    /// Like new, but starts the inferior as described by launch, and says what went wrong.
+    pub fn launch(
+        target: &str,
+        launch: &Launch,
+        breakpoints: &Vec<usize>,
+    ) -> Result<Inferior, io::Error> {
+        let mut command = match &launch.cwd {
+            // Resolve a relative path to the program before changing directory
+            Some(_) if target.contains('/') => Command::new(std::fs::canonicalize(target)?),
+            _ => Command::new(target),
+        };
+        command.args(&launch.args);
+        if let Some(environment) = &launch.environment {
+            command.env_clear().envs(environment.iter().cloned());
+        }
+        if let Some(cwd) = &launch.cwd {
+            command.current_dir(cwd);
+        }
+        let output = launch.redirect(&mut command)?;
         let child;
         unsafe {
-            child = Command::new(target)
-                .args(args)
-                .pre_exec(child_traceme)
-                .spawn()
-                .ok()?;
+            child = command.pre_exec(child_traceme).spawn()?;
         }
 
         let breakpoints_mapping: HashMap<usize, u8> = Default::default();
         let mut inferior = Inferior {
-            child,
+            pid: Pid::from_raw(child.id() as i32),
             breakpoints_mapping,
+            in_syscall: false,
+            checkpoints: Vec::new(),
+            output,
         };
 
-        let status = inferior.wait(None).ok()?;
-        return match status {
+        let to_io_error = |err: nix::Error| io::Error::new(io::ErrorKind::Other, err);
+        let status = inferior.wait(None).map_err(to_io_error)?;
+        match status {
             Status::Stopped(_, _) => {
+                // Report syscall stops as SIGTRAP|0x80, so they can't be confused with breakpoints
+                ptrace::setoptions(inferior.pid(), ptrace::Options::PTRACE_O_TRACESYSGOOD)
+                    .map_err(to_io_error)?;
+                // Breakpoints in shared libraries can't be inserted until the library is mapped
+                // in; cont retries them
                 for addr in breakpoints {
-                    match inferior.write_byte(*addr, 0xcc) {
-                        Ok(orig_byte) => {
-                            inferior.breakpoints_mapping.insert(*addr, orig_byte);
-                        }
-                        Err(_) => return None,
-                    }
+                    let _ = inferior.set_breakpoint(*addr);
                 }
-                Some(inferior)
+                Ok(inferior)
             }
-            _ => None,
-        };
+            _ => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "the program exited before it could be traced",
+            )),
+        }
+    }
+
+    /// Hands over the pseudo-terminal the inferior's output is captured through, if it is.
+    pub fn take_output(&mut self) -> Option<File> {
+        self.output.take()
+    }
+
+    /// Returns whether a breakpoint is installed at addr.
+    pub fn has_breakpoint(&self, addr: usize) -> bool {
+        self.breakpoints_mapping.contains_key(&addr)
+    }
+
+    /// Installs a breakpoint at addr, if there isn't one there already.
+    pub fn set_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        if !self.breakpoints_mapping.contains_key(&addr) {
+            let orig_byte = self.write_byte(addr, 0xcc)?;
+            self.breakpoints_mapping.insert(addr, orig_byte);
+        }
+        Ok(())
+    }
+
+    /// Removes the breakpoint at addr (if any), restoring the original instruction.
+    pub fn remove_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        if let Some(orig_byte) = self.breakpoints_mapping.remove(&addr) {
+            self.write_byte(addr, orig_byte)?;
+        }
+        Ok(())
     }
 
     fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
         let aligned_addr = align_addr_to_word(addr);
         let byte_offset = addr - aligned_addr;
         let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
-        let orig_byte = (word >> 8 * byte_offset) & 0xff;
-        let masked_word = word & !(0xff << 8 * byte_offset);
-        let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
+        let orig_byte = (word >> (8 * byte_offset)) & 0xff;
+        let masked_word = word & !(0xff << (8 * byte_offset));
+        let updated_word = masked_word | ((val as u64) << (8 * byte_offset));
         ptrace::write(
             self.pid(),
             aligned_addr as ptrace::AddressType,
@@ -91,107 +784,732 @@ impl Inferior {
         Ok(orig_byte as u8)
     }
 
-    pub fn print_backtrace(&self, debug_data: &DwarfData) -> Result<(), nix::Error> {
+    /// Reads len bytes of the inferior's memory starting at addr. Breakpoints are hidden: the
+    /// original bytes are returned in their place.
+    pub fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = Vec::with_capacity(len);
+        let mut curr_addr = align_addr_to_word(addr);
+        while curr_addr < addr + len {
+            let word = ptrace::read(self.pid(), curr_addr as ptrace::AddressType)? as u64;
+            for i in 0..size_of::<u64>() {
+                let byte_addr = curr_addr + i;
+                if byte_addr >= addr && byte_addr < addr + len {
+                    match self.breakpoints_mapping.get(&byte_addr) {
+                        Some(orig_byte) => bytes.push(*orig_byte),
+                        None => bytes.push((word >> (8 * i)) as u8),
+                    }
+                }
+            }
+            curr_addr += size_of::<u64>();
+        }
+        Ok(bytes)
+    }
+
+    /// Writes bytes into the inferior's memory starting at addr. Breakpoints stay in place: writes
+    /// to a breakpoint address update the instruction byte that will be restored later.
+    pub fn write_memory(&mut self, addr: usize, bytes: &[u8]) -> Result<(), nix::Error> {
+        for (i, byte) in bytes.iter().enumerate() {
+            match self.breakpoints_mapping.get_mut(&(addr + i)) {
+                Some(orig_byte) => *orig_byte = *byte,
+                None => {
+                    self.write_byte(addr + i, *byte)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_registers(&self) -> Result<libc::user_regs_struct, nix::Error> {
+        ptrace::getregs(self.pid())
+    }
+
+    pub fn set_registers(&mut self, regs: libc::user_regs_struct) -> Result<(), nix::Error> {
+        ptrace::setregs(self.pid(), regs)
+    }
+
+    /// Captures the registers of the stopped inferior so that DWARF locations can be evaluated
+    /// against them. func is the function the inferior is stopped in, if known; its
+    /// DW_AT_frame_base is used to resolve frame-base-relative locations.
+    pub fn stopped_frame(
+        &self,
+        debug_data: &DwarfData,
+        func: Option<&Function>,
+    ) -> Result<StoppedFrame<'_>, nix::Error> {
         let regs = ptrace::getregs(self.pid())?;
-        let mut rip: usize = regs.rip as usize;
-        let mut rbp: usize = regs.rbp as usize;
-        let mut line: Option<Line>;
-        let mut func: Option<String>;
+        let mut frame = StoppedFrame {
+            inferior: self,
+            regs,
+            fpregs: getfpregs(self.pid()),
+            cfa: None,
+            frame_base: None,
+            entry_values: Vec::new(),
+        };
+        // Without unwind information, assume the function keeps a frame pointer: the canonical
+        // frame address then sits just above the saved rbp and the return address
+        frame.cfa = debug_data
+            .get_cfa(regs.rip as usize, &frame)
+            .or(Some(regs.rbp as usize + 2 * size_of::<usize>()));
+        frame.frame_base = evaluate_frame_base(func, &frame);
+        if func.is_some() {
+            frame.entry_values = self.entry_values(debug_data, &frame).unwrap_or_default();
+        }
+        Ok(frame)
+    }
+
+    /// Works out the values the registers held on entry to the function of frame, from what its
+    /// caller's call site says was passed in them. Returns None if the call site isn't known.
+    fn entry_values(
+        &self,
+        debug_data: &DwarfData,
+        frame: &StoppedFrame,
+    ) -> Option<Vec<(u16, u64)>> {
+        let rip = frame.regs.rip as usize;
+        let rbp = frame.regs.rbp as usize;
+        // The caller's frame, as far as the frame chain goes (like in backtraces, other
+        // registers aren't restored)
+        let (cfa, saved_rbp) = debug_data
+            .unwind(rip, frame)
+            .unwrap_or((rbp + 2 * size_of::<usize>(), Some(rbp)));
+        let mut regs = frame.regs;
+        regs.rip = ptrace::read(self.pid(), (cfa - 8) as ptrace::AddressType).ok()? as u64;
+        if let Some(saved_rbp) = saved_rbp {
+            regs.rbp = ptrace::read(self.pid(), saved_rbp as ptrace::AddressType).ok()? as u64;
+        }
+        regs.rsp = cfa as u64;
+        let return_address = regs.rip as usize;
+        let call_site = debug_data.get_call_site(return_address)?;
+        let mut caller = StoppedFrame {
+            inferior: self,
+            regs,
+            fpregs: None,
+            cfa: None,
+            frame_base: None,
+            entry_values: Vec::new(),
+        };
+        // The return address may already belong to the next function (after a noreturn call),
+        // so look up the call instead
+        caller.cfa = debug_data
+            .get_cfa(return_address - 1, &caller)
+            .or(Some(regs.rbp as usize + 2 * size_of::<usize>()));
+        let caller_func = debug_data.get_function_data_from_addr(return_address - 1);
+        caller.frame_base = evaluate_frame_base(caller_func, &caller);
+        Some(call_site.register_values(&caller))
+    }
+
+    /// Reads the value of a variable in the given frame and formats it for display.
+    pub fn read_variable(
+        &self,
+        var: &Variable,
+        frame: &StoppedFrame,
+    ) -> Result<String, nix::Error> {
+        Ok(match self.read_variable_bytes(var, frame)? {
+            Ok(bytes) => format_value(&var.entity_type, &bytes, &|addr, len| {
+                self.read_memory(addr, len).ok()
+            }),
+            Err(placeholder) => placeholder.to_string(),
+        })
+    }
+
+    /// Reads the raw bytes of var's value. If the value can't be read, a placeholder to show
+    /// instead (such as "<optimized out>") is returned as the inner error.
+    pub fn read_variable_bytes(
+        &self,
+        var: &Variable,
+        frame: &StoppedFrame,
+    ) -> Result<Result<Vec<u8>, &'static str>, nix::Error> {
+        let pieces = var.location.evaluate(frame.regs.rip as usize, frame);
+        if pieces.is_empty() {
+            return Ok(Err("<optimized out>"));
+        }
+        let mut bytes = Vec::new();
+        for (piece, size) in pieces {
+            let size = size.unwrap_or(var.entity_type.size);
+            match piece {
+                Piece::Memory(addr) => bytes.extend(self.read_memory(addr, size)?),
+                Piece::Register(register) => match frame.register(register) {
+                    Some(value) => bytes.extend(value.to_le_bytes().iter().take(size)),
+                    None => return Ok(Err("<not available>")),
+                },
+                Piece::Value(value) => bytes.extend(value.to_le_bytes().iter().take(size)),
+                Piece::Bytes(data) => bytes.extend(data.into_iter().take(size)),
+                Piece::OptimizedOut => return Ok(Err("<optimized out>")),
+            }
+        }
+        bytes.truncate(var.entity_type.size);
+        Ok(Ok(bytes))
+    }
+
+    /// Returns the names and current values of the formal parameters (if params is true) or the
+    /// local variables of the function the inferior is currently stopped in. Returns None if
+    /// there's no debug info for the function.
+    pub fn variables(
+        &self,
+        debug_data: &DwarfData,
+        params: bool,
+    ) -> Result<Option<Vec<(String, String)>>, nix::Error> {
+        let regs = ptrace::getregs(self.pid())?;
+        let func = match debug_data.get_function_data_from_addr(regs.rip as usize) {
+            Some(func) => func,
+            None => return Ok(None),
+        };
+        let frame = self.stopped_frame(debug_data, Some(func))?;
+        let mut variables = Vec::new();
+        for var in func
+            .variables_in_scope(regs.rip as usize)
+            .filter(|var| var.is_parameter == params)
+        {
+            variables.push((var.name.clone(), self.read_variable(var, &frame)?));
+        }
+        Ok(Some(variables))
+    }
+
+    /// Describes the current stack frame: its canonical frame address, the current and saved
+    /// instruction pointers, and the calling frame.
+    pub fn frame_info(&self, debug_data: &DwarfData) -> Result<FrameInfo, nix::Error> {
+        let frame = self.stopped_frame(debug_data, None)?;
+        let rip = frame.regs.rip as usize;
+        let rbp = frame.regs.rbp as usize;
+        let cfa = frame.cfa.unwrap();
+        // The return address is always pushed just below the canonical frame address
+        let saved_rip = ptrace::read(self.pid(), (cfa - 8) as ptrace::AddressType)? as usize;
+        let saved_rbp = ptrace::read(self.pid(), rbp as ptrace::AddressType)? as usize;
+        Ok(FrameInfo {
+            cfa,
+            rip,
+            function: debug_data.get_function_from_addr(rip),
+            line: debug_data.get_line_from_addr(rip),
+            saved_rip,
+            caller_cfa: saved_rbp + 2 * size_of::<usize>(),
+            caller_function: debug_data.get_function_from_addr(saved_rip),
+            caller_line: debug_data.get_line_from_addr(saved_rip),
+            rbp,
+        })
+    }
+
+    /// Pops the current frame, as if its function had returned right away: rip, rsp and rbp are
+    /// restored from the frame chain, and the return value (if any) is put in xmm0 if float is
+    /// set, or else rax. Other callee-saved registers aren't restored. Returns the address
+    /// execution will resume at.
+    pub fn pop_frame(
+        &mut self,
+        debug_data: &DwarfData,
+        value: Option<u64>,
+        float: bool,
+    ) -> Result<usize, nix::Error> {
+        let frame = self.stopped_frame(debug_data, None)?;
+        let mut regs = frame.regs;
+        // Without unwind information, assume the frame pointer chain is intact
+        let (cfa, saved_rbp) = match debug_data.unwind(regs.rip as usize, &frame) {
+            Some((cfa, saved_rbp)) => (cfa, saved_rbp),
+            None => (
+                regs.rbp as usize + 2 * size_of::<usize>(),
+                Some(regs.rbp as usize),
+            ),
+        };
+        regs.rip = ptrace::read(self.pid(), (cfa - 8) as ptrace::AddressType)? as u64;
+        if let Some(saved_rbp) = saved_rbp {
+            regs.rbp = ptrace::read(self.pid(), saved_rbp as ptrace::AddressType)? as u64;
+        }
+        regs.rsp = cfa as u64;
+        // Don't let the kernel restart a syscall we were stopped in
+        regs.orig_rax = u64::MAX;
+        match value {
+            Some(value) if float => {
+                let mut fpregs =
+                    getfpregs(self.pid()).ok_or(nix::Error::Sys(nix::errno::Errno::EIO))?;
+                fpregs.xmm_space[0] = value as u32;
+                fpregs.xmm_space[1] = (value >> 32) as u32;
+                setfpregs(self.pid(), &fpregs)?;
+            }
+            Some(value) => regs.rax = value,
+            None => {}
+        }
+        self.set_registers(regs)?;
+        Ok(regs.rip as usize)
+    }
+
+    /// Unwinds the stack, returning its frames innermost first (inlined calls get frames of
+    /// their own).
+    pub fn backtrace(&self, debug_data: &DwarfData) -> Result<Vec<Frame>, nix::Error> {
+        let mut backtrace = Vec::new();
+        let mut frame = self.stopped_frame(debug_data, None)?;
+        // In outer frames rip is a return address, which may already belong to the next
+        // function (after a noreturn call); unwind information is looked up for the call instead
+        let mut call_offset = 0;
         loop {
-            line = debug_data.get_line_from_addr(rip);
-            func = debug_data.get_function_from_addr(rip);
-            if line.is_some() && func.is_some() {
-                let line_unwrap = line.unwrap();
-                let func_unwrap = func.unwrap();
-                println!(
-                    "{} ({}:{})",
-                    func_unwrap, line_unwrap.file, line_unwrap.number
-                );
-
-                if func_unwrap == "main" {
-                    break;
+            let rip = frame.regs.rip as usize;
+            let rbp = frame.regs.rbp as usize;
+            let library = debug_data
+                .get_library_name_from_addr(rip)
+                .map(|library| library.to_string());
+            // Functions inlined at rip show up as separate frames, innermost first
+            let frames = debug_data.get_inline_frames_from_addr(rip);
+            let func = if frames.is_empty() {
+                // No debug info: fall back to the symbol table
+                let (name, offset) = match debug_data.get_symbol_from_addr(rip) {
+                    Some((name, offset)) => (Some(name.to_string()), Some(offset)),
+                    None => (None, None),
+                };
+                backtrace.push(Frame {
+                    address: rip,
+                    function: name.clone(),
+                    offset,
+                    line: None,
+                    inlined: false,
+                    library,
+                });
+                match name {
+                    Some(name) => name,
+                    None => break,
                 }
-
-                rip = ptrace::read(self.pid(), (rbp + 8) as ptrace::AddressType)? as usize;
-                rbp = ptrace::read(self.pid(), rbp as ptrace::AddressType)? as usize;
             } else {
-                println!("??? [rip -> {:#x} | rbp -> {:#x}]", rip, rbp);
+                let count = frames.len();
+                for (i, (func, line)) in frames.into_iter().enumerate() {
+                    backtrace.push(Frame {
+                        address: rip,
+                        function: Some(func),
+                        offset: None,
+                        line,
+                        inlined: i + 1 < count,
+                        library: library.clone(),
+                    });
+                }
+                backtrace.last().unwrap().function.clone().unwrap()
+            };
+
+            if func == "main" {
+                break;
+            }
+
+            // Without unwind information, assume the frame pointer chain is intact
+            let (cfa, saved_rbp) = match debug_data.unwind(rip - call_offset, &frame) {
+                Some((cfa, saved_rbp)) => (cfa, saved_rbp),
+                None => (rbp + 2 * size_of::<usize>(), Some(rbp)),
+            };
+            frame.regs.rip = ptrace::read(self.pid(), (cfa - 8) as ptrace::AddressType)? as u64;
+            if let Some(saved_rbp) = saved_rbp {
+                frame.regs.rbp = ptrace::read(self.pid(), saved_rbp as ptrace::AddressType)? as u64;
+            }
+            frame.regs.rsp = cfa as u64;
+            call_offset = 1;
+            if frame.regs.rip == 0 {
                 break;
             }
         }
-        Ok(())
+        Ok(backtrace)
     }
 
     pub fn kill(&mut self) -> Result<Status, nix::Error> {
-        return match ptrace::kill(self.pid()) {
+        match ptrace::kill(self.pid()) {
             Ok(_) => self.wait(None), // reap the killed process
             Err(err) => Err(err),
-        };
+        }
     }
 
-    pub fn cont(&mut self, breakpoints: &Vec<usize>) -> Result<Status, nix::Error> {
+    /// Continues the inferior until it stops. If syscalls is true, the inferior also stops on
+    /// every syscall entry and exit.
+    pub fn cont(&mut self, breakpoints: &Vec<usize>, syscalls: bool) -> Result<Status, nix::Error> {
         for addr in breakpoints {
-            match self.write_byte(*addr, 0xcc) {
-                Ok(orig_byte) => {
-                    self.breakpoints_mapping.insert(*addr, orig_byte);
-                }
-                Err(err) => return Err(err),
+            // Ignore breakpoints in shared libraries that haven't been loaded (yet)
+            let _ = self.set_breakpoint(*addr);
+        }
+        match self.resume(None, syscalls)? {
+            Some(status) => Ok(status),
+            None => self.wait(None),
+        }
+    }
+
+    /// Continues the inferior (delivering signal, if given) without waiting for it to stop. If the
+    /// inferior is sitting on a breakpoint, the original instruction is stepped over first; should
+    /// the inferior stop for some other reason while doing so, that status is returned and the
+    /// inferior is left stopped. If syscalls is true, the inferior is resumed with PTRACE_SYSCALL.
+    pub fn resume(
+        &mut self,
+        signal: Option<signal::Signal>,
+        syscalls: bool,
+    ) -> Result<Option<Status>, nix::Error> {
+        let mut signal = signal;
+        let rip = ptrace::getregs(self.pid())?.rip as usize;
+        if self.breakpoints_mapping.contains_key(&rip) {
+            match self.step(signal)? {
+                Status::Stopped(signal::Signal::SIGTRAP, _) => signal = None,
+                status => return Ok(Some(status)),
             }
         }
+        if syscalls {
+            ptrace::syscall(self.pid(), signal)?;
+        } else {
+            // The exit of a syscall we're stopped in won't be reported
+            self.in_syscall = false;
+            ptrace::cont(self.pid(), signal)?;
+        }
+        Ok(None)
+    }
 
-        let mut regs = ptrace::getregs(self.pid())?;
-        let rip: usize = regs.rip as usize;
-        let target_rip = rip - 1;
+    /// Executes a single instruction (delivering signal, if given). A breakpoint at the current
+    /// instruction is temporarily removed so that the original instruction runs.
+    pub fn step(&mut self, signal: Option<signal::Signal>) -> Result<Status, nix::Error> {
+        self.in_syscall = false;
+        let rip = ptrace::getregs(self.pid())?.rip as usize;
+        let orig_byte = match self.breakpoints_mapping.get(&rip) {
+            Some(orig_byte) => *orig_byte,
+            None => {
+                ptrace::step(self.pid(), signal)?;
+                return self.wait(None);
+            }
+        };
+        self.write_byte(rip, orig_byte)?;
+        ptrace::step(self.pid(), signal)?;
+        let status = self.wait(None)?;
+        if let Status::Stopped(_, _) = status {
+            self.write_byte(rip, 0xcc)?;
+        }
+        Ok(status)
+    }
 
-        let orig_byte_wrap = self.breakpoints_mapping.get(&target_rip);
-        if orig_byte_wrap.is_some() {
-            let orig_byte = orig_byte_wrap.unwrap().clone();
-            match self.write_byte(target_rip, orig_byte) {
-                Ok(byte) => {
-                    assert_eq!(byte, 0xcc);
-                    regs.rip -= 1;
-                    ptrace::setregs(self.pid(), regs)?;
+    /// Calls the function at addr, following the System V x86-64 calling convention: integer
+    /// arguments go in rdi, rsi, rdx, rcx, r8 and r9, and the bits of floating point arguments in
+    /// the low halves of xmm0-7. The function returns to return_addr, where a temporary
+    /// breakpoint catches it. Breakpoints hit inside the function are ignored.
+    ///
+    /// Returns the contents of rax and the low 64 bits of xmm0 once the function returns, or the
+    /// status of the inferior if it stops for another reason (such as a signal) first. Either
+    /// way, the registers are restored afterwards (unless the inferior has died).
+    pub fn call_function(
+        &mut self,
+        addr: usize,
+        int_args: &[u64],
+        float_args: &[u64],
+        return_addr: usize,
+    ) -> Result<Result<(u64, u64), Status>, nix::Error> {
+        if int_args.len() > 6 || float_args.len() > 8 {
+            return Err(nix::Error::Sys(nix::errno::Errno::E2BIG));
+        }
+        let saved_regs = self.get_registers()?;
+        let saved_fpregs = getfpregs(self.pid()).ok_or(nix::Error::Sys(nix::errno::Errno::EIO))?;
 
-                    ptrace::step(self.pid(), None)?;
-                    match self.wait(None) {
-                        Ok(status) => match status {
-                            Status::Stopped(_, _) => match self.write_byte(target_rip, 0xcc) {
-                                Ok(byte) => {
-                                    assert_eq!(byte, orig_byte);
-                                }
-                                Err(err) => return Err(err),
-                            },
-                            _ => return Ok(status),
-                        },
-                        Err(err) => return Err(err),
-                    }
-                }
-                Err(err) => return Err(err),
-            }
+        let mut regs = saved_regs;
+        // Stay clear of the red zone below rsp, and align the stack so that rsp + 8 is a multiple
+        // of 16 at the function's entry, as if it had been called
+        regs.rsp = ((saved_regs.rsp - 256) & !0xf) - 8;
+        ptrace::write(
+            self.pid(),
+            regs.rsp as ptrace::AddressType,
+            return_addr as *mut std::ffi::c_void,
+        )?;
+        let mut int_registers = [
+            &mut regs.rdi,
+            &mut regs.rsi,
+            &mut regs.rdx,
+            &mut regs.rcx,
+            &mut regs.r8,
+            &mut regs.r9,
+        ];
+        for (register, value) in int_registers.iter_mut().zip(int_args) {
+            **register = *value;
+        }
+        // Variadic functions take the number of vector registers used in al
+        regs.rax = float_args.len() as u64;
+        regs.rip = addr as u64;
+        // Don't let the kernel restart a syscall we were stopped in
+        regs.orig_rax = u64::MAX;
+        let mut fpregs = saved_fpregs;
+        for (i, &bits) in float_args.iter().enumerate() {
+            fpregs.xmm_space[4 * i] = bits as u32;
+            fpregs.xmm_space[4 * i + 1] = (bits >> 32) as u32;
         }
 
-        return match ptrace::cont(self.pid(), None) {
-            Ok(_) => self.wait(None),
-            Err(err) => Err(err),
+        let temporary = !self.breakpoints_mapping.contains_key(&return_addr);
+        self.set_breakpoint(return_addr)?;
+        self.set_registers(regs)?;
+        setfpregs(self.pid(), &fpregs)?;
+        let outcome = loop {
+            let status = match self.resume(None, false)? {
+                Some(status) => status,
+                None => self.wait(None)?,
+            };
+            match status {
+                Status::Stopped(signal::Signal::SIGTRAP, rip)
+                    if rip == return_addr && self.get_registers()?.rsp == regs.rsp + 8 =>
+                {
+                    let fpregs =
+                        getfpregs(self.pid()).ok_or(nix::Error::Sys(nix::errno::Errno::EIO))?;
+                    let xmm0 = fpregs.xmm_space[0] as u64 | (fpregs.xmm_space[1] as u64) << 32;
+                    break Ok((self.get_registers()?.rax, xmm0));
+                }
+                Status::Stopped(signal::Signal::SIGTRAP, rip)
+                    if self.breakpoints_mapping.contains_key(&rip) => {}
+                Status::Exited(_) | Status::Signaled(_) => return Ok(Err(status)),
+                status => break Err(status),
+            }
         };
+        if temporary {
+            self.remove_breakpoint(return_addr)?;
+        }
+        self.set_registers(saved_regs)?;
+        setfpregs(self.pid(), &saved_fpregs)?;
+        Ok(outcome)
     }
 
     /// Returns the pid of this inferior.
     pub fn pid(&self) -> Pid {
-        Pid::from_raw(self.child.id() as i32)
+        self.pid
+    }
+
+    /// Forks the stopped process pid by making it run a clone syscall in place of its current
+    /// instruction. Both processes are then put back exactly as they were, and the pid of the
+    /// copy, which is left stopped (and traced), is returned.
+    fn fork(pid: Pid) -> Result<Pid, nix::Error> {
+        let regs = ptrace::getregs(pid)?;
+        let rip = regs.rip as usize;
+        let orig_word = ptrace::read(pid, rip as ptrace::AddressType)? as u64;
+        let mut syscall_word = orig_word.to_le_bytes();
+        syscall_word[..2].copy_from_slice(&SYSCALL_INSTRUCTION);
+        let syscall_word = u64::from_le_bytes(syscall_word);
+
+        // clone without an exit signal works like fork, but won't send SIGCHLD to the inferior
+        // when we kill the copy. orig_rax = -1 stops the kernel from restarting an interrupted
+        // syscall instead of running ours.
+        let mut clone_regs = regs;
+        clone_regs.rax = SYS_CLONE;
+        clone_regs.orig_rax = u64::MAX;
+        clone_regs.rdi = 0;
+        clone_regs.rsi = 0;
+        clone_regs.rdx = 0;
+        clone_regs.r10 = 0;
+        clone_regs.r8 = 0;
+        ptrace::write(
+            pid,
+            rip as ptrace::AddressType,
+            syscall_word as *mut std::ffi::c_void,
+        )?;
+        ptrace::setregs(pid, clone_regs)?;
+        // Have the copy traced (and stopped) from the start
+        ptrace::setoptions(
+            pid,
+            ptrace::Options::PTRACE_O_TRACESYSGOOD | ptrace::Options::PTRACE_O_TRACECLONE,
+        )?;
+        ptrace::step(pid, None)?;
+        let copy = match waitpid(pid, Some(WaitPidFlag::__WALL))? {
+            WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_CLONE) => {
+                let copy = Pid::from_raw(ptrace::getevent(pid)? as i32);
+                // Finish the syscall
+                ptrace::step(pid, None)?;
+                waitpid(pid, Some(WaitPidFlag::__WALL))?;
+                Ok(copy)
+            }
+            // The clone failed
+            _ => Err(nix::Error::Sys(nix::errno::Errno::from_i32(
+                -(ptrace::getregs(pid)?.rax as i64) as i32,
+            ))),
+        };
+        ptrace::setoptions(pid, ptrace::Options::PTRACE_O_TRACESYSGOOD)?;
+        ptrace::write(
+            pid,
+            rip as ptrace::AddressType,
+            orig_word as *mut std::ffi::c_void,
+        )?;
+        ptrace::setregs(pid, regs)?;
+        let copy = copy?;
+
+        // The copy starts out stopped by SIGSTOP, with the syscall instruction still in place
+        waitpid(copy, Some(WaitPidFlag::__WALL))?;
+        ptrace::setoptions(copy, ptrace::Options::PTRACE_O_TRACESYSGOOD)?;
+        ptrace::write(
+            copy,
+            rip as ptrace::AddressType,
+            orig_word as *mut std::ffi::c_void,
+        )?;
+        ptrace::setregs(copy, regs)?;
+        Ok(copy)
+    }
+
+    /// Saves the current state of the inferior as a new checkpoint, returning its number and the
+    /// pid of the process holding it.
+    pub fn checkpoint(&mut self) -> Result<(usize, Pid), nix::Error> {
+        if self.in_syscall {
+            // The copy would be stuck halfway through the syscall
+            return Err(nix::Error::Sys(nix::errno::Errno::EBUSY));
+        }
+        let pid = Inferior::fork(self.pid)?;
+        self.checkpoints.push(Some(Checkpoint {
+            pid,
+            rip: ptrace::getregs(pid)?.rip as usize,
+            breakpoints_mapping: self.breakpoints_mapping.clone(),
+        }));
+        Ok((self.checkpoints.len() - 1, pid))
+    }
+
+    /// Lists the checkpoints, by number.
+    pub fn checkpoints(&self) -> Vec<(usize, &Checkpoint)> {
+        self.checkpoints
+            .iter()
+            .enumerate()
+            .filter_map(|(i, checkpoint)| Some((i, checkpoint.as_ref()?)))
+            .collect()
+    }
+
+    /// Goes back to the state saved in checkpoint n. The process being debugged is killed and
+    /// replaced by a fresh copy of the checkpoint, so the checkpoint can be restarted again later.
+    /// Returns false if there is no checkpoint n.
+    pub fn restart(&mut self, n: usize) -> Result<bool, nix::Error> {
+        let checkpoint = match self.checkpoints.get(n) {
+            Some(Some(checkpoint)) => checkpoint,
+            _ => return Ok(false),
+        };
+        let saved_breakpoints = checkpoint.breakpoints_mapping.clone();
+        let copy = Inferior::fork(checkpoint.pid)?;
+        signal::kill(self.pid, signal::Signal::SIGKILL)?;
+        waitpid(self.pid, Some(WaitPidFlag::__WALL))?;
+        self.pid = copy;
+        self.in_syscall = false;
+        // Bring the copy's breakpoints up to date with the ones set since the checkpoint
+        for (addr, orig_byte) in &saved_breakpoints {
+            if !self.breakpoints_mapping.contains_key(addr) {
+                self.write_byte(*addr, *orig_byte)?;
+            }
+        }
+        let current: Vec<usize> = self.breakpoints_mapping.keys().cloned().collect();
+        for addr in current {
+            if !saved_breakpoints.contains_key(&addr) {
+                self.write_byte(addr, 0xcc)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Deletes checkpoint n, killing the process holding it. Returns false if there is no
+    /// checkpoint n.
+    pub fn delete_checkpoint(&mut self, n: usize) -> bool {
+        match self
+            .checkpoints
+            .get_mut(n)
+            .and_then(|checkpoint| checkpoint.take())
+        {
+            Some(checkpoint) => {
+                if signal::kill(checkpoint.pid, signal::Signal::SIGKILL).is_ok() {
+                    let _ = waitpid(checkpoint.pid, Some(WaitPidFlag::__WALL));
+                }
+                true
+            }
+            None => false,
+        }
     }
 
     /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
     /// after the waitpid call.
-    pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
-        Ok(match waitpid(self.pid(), options)? {
+    pub fn wait(&mut self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
+        let wait_status = waitpid(self.pid(), options)?;
+        self.decode_status(wait_status)
+    }
+
+    /// Like wait, but returns None instead of blocking if the inferior is still running.
+    pub fn try_wait(&mut self) -> Result<Option<Status>, nix::Error> {
+        match waitpid(self.pid(), Some(WaitPidFlag::WNOHANG))? {
+            WaitStatus::StillAlive => Ok(None),
+            wait_status => Ok(Some(self.decode_status(wait_status)?)),
+        }
+    }
+
+    fn decode_status(&mut self, wait_status: WaitStatus) -> Result<Status, nix::Error> {
+        Ok(match wait_status {
             WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
             WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
             WaitStatus::Stopped(_pid, signal) => {
-                let regs = ptrace::getregs(self.pid())?;
+                let mut regs = ptrace::getregs(self.pid())?;
+                // Executing a breakpoint leaves rip just past the 0xcc. Rewind it so that the
+                // inferior is stopped at the breakpoint address, and the original instruction
+                // runs when it is resumed. (Single steps also raise SIGTRAP, but not from the
+                // kernel's int3 handler.)
+                let prev_rip = regs.rip as usize - 1;
+                if signal == signal::Signal::SIGTRAP
+                    && self.breakpoints_mapping.contains_key(&prev_rip)
+                    && ptrace::getsiginfo(self.pid())?.si_code == SI_KERNEL
+                {
+                    regs.rip = prev_rip as u64;
+                    ptrace::setregs(self.pid(), regs)?;
+                }
                 Status::Stopped(signal, regs.rip as usize)
             }
+            WaitStatus::PtraceSyscall(_pid) => {
+                let regs = ptrace::getregs(self.pid())?;
+                self.in_syscall = !self.in_syscall;
+                if self.in_syscall {
+                    Status::SyscallEntry(regs.orig_rax)
+                } else {
+                    Status::SyscallExit(regs.orig_rax, regs.rax as i64)
+                }
+            }
             other => panic!("waitpid returned unexpected status: {:?}", other),
         })
     }
 }
+
+impl Drop for Inferior {
+    /// Checkpoints are suspended processes of their own, so they must be cleaned up along with
+    /// the inferior.
+    fn drop(&mut self) {
+        for n in 0..self.checkpoints.len() {
+            self.delete_checkpoint(n);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<String> {
+        line.split_whitespace()
+            .map(|word| word.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_plain_arguments() {
+        let launch = Launch::from_args(&words("-v input.txt 2")).unwrap();
+        assert_eq!(launch.args, words("-v input.txt 2"));
+        assert_eq!(launch.stdin, None);
+        assert_eq!(launch.stdout, None);
+        assert_eq!(launch.stderr, None);
+        assert!(!launch.stderr_to_stdout);
+    }
+
+    #[test]
+    fn takes_out_redirections() {
+        let launch = Launch::from_args(&words("a < in.txt b > out.txt 2>> err.txt")).unwrap();
+        assert_eq!(launch.args, words("a b"));
+        assert_eq!(launch.stdin, Some("in.txt".to_string()));
+        assert_eq!(launch.stdout, Some(("out.txt".to_string(), false)));
+        assert_eq!(launch.stderr, Some(("err.txt".to_string(), true)));
+    }
+
+    #[test]
+    fn takes_attached_file_names() {
+        let launch = Launch::from_args(&words("<in >>out 2>err")).unwrap();
+        assert!(launch.args.is_empty());
+        assert_eq!(launch.stdin, Some("in".to_string()));
+        assert_eq!(launch.stdout, Some(("out".to_string(), true)));
+        assert_eq!(launch.stderr, Some(("err".to_string(), false)));
+    }
+
+    #[test]
+    fn merges_stderr_into_stdout() {
+        let launch = Launch::from_args(&words("> log 2>&1")).unwrap();
+        assert_eq!(launch.stdout, Some(("log".to_string(), false)));
+        assert!(launch.stderr_to_stdout);
+        // A later redirection of stderr wins
+        let launch = Launch::from_args(&words("2>&1 2> err")).unwrap();
+        assert!(!launch.stderr_to_stdout);
+        assert_eq!(launch.stderr, Some(("err".to_string(), false)));
+    }
+
+    #[test]
+    fn requires_file_names() {
+        assert_eq!(
+            Launch::from_args(&words("a >")).err(),
+            Some("Missing file name after >".to_string())
+        );
+        assert!(Launch::from_args(&words("<")).is_err());
+    }
+}
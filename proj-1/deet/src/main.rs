@@ -1,24 +1,84 @@
 mod debugger;
 mod debugger_command;
 mod dwarf_data;
+mod gdbserver;
 mod gimli_wrapper;
 mod inferior;
+mod json;
+mod output;
+mod printf;
+mod record;
+mod shared_library;
+mod syscalls;
+mod tui;
 
-use crate::debugger::Debugger;
+use crate::debugger::{Debugger, Interpreter};
 use nix::sys::signal::{signal, SigHandler, Signal};
 use std::env;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
+    let gdbserver = args.len() >= 4 && args[1] == "--gdbserver";
+    // Command scripts to run at startup, given with -x (possibly more than once)
+    let mut scripts = Vec::new();
+    let mut interpreter = Interpreter::Console;
+    let mut tui = false;
+    let mut dump_dwarf = false;
+    let mut target_index = 1;
+    while !gdbserver && args.len() > target_index + 1 {
+        match args[target_index].as_str() {
+            "-x" => {
+                scripts.push(&args[target_index + 1]);
+                target_index += 2;
+            }
+            "--interpreter=console" => {
+                interpreter = Interpreter::Console;
+                target_index += 1;
+            }
+            "--tui" => {
+                tui = true;
+                target_index += 1;
+            }
+            "--dump-dwarf" => {
+                dump_dwarf = true;
+                target_index += 1;
+            }
+            "--interpreter=json" => {
+                interpreter = Interpreter::Json;
+                target_index += 1;
+            }
+            _ => break,
+        }
+    }
+    if args.len() != target_index + 1 && !gdbserver {
+        println!("Usage: {} [--tui] [--dump-dwarf] [--interpreter=console|json] [-x script]... <target program>", args[0]);
+        println!(
+            "       {} --gdbserver [host]:port <target program> [args...]",
+            args[0]
+        );
         std::process::exit(1);
     }
-    let target = &args[1];
 
     // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
     // processes)
     unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
 
-    Debugger::new(target).run();
+    if gdbserver {
+        gdbserver::run(&args[2], &args[3], &args[4..]);
+        return;
+    }
+    let target = &args[target_index];
+    let mut debugger = Debugger::new(target, interpreter);
+    if dump_dwarf {
+        debugger.dump_debug_data();
+    }
+    if tui {
+        debugger.enable_tui();
+    }
+    for script in scripts {
+        if let Err(err) = debugger.source(script) {
+            println!("{}: {}", script, err);
+        }
+    }
+    debugger.run();
 }
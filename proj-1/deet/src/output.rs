@@ -0,0 +1,104 @@
+//! Forwarding of the inferior's output in `--interpreter=json` mode. Output that isn't redirected
+//! is captured through a pseudo-terminal and reported as "output" events, so that it can't get
+//! mixed up with the JSON lines on our stdout.
+
+use crate::json::Json;
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub struct OutputForwarder {
+    pty: File,
+    /// Held by the forwarding thread from when output arrives until it has been printed
+    busy: Arc<Mutex<()>>,
+}
+
+impl OutputForwarder {
+    /// Starts forwarding whatever the inferior writes to the pseudo-terminal whose master side
+    /// is pty. This is done from a thread, so that the inferior never blocks on a full buffer
+    /// while we're waiting for it to stop.
+    pub fn start(pty: File) -> io::Result<OutputForwarder> {
+        let mut reader = pty.try_clone()?;
+        let busy = Arc::new(Mutex::new(()));
+        let thread_busy = busy.clone();
+        thread::spawn(move || {
+            let mut buffer = [0u8; 4096];
+            // Bytes of a UTF-8 character split across reads
+            let mut partial = Vec::new();
+            loop {
+                let mut fds = [PollFd::new(reader.as_raw_fd(), PollFlags::POLLIN)];
+                match poll(&mut fds, -1) {
+                    Ok(_) => {}
+                    Err(nix::Error::Sys(Errno::EINTR)) => continue,
+                    Err(_) => break,
+                }
+                let _busy = thread_busy.lock().unwrap();
+                // Once the inferior (and anything it forked) is gone, reads fail with EIO
+                let count = match reader.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(count) => count,
+                };
+                partial.extend_from_slice(&buffer[..count]);
+                let text = take_text(&mut partial);
+                if !text.is_empty() {
+                    print_output(text);
+                }
+            }
+            if !partial.is_empty() {
+                print_output(String::from_utf8_lossy(&partial).to_string());
+            }
+        });
+        Ok(OutputForwarder { pty, busy })
+    }
+
+    /// Waits until everything the inferior has written so far has been forwarded, so that it's
+    /// reported ahead of whatever we report next.
+    pub fn flush(&self) {
+        loop {
+            let _busy = self.busy.lock().unwrap();
+            // Once the thread is gone, nothing more will be forwarded
+            if unread_bytes(&self.pty) == 0 || Arc::strong_count(&self.busy) == 1 {
+                return;
+            }
+            drop(_busy);
+            thread::yield_now();
+        }
+    }
+}
+
+fn print_output(text: String) {
+    let event = Json::object(vec![
+        ("event", Json::from("output")),
+        ("stream", Json::from("inferior")),
+        ("text", Json::from(text)),
+    ]);
+    println!("{}", event);
+}
+
+/// Takes the text read so far out of bytes, leaving behind the start of a character that hasn't
+/// been read in full.
+fn take_text(bytes: &mut Vec<u8>) -> String {
+    let complete = match std::str::from_utf8(bytes) {
+        Ok(_) => bytes.len(),
+        Err(err) if err.error_len().is_none() => err.valid_up_to(),
+        // Not UTF-8 at all
+        Err(_) => bytes.len(),
+    };
+    let rest = bytes.split_off(complete);
+    let text = String::from_utf8_lossy(bytes).to_string();
+    *bytes = rest;
+    text
+}
+
+/// Returns the number of bytes waiting to be read from the master side of a pseudo-terminal.
+fn unread_bytes(pty: &File) -> usize {
+    let mut count: std::os::raw::c_int = 0;
+    if unsafe { libc::ioctl(pty.as_raw_fd(), libc::FIONREAD, &mut count) } < 0 {
+        return 0;
+    }
+    count as usize
+}
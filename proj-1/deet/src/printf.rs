@@ -0,0 +1,351 @@
+//! Formatting for the `printf` and `dprintf` commands, following C's printf conversions.
+
+/// A value to be formatted
+#[derive(Clone, Copy)]
+pub enum Value {
+    Int(i64),
+    Unsigned(u64),
+    Float(f64),
+}
+
+impl Value {
+    pub fn as_u64(self) -> u64 {
+        match self {
+            Value::Int(value) => value as u64,
+            Value::Unsigned(value) => value,
+            Value::Float(value) => value as u64,
+        }
+    }
+
+    fn as_i64(self) -> i64 {
+        match self {
+            Value::Int(value) => value,
+            Value::Unsigned(value) => value as i64,
+            Value::Float(value) => value as i64,
+        }
+    }
+
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Value::Int(value) => value as f64,
+            Value::Unsigned(value) => value as f64,
+            Value::Float(value) => value,
+        }
+    }
+}
+
+/// Splits the arguments of printf, `"format", arg1, arg2...`, into the (unescaped) format
+/// string and the argument expressions.
+pub fn parse_args(text: &str) -> Result<(String, Vec<String>), String> {
+    let text = text.trim();
+    if !text.starts_with('"') {
+        return Err("Bad format string".to_string());
+    }
+    let mut format = String::new();
+    let mut chars = text[1..].char_indices();
+    let rest = loop {
+        match chars.next() {
+            Some((i, '"')) => break &text[i + 2..],
+            Some((_, '\\')) => match chars.next() {
+                Some((_, 'n')) => format.push('\n'),
+                Some((_, 't')) => format.push('\t'),
+                Some((_, c)) => format.push(c),
+                None => return Err("Bad format string, non-terminated '\"'".to_string()),
+            },
+            Some((_, c)) => format.push(c),
+            None => return Err("Bad format string, non-terminated '\"'".to_string()),
+        }
+    };
+    let rest = rest.trim();
+    if rest.is_empty() {
+        return Ok((format, Vec::new()));
+    }
+    if !rest.starts_with(',') {
+        return Err("Invalid argument syntax".to_string());
+    }
+    Ok((format, split_args(&rest[1..])))
+}
+
+/// Splits comma-separated argument expressions, leaving alone the commas inside the argument
+/// lists of function calls.
+pub fn split_args(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(text[start..i].trim().to_string());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !text[start..].trim().is_empty() || !args.is_empty() {
+        args.push(text[start..].trim().to_string());
+    }
+    args
+}
+
+/// Pads formatted to width, as requested by the - and 0 flags.
+fn pad(formatted: String, width: usize, left_align: bool, zero_pad: bool) -> String {
+    let len = formatted.chars().count();
+    if len >= width {
+        return formatted;
+    }
+    let padding = width - len;
+    if left_align {
+        format!("{}{}", formatted, " ".repeat(padding))
+    } else if zero_pad {
+        // Zeros go after the sign
+        let (sign, digits) = if formatted.starts_with('-') {
+            formatted.split_at(1)
+        } else {
+            formatted.split_at(0)
+        };
+        format!("{}{}{}", sign, "0".repeat(padding), digits)
+    } else {
+        format!("{}{}", " ".repeat(padding), formatted)
+    }
+}
+
+/// Rewrites the exponent of a number formatted by Rust (`1.5e3`) the way C does (`1.5e+03`).
+fn exponent(formatted: String) -> String {
+    let split = match formatted.find(|c| c == 'e' || c == 'E') {
+        Some(split) => split,
+        None => return formatted,
+    };
+    let (mantissa, exponent) = formatted.split_at(split + 1);
+    let (sign, digits) = if exponent.starts_with('-') {
+        ("-", &exponent[1..])
+    } else {
+        ("+", exponent)
+    };
+    format!("{}{}{:0>2}", mantissa, sign, digits)
+}
+
+/// Formats values according to format. read_string reads the NUL-terminated string at an
+/// address, for %s.
+pub fn format(
+    format: &str,
+    values: &[Value],
+    read_string: &dyn Fn(usize) -> Option<String>,
+) -> Result<String, String> {
+    let mut output = String::new();
+    let mut values = values.iter();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            output.push('%');
+            continue;
+        }
+
+        let mut left_align = false;
+        let mut zero_pad = false;
+        while let Some(flag) = chars.peek() {
+            match flag {
+                '-' => left_align = true,
+                '0' => zero_pad = true,
+                '+' | ' ' | '#' => {}
+                _ => break,
+            }
+            chars.next();
+        }
+        let mut width = 0;
+        while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+            width = width * 10 + digit as usize;
+            chars.next();
+        }
+        let mut precision = None;
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            let mut digits = 0;
+            while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                digits = digits * 10 + digit as usize;
+                chars.next();
+            }
+            precision = Some(digits);
+        }
+        // Length modifiers decide how many bits of an integer argument are used
+        let mut bits = 32;
+        while let Some(modifier) = chars.peek() {
+            match modifier {
+                'l' | 'z' | 'j' | 't' => bits = 64,
+                'h' => bits /= 2,
+                _ => break,
+            }
+            chars.next();
+        }
+        let mask = if bits == 64 { !0 } else { (1u64 << bits) - 1 };
+
+        let conversion = chars
+            .next()
+            .ok_or_else(|| "Incomplete format specifier at end of format string".to_string())?;
+        let value = *values
+            .next()
+            .ok_or_else(|| "Bad format string, missing an argument".to_string())?;
+        let formatted = match conversion {
+            'd' | 'i' => {
+                let shift = 64 - bits;
+                format!("{}", (value.as_i64() << shift) >> shift)
+            }
+            'u' => format!("{}", value.as_u64() & mask),
+            'x' => format!("{:x}", value.as_u64() & mask),
+            'X' => format!("{:X}", value.as_u64() & mask),
+            'o' => format!("{:o}", value.as_u64() & mask),
+            'c' => format!("{}", value.as_u64() as u8 as char),
+            'p' => format!("{:#x}", value.as_u64()),
+            'f' | 'F' => format!("{:.*}", precision.unwrap_or(6), value.as_f64()),
+            'e' => exponent(format!("{:.*e}", precision.unwrap_or(6), value.as_f64())),
+            'E' => exponent(format!("{:.*E}", precision.unwrap_or(6), value.as_f64())),
+            'g' | 'G' => format!("{}", value.as_f64()),
+            's' => {
+                let string = read_string(value.as_u64() as usize).ok_or_else(|| {
+                    format!("Cannot access memory at address {:#x}", value.as_u64())
+                })?;
+                match precision {
+                    Some(precision) => string.chars().take(precision).collect(),
+                    None => string,
+                }
+            }
+            other => {
+                return Err(format!(
+                    "Unrecognized format specifier '{}' in printf",
+                    other
+                ))
+            }
+        };
+        output.push_str(&pad(formatted, width, left_align, zero_pad));
+    }
+    if values.next().is_some() {
+        return Err("Wrong number of arguments for specified format-string".to_string());
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_strings(_: usize) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn parses_format_and_arguments() {
+        assert_eq!(
+            parse_args(r#""%d: %s\n", x, name"#),
+            Ok((
+                "%d: %s\n".to_string(),
+                vec!["x".to_string(), "name".to_string()]
+            ))
+        );
+        assert_eq!(
+            parse_args(r#""say \"hi\"\t""#),
+            Ok(("say \"hi\"\t".to_string(), Vec::new()))
+        );
+        assert!(parse_args("x, y").is_err());
+        assert!(parse_args(r#""unterminated"#).is_err());
+        assert!(parse_args(r#""%d" x"#).is_err());
+    }
+
+    #[test]
+    fn splits_arguments_outside_of_calls() {
+        assert_eq!(split_args("a, f(b, c), d"), vec!["a", "f(b, c)", "d"]);
+        assert_eq!(split_args("  "), Vec::<String>::new());
+        assert_eq!(split_args("a,"), vec!["a", ""]);
+    }
+
+    #[test]
+    fn formats_integers() {
+        let values = [
+            Value::Int(-1),
+            Value::Int(-1),
+            Value::Int(255),
+            Value::Int(300),
+        ];
+        assert_eq!(
+            format("%d %u %x %hhd", &values, &no_strings),
+            Ok("-1 4294967295 ff 44".to_string())
+        );
+        let values = [Value::Unsigned(u64::MAX), Value::Int(8), Value::Int(65)];
+        assert_eq!(
+            format("%lu %o %c", &values, &no_strings),
+            Ok("18446744073709551615 10 A".to_string())
+        );
+        assert_eq!(
+            format(
+                "%p %X",
+                &[Value::Int(0x401000), Value::Int(0xbeef)],
+                &no_strings
+            ),
+            Ok("0x401000 BEEF".to_string())
+        );
+    }
+
+    #[test]
+    fn pads_to_width() {
+        let values = [
+            Value::Int(42),
+            Value::Int(42),
+            Value::Int(-42),
+            Value::Int(7),
+        ];
+        assert_eq!(
+            format("[%5d][%-5d][%05d][%1d]", &values, &no_strings),
+            Ok("[   42][42   ][-0042][7]".to_string())
+        );
+    }
+
+    #[test]
+    fn formats_floats() {
+        let values = [
+            Value::Float(1.23456),
+            Value::Float(2.5),
+            Value::Float(1500.0),
+        ];
+        assert_eq!(
+            format("%f %.2f %e", &values, &no_strings),
+            Ok("1.234560 2.50 1.500000e+03".to_string())
+        );
+        assert_eq!(
+            format("%.1E", &[Value::Float(0.00025)], &no_strings),
+            Ok("2.5E-04".to_string())
+        );
+    }
+
+    #[test]
+    fn formats_strings() {
+        let read_string = |addr: usize| {
+            if addr == 0x1000 {
+                Some("hello".to_string())
+            } else {
+                None
+            }
+        };
+        let values = [Value::Int(0x1000), Value::Int(0x1000)];
+        assert_eq!(
+            format("%s|%.3s|%%", &values, &read_string),
+            Ok("hello|hel|%".to_string())
+        );
+        assert_eq!(
+            format("%s", &[Value::Int(0x2000)], &read_string),
+            Err("Cannot access memory at address 0x2000".to_string())
+        );
+    }
+
+    #[test]
+    fn checks_arguments_against_format() {
+        assert!(format("%d %d", &[Value::Int(1)], &no_strings).is_err());
+        assert!(format("%d", &[Value::Int(1), Value::Int(2)], &no_strings).is_err());
+        assert!(format("%q", &[Value::Int(1)], &no_strings).is_err());
+        assert!(format("100%", &[], &no_strings).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! Execution recording for reverse debugging. While recording, the inferior is single-stepped,
+//! and before each instruction runs we log its registers; after it runs, we log the old contents
+//! of any memory it changed. Undoing a step restores both.
+//!
+//! Memory changes are found by comparing every writable mapping against a shadow copy after each
+//! step, which is slow but catches everything, including writes made by syscalls. This limits
+//! recording to small programs.
+
+use nix::unistd::Pid;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::os::unix::fs::FileExt;
+
+const PAGE_SIZE: usize = 4096;
+
+/// The state needed to undo one instruction
+pub struct Step {
+    /// Registers before the instruction ran
+    pub regs: libc::user_regs_struct,
+    /// Memory the instruction changed, as (address, old bytes)
+    writes: Vec<(usize, Vec<u8>)>,
+    /// Call depth before the instruction ran, relative to where recording started
+    pub depth: i64,
+}
+
+pub struct Recording {
+    pid: Pid,
+    /// /proc/pid/mem, for fast access to the inferior's memory
+    mem: fs::File,
+    /// Contents of each writable page as of the last step, by page address
+    shadow: HashMap<usize, Vec<u8>>,
+    steps: Vec<Step>,
+    /// Current call depth, relative to where recording started
+    depth: i64,
+}
+
+/// Returns the (start, end) ranges of the inferior's writable mappings.
+fn writable_mappings(pid: Pid) -> io::Result<Vec<(usize, usize)>> {
+    let maps = fs::read_to_string(format!("/proc/{}/maps", pid))?;
+    let mut mappings = Vec::new();
+    for line in maps.lines() {
+        let mut fields = line.split_whitespace();
+        let range = fields.next().unwrap_or("");
+        let perms = fields.next().unwrap_or("");
+        let name = fields.nth(3).unwrap_or("");
+        // Of the special mappings ([vvar] and friends), only the heap and stack hold data
+        if !perms.starts_with("rw")
+            || (name.starts_with('[') && name != "[heap]" && name != "[stack]")
+        {
+            continue;
+        }
+        let mut bounds = range.split('-');
+        let start = usize::from_str_radix(bounds.next().unwrap_or(""), 16);
+        let end = usize::from_str_radix(bounds.next().unwrap_or(""), 16);
+        if let (Ok(start), Ok(end)) = (start, end) {
+            mappings.push((start, end));
+        }
+    }
+    Ok(mappings)
+}
+
+impl Recording {
+    /// Starts recording the (stopped) inferior with the given pid.
+    pub fn start(pid: Pid) -> io::Result<Recording> {
+        let mem = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(format!("/proc/{}/mem", pid))?;
+        let mut recording = Recording {
+            pid,
+            mem,
+            shadow: HashMap::new(),
+            steps: Vec::new(),
+            depth: 0,
+        };
+        recording.diff_memory()?;
+        Ok(recording)
+    }
+
+    /// The recorded steps, oldest first
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Current call depth, relative to where recording started
+    pub fn depth(&self) -> i64 {
+        self.depth
+    }
+
+    /// Brings the shadow copy up to date with the inferior's memory, returning the old contents
+    /// of whatever changed. Pages that weren't mapped before are just added to the shadow copy.
+    fn diff_memory(&mut self) -> io::Result<Vec<(usize, Vec<u8>)>> {
+        let mut writes = Vec::new();
+        let mut page = vec![0; PAGE_SIZE];
+        for (start, end) in writable_mappings(self.pid)? {
+            for addr in (start..end).step_by(PAGE_SIZE) {
+                if self.mem.read_exact_at(&mut page, addr as u64).is_err() {
+                    continue;
+                }
+                let old = match self.shadow.get_mut(&addr) {
+                    Some(old) => old,
+                    None => {
+                        self.shadow.insert(addr, page.clone());
+                        continue;
+                    }
+                };
+                if *old == page {
+                    continue;
+                }
+                // Log each run of changed bytes
+                let mut i = 0;
+                while i < PAGE_SIZE {
+                    if old[i] == page[i] {
+                        i += 1;
+                        continue;
+                    }
+                    let run_start = i;
+                    while i < PAGE_SIZE && old[i] != page[i] {
+                        i += 1;
+                    }
+                    writes.push((addr + run_start, old[run_start..i].to_vec()));
+                }
+                old.copy_from_slice(&page);
+            }
+        }
+        Ok(writes)
+    }
+
+    fn read_word(&self, addr: u64) -> Option<u64> {
+        let mut bytes = [0; 8];
+        self.mem.read_exact_at(&mut bytes, addr).ok()?;
+        Some(u64::from_le_bytes(bytes))
+    }
+
+    /// Logs an instruction that just ran, given the registers before and after it.
+    pub fn record_step(
+        &mut self,
+        before: libc::user_regs_struct,
+        after: &libc::user_regs_struct,
+    ) -> io::Result<()> {
+        let writes = self.diff_memory()?;
+        self.steps.push(Step {
+            regs: before,
+            writes,
+            depth: self.depth,
+        });
+        // A call pushes the address of the instruction after it (at most 15 bytes on), and a
+        // ret pops the address it jumps to
+        if after.rsp == before.rsp - 8 {
+            if let Some(return_addr) = self.read_word(after.rsp) {
+                if return_addr > before.rip && return_addr <= before.rip + 15 {
+                    self.depth += 1;
+                }
+            }
+        } else if after.rsp == before.rsp + 8 && self.read_word(before.rsp) == Some(after.rip) {
+            self.depth -= 1;
+        }
+        Ok(())
+    }
+
+    /// Undoes the last recorded instruction's memory changes and returns the registers to
+    /// restore, or None if the recording is exhausted.
+    pub fn undo(&mut self) -> io::Result<Option<libc::user_regs_struct>> {
+        let step = match self.steps.pop() {
+            Some(step) => step,
+            None => return Ok(None),
+        };
+        for (addr, bytes) in step.writes.iter().rev() {
+            self.mem.write_all_at(bytes, *addr as u64)?;
+            let page = addr - addr % PAGE_SIZE;
+            if let Some(shadow) = self.shadow.get_mut(&page) {
+                let offset = addr - page;
+                shadow[offset..offset + bytes.len()].copy_from_slice(bytes);
+            }
+        }
+        self.depth = step.depth;
+        Ok(Some(step.regs))
+    }
+}
@@ -0,0 +1,169 @@
+//! Tracking of the shared libraries loaded into the inferior, through the dynamic linker's
+//! r_debug interface (see <link.h>). The dynamic linker calls _dl_debug_state whenever it has
+//! changed the list of loaded objects; a breakpoint there lets us re-read the list.
+
+use crate::dwarf_data::DwarfData;
+use crate::inferior::Inferior;
+use nix::unistd::Pid;
+use object::{Object, ObjectSection};
+use std::convert::TryInto;
+use std::fs;
+
+/// auxv entry holding the address the dynamic linker was loaded at
+const AT_BASE: u64 = 7;
+
+/// auxv entry holding the program's entry point
+pub const AT_ENTRY: u64 = 9;
+
+/// r_debug.r_state value once the link map is consistent (as opposed to mid-load/unload)
+const RT_CONSISTENT: u64 = 0;
+
+/// Longest library path read out of the inferior
+const MAX_PATH_LEN: usize = 4096;
+
+pub struct SharedLibrary {
+    pub name: String,
+    /// Difference between the addresses in the library file and where it was loaded (l_addr)
+    pub base: usize,
+    /// Loaded address range of the library's .text section
+    pub text: (usize, usize),
+    pub debug_data: Option<DwarfData>,
+}
+
+impl SharedLibrary {
+    /// Loads symbols for the library at path, loaded with the given bias. Returns None if the
+    /// file can't be opened (e.g. for the vDSO, which has no file).
+    pub fn load(name: &str, base: usize) -> Option<SharedLibrary> {
+        let file = fs::File::open(name).ok()?;
+        let mmap = unsafe { memmap::Mmap::map(&file).ok()? };
+        let object = object::File::parse(&mmap).ok()?;
+        let text = match object.section_by_name(".text") {
+            Some(section) => (
+                base + section.address() as usize,
+                base + (section.address() + section.size()) as usize,
+            ),
+            None => (base, base),
+        };
+        Some(SharedLibrary {
+            name: name.to_string(),
+            base,
+            text,
+            debug_data: DwarfData::from_file(name)
+                .or_else(|_| DwarfData::symbols_from_file(name))
+                .ok(),
+        })
+    }
+
+    /// Returns true if addr lies in this library's code.
+    pub fn contains(&self, addr: usize) -> bool {
+        self.text.0 <= addr && addr < self.text.1
+    }
+}
+
+/// Addresses of the dynamic linker's debugging interface in the inferior.
+pub struct LinkerHooks {
+    /// Address of the r_debug structure
+    pub r_debug: usize,
+    /// Address of _dl_debug_state, which is called after every change to the link map
+    pub debug_state: usize,
+}
+
+/// Returns the value of the given entry in the inferior's auxiliary vector.
+pub fn read_auxv(pid: Pid, key: u64) -> Option<u64> {
+    let auxv = fs::read(format!("/proc/{}/auxv", pid)).ok()?;
+    auxv.chunks(16)
+        .filter(|entry| entry.len() == 16)
+        .map(|entry| {
+            (
+                u64::from_le_bytes(entry[..8].try_into().unwrap()),
+                u64::from_le_bytes(entry[8..].try_into().unwrap()),
+            )
+        })
+        .find(|(entry_key, _)| *entry_key == key)
+        .map(|(_, value)| value)
+}
+
+/// Locates the dynamic linker of a freshly started inferior and its r_debug interface. Returns
+/// None for statically linked programs.
+pub fn find_linker_hooks(pid: Pid) -> Option<LinkerHooks> {
+    let base = read_auxv(pid, AT_BASE)? as usize;
+    if base == 0 {
+        return None;
+    }
+
+    // The mapping starting at AT_BASE is the dynamic linker itself
+    let maps = fs::read_to_string(format!("/proc/{}/maps", pid)).ok()?;
+    let path = maps.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let start = usize::from_str_radix(fields.first()?.split('-').next()?, 16).ok()?;
+        if start == base {
+            Some(fields.get(5)?.to_string())
+        } else {
+            None
+        }
+    })?;
+
+    let file = fs::File::open(&path).ok()?;
+    let mmap = unsafe { memmap::Mmap::map(&file).ok()? };
+    let object = object::File::parse(&mmap).ok()?;
+    let find_symbol = |name: &str| {
+        object
+            .dynamic_symbols()
+            .chain(object.symbols())
+            .find(|(_, symbol)| symbol.name() == Some(name))
+            .map(|(_, symbol)| base + symbol.address() as usize)
+    };
+    Some(LinkerHooks {
+        r_debug: find_symbol("_r_debug")?,
+        debug_state: find_symbol("_dl_debug_state")?,
+    })
+}
+
+fn read_word(inferior: &Inferior, addr: usize) -> Result<u64, nix::Error> {
+    let bytes = inferior.read_memory(addr, 8)?;
+    Ok(u64::from_le_bytes(bytes[..].try_into().unwrap()))
+}
+
+fn read_path(inferior: &Inferior, addr: usize) -> Result<String, nix::Error> {
+    let mut bytes = Vec::new();
+    while bytes.len() < MAX_PATH_LEN {
+        let word = inferior.read_memory(addr + bytes.len(), 8)?;
+        match word.iter().position(|byte| *byte == 0) {
+            Some(end) => {
+                bytes.extend_from_slice(&word[..end]);
+                break;
+            }
+            None => bytes.extend_from_slice(&word),
+        }
+    }
+    Ok(String::from_utf8_lossy(&bytes).to_string())
+}
+
+/// Walks the link map, returning the path and load bias of every loaded shared object (the main
+/// program is left out). Returns None if the dynamic linker is in the middle of updating it.
+pub fn read_link_map(
+    inferior: &Inferior,
+    r_debug: usize,
+) -> Result<Option<Vec<(String, usize)>>, nix::Error> {
+    // struct r_debug { int r_version; struct link_map *r_map; ElfW(Addr) r_brk;
+    //                  enum r_state; ElfW(Addr) r_ldbase; }
+    if read_word(inferior, r_debug + 24)? & 0xffff_ffff != RT_CONSISTENT {
+        return Ok(None);
+    }
+    let mut libraries = Vec::new();
+    let mut link_map = read_word(inferior, r_debug + 8)? as usize;
+    while link_map != 0 {
+        // struct link_map { ElfW(Addr) l_addr; char *l_name; ElfW(Dyn) *l_ld;
+        //                   struct link_map *l_next, *l_prev; }
+        let base = read_word(inferior, link_map)? as usize;
+        let name_addr = read_word(inferior, link_map + 8)? as usize;
+        if name_addr != 0 {
+            let name = read_path(inferior, name_addr)?;
+            if !name.is_empty() {
+                libraries.push((name, base));
+            }
+        }
+        link_map = read_word(inferior, link_map + 24)? as usize;
+    }
+    Ok(Some(libraries))
+}
@@ -0,0 +1,336 @@
+//! x86-64 Linux system call names and argument decoding, used by `catch syscall` and
+//! `trace syscalls`.
+
+use crate::inferior::Inferior;
+
+/// Names of syscalls 0 through 334, indexed by syscall number. (Kept packed, rather than one
+/// per line as rustfmt would have it.)
+#[rustfmt::skip]
+const SYSCALL_NAMES: [&str; 335] = [
+    "read", "write", "open", "close", "stat", "fstat", "lstat", "poll", "lseek", "mmap",
+    "mprotect", "munmap", "brk", "rt_sigaction", "rt_sigprocmask", "rt_sigreturn", "ioctl",
+    "pread64", "pwrite64", "readv", "writev", "access", "pipe", "select", "sched_yield", "mremap",
+    "msync", "mincore", "madvise", "shmget", "shmat", "shmctl", "dup", "dup2", "pause",
+    "nanosleep", "getitimer", "alarm", "setitimer", "getpid", "sendfile", "socket", "connect",
+    "accept", "sendto", "recvfrom", "sendmsg", "recvmsg", "shutdown", "bind", "listen",
+    "getsockname", "getpeername", "socketpair", "setsockopt", "getsockopt", "clone", "fork",
+    "vfork", "execve", "exit", "wait4", "kill", "uname", "semget", "semop", "semctl", "shmdt",
+    "msgget", "msgsnd", "msgrcv", "msgctl", "fcntl", "flock", "fsync", "fdatasync", "truncate",
+    "ftruncate", "getdents", "getcwd", "chdir", "fchdir", "rename", "mkdir", "rmdir", "creat",
+    "link", "unlink", "symlink", "readlink", "chmod", "fchmod", "chown", "fchown", "lchown",
+    "umask", "gettimeofday", "getrlimit", "getrusage", "sysinfo", "times", "ptrace", "getuid",
+    "syslog", "getgid", "setuid", "setgid", "geteuid", "getegid", "setpgid", "getppid", "getpgrp",
+    "setsid", "setreuid", "setregid", "getgroups", "setgroups", "setresuid", "getresuid",
+    "setresgid", "getresgid", "getpgid", "setfsuid", "setfsgid", "getsid", "capget", "capset",
+    "rt_sigpending", "rt_sigtimedwait", "rt_sigqueueinfo", "rt_sigsuspend", "sigaltstack", "utime",
+    "mknod", "uselib", "personality", "ustat", "statfs", "fstatfs", "sysfs", "getpriority",
+    "setpriority", "sched_setparam", "sched_getparam", "sched_setscheduler", "sched_getscheduler",
+    "sched_get_priority_max", "sched_get_priority_min", "sched_rr_get_interval", "mlock",
+    "munlock", "mlockall", "munlockall", "vhangup", "modify_ldt", "pivot_root", "_sysctl", "prctl",
+    "arch_prctl", "adjtimex", "setrlimit", "chroot", "sync", "acct", "settimeofday", "mount",
+    "umount2", "swapon", "swapoff", "reboot", "sethostname", "setdomainname", "iopl", "ioperm",
+    "create_module", "init_module", "delete_module", "get_kernel_syms", "query_module", "quotactl",
+    "nfsservctl", "getpmsg", "putpmsg", "afs_syscall", "tuxcall", "security", "gettid",
+    "readahead", "setxattr", "lsetxattr", "fsetxattr", "getxattr", "lgetxattr", "fgetxattr",
+    "listxattr", "llistxattr", "flistxattr", "removexattr", "lremovexattr", "fremovexattr",
+    "tkill", "time", "futex", "sched_setaffinity", "sched_getaffinity", "set_thread_area",
+    "io_setup", "io_destroy", "io_getevents", "io_submit", "io_cancel", "get_thread_area",
+    "lookup_dcookie", "epoll_create", "epoll_ctl_old", "epoll_wait_old", "remap_file_pages",
+    "getdents64", "set_tid_address", "restart_syscall", "semtimedop", "fadvise64", "timer_create",
+    "timer_settime", "timer_gettime", "timer_getoverrun", "timer_delete", "clock_settime",
+    "clock_gettime", "clock_getres", "clock_nanosleep", "exit_group", "epoll_wait", "epoll_ctl",
+    "tgkill", "utimes", "vserver", "mbind", "set_mempolicy", "get_mempolicy", "mq_open",
+    "mq_unlink", "mq_timedsend", "mq_timedreceive", "mq_notify", "mq_getsetattr", "kexec_load",
+    "waitid", "add_key", "request_key", "keyctl", "ioprio_set", "ioprio_get", "inotify_init",
+    "inotify_add_watch", "inotify_rm_watch", "migrate_pages", "openat", "mkdirat", "mknodat",
+    "fchownat", "futimesat", "newfstatat", "unlinkat", "renameat", "linkat", "symlinkat",
+    "readlinkat", "fchmodat", "faccessat", "pselect6", "ppoll", "unshare", "set_robust_list",
+    "get_robust_list", "splice", "tee", "sync_file_range", "vmsplice", "move_pages", "utimensat",
+    "epoll_pwait", "signalfd", "timerfd_create", "eventfd", "fallocate", "timerfd_settime",
+    "timerfd_gettime", "accept4", "signalfd4", "eventfd2", "epoll_create1", "dup3", "pipe2",
+    "inotify_init1", "preadv", "pwritev", "rt_tgsigqueueinfo", "perf_event_open", "recvmmsg",
+    "fanotify_init", "fanotify_mark", "prlimit64", "name_to_handle_at", "open_by_handle_at",
+    "clock_adjtime", "syncfs", "sendmmsg", "setns", "getcpu", "process_vm_readv",
+    "process_vm_writev", "kcmp", "finit_module", "sched_setattr", "sched_getattr", "renameat2",
+    "seccomp", "getrandom", "memfd_create", "kexec_file_load", "bpf", "execveat", "userfaultfd",
+    "membarrier", "mlock2", "copy_file_range", "preadv2", "pwritev2", "pkey_mprotect",
+    "pkey_alloc", "pkey_free", "statx", "io_pgetevents", "rseq",
+];
+
+/// Names of syscalls 424 through 450 (numbers in between are unused on x86-64)
+#[rustfmt::skip]
+const SYSCALL_NAMES_424: [&str; 27] = [
+    "pidfd_send_signal", "io_uring_setup", "io_uring_enter", "io_uring_register", "open_tree",
+    "move_mount", "fsopen", "fsconfig", "fsmount", "fspick", "pidfd_open", "clone3", "close_range",
+    "openat2", "pidfd_getfd", "faccessat2", "process_madvise", "epoll_pwait2", "mount_setattr",
+    "quotactl_fd", "landlock_create_ruleset", "landlock_add_rule", "landlock_restrict_self",
+    "memfd_secret", "process_mrelease", "futex_waitv", "set_mempolicy_home_node",
+];
+
+/// How to display a syscall argument
+#[derive(Clone, Copy)]
+enum Arg {
+    Int,
+    Long,
+    Hex,
+    Oct,
+    Ptr,
+    /// NUL-terminated string
+    Str,
+    /// Buffer whose length is given by the argument at this index
+    Buf(usize),
+}
+
+/// Longest string or buffer contents displayed before truncating with "..."
+const MAX_STRING_LEN: usize = 32;
+
+pub fn syscall_name(number: u64) -> Option<&'static str> {
+    let number = number as usize;
+    if number < SYSCALL_NAMES.len() {
+        Some(SYSCALL_NAMES[number])
+    } else if number >= 424 && number - 424 < SYSCALL_NAMES_424.len() {
+        Some(SYSCALL_NAMES_424[number - 424])
+    } else {
+        None
+    }
+}
+
+pub fn syscall_number(name: &str) -> Option<u64> {
+    if let Some(number) = SYSCALL_NAMES.iter().position(|n| *n == name) {
+        return Some(number as u64);
+    }
+    SYSCALL_NAMES_424
+        .iter()
+        .position(|n| *n == name)
+        .map(|number| number as u64 + 424)
+}
+
+/// Returns true if the syscall never returns to the caller (on success), so there will be no
+/// syscall-exit stop for it.
+pub fn is_noreturn(number: u64) -> bool {
+    matches!(syscall_name(number), Some("exit") | Some("exit_group"))
+}
+
+/// Argument signatures of common syscalls. Syscalls missing here are shown with six hex
+/// arguments.
+fn signature(name: &str) -> Option<&'static [Arg]> {
+    use Arg::*;
+    Some(match name {
+        "read" => &[Int, Ptr, Long],
+        "write" => &[Int, Buf(2), Long],
+        "open" => &[Str, Hex, Oct],
+        "close" => &[Int],
+        "stat" | "lstat" => &[Str, Ptr],
+        "fstat" => &[Int, Ptr],
+        "lseek" => &[Int, Long, Int],
+        "mmap" => &[Ptr, Long, Hex, Hex, Int, Hex],
+        "mprotect" => &[Ptr, Long, Hex],
+        "munmap" => &[Ptr, Long],
+        "brk" => &[Ptr],
+        "ioctl" => &[Int, Hex, Ptr],
+        "pread64" => &[Int, Ptr, Long, Long],
+        "pwrite64" => &[Int, Buf(2), Long, Long],
+        "access" => &[Str, Oct],
+        "pipe" => &[Ptr],
+        "dup" => &[Int],
+        "dup2" => &[Int, Int],
+        "nanosleep" => &[Ptr, Ptr],
+        "getpid" | "getppid" | "getuid" | "geteuid" | "getgid" | "getegid" | "fork" | "vfork"
+        | "sched_yield" => &[],
+        "exit" | "exit_group" => &[Int],
+        "execve" => &[Str, Ptr, Ptr],
+        "kill" => &[Int, Int],
+        "unlink" | "rmdir" | "chdir" => &[Str],
+        "mkdir" => &[Str, Oct],
+        "arch_prctl" => &[Hex, Ptr],
+        "set_tid_address" => &[Ptr],
+        "openat" => &[Int, Str, Hex, Oct],
+        "newfstatat" => &[Int, Str, Ptr, Hex],
+        "clock_nanosleep" => &[Int, Hex, Ptr, Ptr],
+        "getrandom" => &[Ptr, Long, Hex],
+        _ => return None,
+    })
+}
+
+/// Quotes bytes as a C string literal, truncating long contents.
+fn quote(bytes: &[u8], truncated: bool) -> String {
+    let mut quoted = String::from("\"");
+    for byte in bytes {
+        match *byte {
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            b'\n' => quoted.push_str("\\n"),
+            b'\t' => quoted.push_str("\\t"),
+            0x20..=0x7e => quoted.push(*byte as char),
+            _ => quoted.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    quoted.push('"');
+    if truncated {
+        quoted.push_str("...");
+    }
+    quoted
+}
+
+/// Reads len bytes of the inferior's memory at an address
+type ReadMemory<'a> = dyn Fn(usize, usize) -> Option<Vec<u8>> + 'a;
+
+fn read_string(read_memory: &ReadMemory, addr: usize) -> Option<String> {
+    let mut bytes = Vec::new();
+    while bytes.len() < MAX_STRING_LEN {
+        let byte = *read_memory(addr + bytes.len(), 1)?.first()?;
+        if byte == 0 {
+            return Some(quote(&bytes, false));
+        }
+        bytes.push(byte);
+    }
+    Some(quote(&bytes, true))
+}
+
+fn format_arg(read_memory: &ReadMemory, arg: Arg, args: &[u64; 6], index: usize) -> String {
+    let value = args[index];
+    match arg {
+        Arg::Int => format!("{}", value as i32),
+        Arg::Long => format!("{}", value as i64),
+        Arg::Hex => format!("{:#x}", value),
+        Arg::Oct => format!("{:#o}", value),
+        Arg::Ptr if value == 0 => "NULL".to_string(),
+        Arg::Ptr => format!("{:#x}", value),
+        Arg::Str if value == 0 => "NULL".to_string(),
+        Arg::Str => {
+            read_string(read_memory, value as usize).unwrap_or_else(|| format!("{:#x}", value))
+        }
+        Arg::Buf(len_index) => {
+            let len = args[len_index] as usize;
+            match read_memory(value as usize, len.min(MAX_STRING_LEN)) {
+                Some(bytes) => quote(&bytes, len > MAX_STRING_LEN),
+                None => format!("{:#x}", value),
+            }
+        }
+    }
+}
+
+/// Formats the syscall the inferior is stopped at (on entry) as `name(arg, ...)`.
+pub fn format_call(inferior: &Inferior, regs: &libc::user_regs_struct) -> String {
+    let number = regs.orig_rax;
+    let args = [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9];
+    format_args(number, &args, &|addr, len| {
+        inferior.read_memory(addr, len).ok()
+    })
+}
+
+fn format_args(number: u64, args: &[u64; 6], read_memory: &ReadMemory) -> String {
+    let name = match syscall_name(number) {
+        Some(name) => name.to_string(),
+        None => format!("syscall_{}", number),
+    };
+    let formatted: Vec<String> = match signature(&name) {
+        Some(signature) => signature
+            .iter()
+            .enumerate()
+            .map(|(i, arg)| format_arg(read_memory, *arg, args, i))
+            .collect(),
+        None => args.iter().map(|arg| format!("{:#x}", arg)).collect(),
+    };
+    format!("{}({})", name, formatted.join(", "))
+}
+
+/// Formats a syscall's return value, decoding errors as -1 and the errno name.
+pub fn format_return(number: u64, ret: i64) -> String {
+    if (-4095..0).contains(&ret) {
+        let errno = nix::errno::Errno::from_i32(-ret as i32);
+        return format!("-1 {:?} ({})", errno, errno.desc());
+    }
+    match syscall_name(number) {
+        Some("mmap") | Some("brk") | Some("mremap") => format!("{:#x}", ret),
+        _ => format!("{}", ret),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Memory holding data at 0x1000, with nothing mapped anywhere else
+    fn memory(data: &'static [u8]) -> impl Fn(usize, usize) -> Option<Vec<u8>> {
+        move |addr, len| {
+            let start = addr.checked_sub(0x1000)?;
+            Some(data.get(start..start + len)?.to_vec())
+        }
+    }
+
+    #[test]
+    fn formats_calls_by_signature() {
+        let read_memory = memory(b"hello\n\0");
+        let number = syscall_number("write").unwrap();
+        assert_eq!(
+            format_args(number, &[1, 0x1000, 6, 0, 0, 0], &read_memory),
+            "write(1, \"hello\\n\", 6)"
+        );
+        let number = syscall_number("openat").unwrap();
+        assert_eq!(
+            format_args(
+                number,
+                &[-100i64 as u64, 0x1000, 0x80000, 0o644, 0, 0],
+                &read_memory
+            ),
+            "openat(-100, \"hello\\n\", 0x80000, 0o644)"
+        );
+        let number = syscall_number("mmap").unwrap();
+        assert_eq!(
+            format_args(number, &[0, 4096, 3, 0x22, -1i64 as u64, 0], &read_memory),
+            "mmap(NULL, 4096, 0x3, 0x22, -1, 0x0)"
+        );
+    }
+
+    #[test]
+    fn formats_unknown_calls_in_hex() {
+        assert_eq!(
+            format_args(1000, &[1, 2, 3, 4, 5, 0xff], &memory(b"")),
+            "syscall_1000(0x1, 0x2, 0x3, 0x4, 0x5, 0xff)"
+        );
+    }
+
+    #[test]
+    fn truncates_and_escapes_strings() {
+        let read_memory = memory(b"a\"b\\c\t\x01\0");
+        let number = syscall_number("unlink").unwrap();
+        assert_eq!(
+            format_args(number, &[0x1000, 0, 0, 0, 0, 0], &read_memory),
+            "unlink(\"a\\\"b\\\\c\\t\\x01\")"
+        );
+        let read_memory = memory(&[b'x'; 40]);
+        let number = syscall_number("write").unwrap();
+        assert_eq!(
+            format_args(number, &[2, 0x1000, 40, 0, 0, 0], &read_memory),
+            format!("write(2, \"{}\"..., 40)", "x".repeat(MAX_STRING_LEN))
+        );
+        // Unreadable pointers are shown as addresses
+        assert_eq!(
+            format_args(number, &[2, 0x5000, 4, 0, 0, 0], &read_memory),
+            "write(2, 0x5000, 4)"
+        );
+    }
+
+    #[test]
+    fn formats_return_values() {
+        let mmap = syscall_number("mmap").unwrap();
+        let read = syscall_number("read").unwrap();
+        assert_eq!(format_return(read, 42), "42");
+        assert_eq!(format_return(mmap, 0x7f0000001000), "0x7f0000001000");
+        assert_eq!(
+            format_return(read, -2),
+            "-1 ENOENT (No such file or directory)"
+        );
+        assert_eq!(format_return(read, -4096), "-4096");
+    }
+
+    #[test]
+    fn numbers_syscalls() {
+        assert_eq!(syscall_name(0), Some("read"));
+        assert_eq!(syscall_number("clone3"), Some(435));
+        assert_eq!(syscall_name(435), Some("clone3"));
+        assert_eq!(syscall_name(400), None);
+        assert!(is_noreturn(syscall_number("exit_group").unwrap()));
+    }
+}
@@ -0,0 +1,297 @@
+//! The `--tui` split-pane interface. Source, registers and backtrace panes are drawn with ANSI
+//! escape sequences above a command pane. The command pane is the terminal's scrolling region,
+//! so everything else deet (or the inferior) prints ends up there.
+
+use nix::sys::termios::{self, LocalFlags, SetArg, SpecialCharacterIndices};
+use std::io::{self, Read, Write};
+
+/// Width of the column holding the registers and backtrace panes
+const SIDE_WIDTH: usize = 32;
+
+/// Escape sequences (after the ESC) of the keys that run a command straight away: F5, F10 and
+/// F11 as in most IDEs, and alt+c, alt+n and alt+s for terminals that grab the function keys
+const SHORTCUTS: &[(&str, &str)] = &[
+    ("[15~", "continue"),
+    ("[21~", "next"),
+    ("[23~", "step"),
+    ("c", "continue"),
+    ("n", "next"),
+    ("s", "step"),
+];
+
+/// What the panes should show
+pub struct View {
+    /// Path and lines of the source file to show
+    pub source: Option<(String, Vec<String>)>,
+    /// The line (numbered from 1) the inferior is stopped at
+    pub current_line: Option<usize>,
+    /// Lines with breakpoints on them
+    pub breakpoint_lines: Vec<usize>,
+    pub registers: Vec<(&'static str, u64)>,
+    pub frames: Vec<String>,
+    /// Shown in place of the source if there is none
+    pub status: String,
+}
+
+pub struct Tui {
+    rows: usize,
+    cols: usize,
+    history: Vec<String>,
+}
+
+fn terminal_size() -> (usize, usize) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+        && size.ws_row > 0
+        && size.ws_col > 0
+    {
+        (size.ws_row as usize, size.ws_col as usize)
+    } else {
+        (24, 80)
+    }
+}
+
+/// Pads or truncates text to exactly width columns.
+fn fit(text: &str, width: usize) -> String {
+    let text = text.replace('\t', "    ");
+    let mut fitted: String = text.chars().take(width).collect();
+    let len = fitted.chars().count();
+    fitted.push_str(&" ".repeat(width - len));
+    fitted
+}
+
+impl Tui {
+    pub fn new() -> Tui {
+        let mut tui = Tui {
+            rows: 0,
+            cols: 0,
+            history: Vec::new(),
+        };
+        tui.resize();
+        tui
+    }
+
+    /// Rows used by the panes above the command pane
+    fn top_rows(&self) -> usize {
+        self.rows * 2 / 3
+    }
+
+    /// Picks up the terminal's size, and clears the screen and sets up the command pane if it
+    /// changed.
+    fn resize(&mut self) {
+        let (rows, cols) = terminal_size();
+        if (rows, cols) == (self.rows, self.cols) {
+            return;
+        }
+        self.rows = rows;
+        self.cols = cols;
+        print!(
+            "\x1b[2J\x1b[{};{}r\x1b[{};1H",
+            self.top_rows() + 1,
+            self.rows,
+            self.rows
+        );
+        let _ = io::stdout().flush();
+    }
+
+    /// Redraws the panes, leaving the cursor where it was in the command pane.
+    pub fn draw(&mut self, view: &View) {
+        self.resize();
+        let top = self.top_rows();
+        let side_width = SIDE_WIDTH.min(self.cols / 2);
+        let source_width = self.cols.saturating_sub(side_width + 1);
+        // Less the title row and the command pane's header row (a tiny terminal has no room left)
+        let height = top.saturating_sub(2);
+
+        let mut left = Vec::new();
+        match &view.source {
+            Some((path, lines)) => {
+                left.push(format!(" {} ", path));
+                let current = view.current_line.unwrap_or(1);
+                let first = current
+                    .saturating_sub(height / 2)
+                    .max(1)
+                    .min(lines.len().saturating_sub(height) + 1);
+                for number in first..first + height {
+                    let text = match lines.get(number - 1) {
+                        Some(text) => text,
+                        None => break,
+                    };
+                    let marker = if view.breakpoint_lines.contains(&number) {
+                        '*'
+                    } else {
+                        ' '
+                    };
+                    let line = fit(&format!("{}{:>4}  {}", marker, number, text), source_width);
+                    if Some(number) == view.current_line {
+                        left.push(format!("\x1b[7m{}\x1b[0m", line));
+                    } else {
+                        left.push(line);
+                    }
+                }
+            }
+            None => {
+                left.push(" Source ".to_string());
+                left.push(String::new());
+                left.push(format!("  {}", view.status));
+            }
+        }
+
+        let mut right = vec![" Registers ".to_string()];
+        let register_rows = view.registers.len().min(height.saturating_sub(3));
+        for (name, value) in view.registers.iter().take(register_rows) {
+            right.push(format!(" {:<6} {:#018x}", name, value));
+        }
+        right.push(" Backtrace ".to_string());
+        for (i, frame) in view.frames.iter().enumerate() {
+            right.push(format!(" #{} {}", i, frame));
+        }
+        let right_headers = [0, register_rows + 1];
+
+        let mut out = String::from("\x1b7");
+        for row in 0..top {
+            out.push_str(&format!("\x1b[{};1H\x1b[2K", row + 1));
+            if row == top - 1 {
+                out.push_str(&format!("\x1b[7m{}\x1b[0m", fit(" Command ", self.cols)));
+                continue;
+            }
+            let text = left.get(row).map(|text| text.as_str()).unwrap_or("");
+            if row == 0 {
+                out.push_str(&format!("\x1b[7m{}\x1b[0m", fit(text, source_width)));
+            } else if text.starts_with("\x1b[") {
+                out.push_str(text);
+            } else {
+                out.push_str(&fit(text, source_width));
+            }
+            out.push('|');
+            let text = right.get(row).map(|text| text.as_str()).unwrap_or("");
+            if right_headers.contains(&row) {
+                out.push_str(&format!("\x1b[7m{}\x1b[0m", fit(text, side_width)));
+            } else {
+                out.push_str(&fit(text, side_width));
+            }
+        }
+        out.push_str("\x1b8");
+        print!("{}", out);
+        let _ = io::stdout().flush();
+    }
+
+    /// Reads a command line in the command pane. Besides basic line editing and history (up and
+    /// down), shortcut keys return their command immediately. Returns None at end of input.
+    pub fn read_line(&mut self, prompt: &str) -> Option<String> {
+        print!("{}", prompt);
+        let _ = io::stdout().flush();
+        let original = match termios::tcgetattr(libc::STDIN_FILENO) {
+            Ok(original) => original,
+            // Not a terminal: just read a line
+            Err(_) => {
+                let mut line = String::new();
+                return match io::stdin().read_line(&mut line) {
+                    Ok(0) | Err(_) => None,
+                    Ok(_) => Some(line.trim_end_matches('\n').to_string()),
+                };
+            }
+        };
+        let mut raw = original.clone();
+        raw.local_flags
+            .remove(LocalFlags::ICANON | LocalFlags::ECHO | LocalFlags::ISIG);
+        raw.control_chars[SpecialCharacterIndices::VMIN as usize] = 1;
+        raw.control_chars[SpecialCharacterIndices::VTIME as usize] = 0;
+        let _ = termios::tcsetattr(libc::STDIN_FILENO, SetArg::TCSANOW, &raw);
+        let line = self.edit_line(prompt);
+        let _ = termios::tcsetattr(libc::STDIN_FILENO, SetArg::TCSANOW, &original);
+        if let Some(line) = &line {
+            if !line.trim().is_empty() && self.history.last() != Some(line) {
+                self.history.push(line.clone());
+            }
+        }
+        line
+    }
+
+    fn edit_line(&self, prompt: &str) -> Option<String> {
+        let mut line = String::new();
+        let mut history_index = self.history.len();
+        let mut stdin = io::stdin();
+        let mut read_byte = || {
+            let mut byte = [0u8];
+            match stdin.read(&mut byte) {
+                Ok(1) => Some(byte[0]),
+                _ => None,
+            }
+        };
+        loop {
+            let byte = read_byte()?;
+            match byte {
+                b'\r' | b'\n' => break,
+                // Backspace
+                0x7f | 0x08 if !line.is_empty() => {
+                    line.pop();
+                    print!("\x08 \x08");
+                }
+                // ctrl+c abandons the line
+                0x03 => {
+                    line.clear();
+                    print!("^C\r\n{}", prompt);
+                }
+                // ctrl+d quits on an empty line
+                0x04 if line.is_empty() => {
+                    println!();
+                    return None;
+                }
+                0x1b => {
+                    let mut sequence = String::new();
+                    match read_byte()? {
+                        b'[' => {
+                            sequence.push('[');
+                            loop {
+                                let byte = read_byte()?;
+                                sequence.push(byte as char);
+                                if (0x40..=0x7e).contains(&byte) {
+                                    break;
+                                }
+                            }
+                        }
+                        b'O' => {
+                            sequence.push('O');
+                            sequence.push(read_byte()? as char);
+                        }
+                        byte => sequence.push(byte as char),
+                    }
+                    if let Some((_, command)) = SHORTCUTS.iter().find(|(keys, _)| *keys == sequence)
+                    {
+                        line = command.to_string();
+                        print!("\r\x1b[2K{}{}", prompt, line);
+                        break;
+                    }
+                    let history_index_before = history_index;
+                    match sequence.as_str() {
+                        "[A" if history_index > 0 => history_index -= 1,
+                        "[B" if history_index < self.history.len() => history_index += 1,
+                        _ => {}
+                    }
+                    if history_index != history_index_before {
+                        line = self.history.get(history_index).cloned().unwrap_or_default();
+                        print!("\r\x1b[2K{}{}", prompt, line);
+                    }
+                }
+                byte if (0x20..0x7f).contains(&byte) => {
+                    line.push(byte as char);
+                    print!("{}", byte as char);
+                }
+                _ => {}
+            }
+            let _ = io::stdout().flush();
+        }
+        print!("\r\n");
+        let _ = io::stdout().flush();
+        Some(line)
+    }
+}
+
+impl Drop for Tui {
+    /// Gives the whole terminal back to ordinary scrolling output.
+    fn drop(&mut self) {
+        print!("\x1b[r\x1b[{};1H", self.rows);
+        let _ = io::stdout().flush();
+    }
+}
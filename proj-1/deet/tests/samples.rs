@@ -0,0 +1,234 @@
+//! Runs deet against the programs in samples/, feeding it commands on stdin.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Once;
+
+static BUILD_SAMPLES: Once = Once::new();
+
+/// Returns the path of a sample program, building the samples the first time.
+fn sample(name: &str) -> String {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    BUILD_SAMPLES.call_once(|| {
+        let status = Command::new("make")
+            .current_dir(root)
+            .stdout(Stdio::null())
+            .status()
+            .expect("Failed to run make");
+        assert!(status.success(), "Failed to build the samples");
+    });
+    root.join("samples")
+        .join(name)
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+/// A scratch directory for the tests, which also stands in for $HOME so that no ~/.deetinit is
+/// run and no history is written.
+fn scratch_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join("deet-tests");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Runs deet with args, typing in commands, and returns everything it printed.
+fn deet(args: &[&str], commands: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_deet"))
+        .args(args)
+        .env("HOME", scratch_dir())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start deet");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(commands.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    text
+}
+
+/// Writes a command script to the scratch directory, returning its path.
+fn script(name: &str, lines: &str) -> String {
+    let path = scratch_dir().join(name);
+    std::fs::write(&path, lines).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn assert_contains(output: &str, expected: &str) {
+    assert!(
+        output.contains(expected),
+        "Expected {:?} in output:\n{}",
+        expected,
+        output
+    );
+}
+
+#[test]
+fn runs_to_completion() {
+    let output = deet(&[&sample("hello")], "run\n");
+    assert_contains(&output, "Hello world!");
+    assert_contains(&output, "Child exited (status 0)");
+}
+
+#[test]
+fn reports_raw_exit_syscall() {
+    let output = deet(&[&sample("exit")], "run\n");
+    assert_contains(&output, "Child exited (status 0)");
+}
+
+#[test]
+fn passes_arguments_and_reuses_them() {
+    let output = deet(&[&sample("sleepy_print")], "run 1\nrun\n");
+    assert_eq!(output.matches("Child exited (status 0)").count(), 2);
+    assert!(
+        !output.contains("Usage:"),
+        "Arguments were lost:\n{}",
+        output
+    );
+}
+
+#[test]
+fn stops_on_segfault_with_backtrace() {
+    let output = deet(&[&sample("segfault")], "run\nbacktrace\n");
+    assert_contains(&output, "Child stopped by SIGSEGV at func2 (");
+    assert_contains(&output, "segfault.c:5)");
+    assert_contains(&output, "\nfunc1 (");
+    assert_contains(&output, "\nmain (");
+    assert_contains(&output, "Child exited by SIGKILL");
+}
+
+#[test]
+fn stops_at_function_breakpoint() {
+    let commands = "break func2\nrun\ninfo args\nprint global\nnext\nnext\nprint sum\ncontinue\n";
+    let output = deet(&[&sample("function_calls")], commands);
+    assert_contains(&output, "Set breakpoint 0 at");
+    assert_contains(&output, "Child stopped by SIGTRAP at func2 (");
+    assert_contains(&output, "function_calls.c:10)");
+    assert_contains(&output, "a = 42\nb = 5\n");
+    assert_contains(&output, "$1 = 5\n");
+    assert_contains(&output, "12\t    printf(\"sum = %d\\n\", sum);");
+    assert_contains(&output, "$2 = 47\n");
+    assert_contains(&output, "Child exited (status 0)");
+}
+
+#[test]
+fn stops_at_breakpoint_every_time() {
+    // Continuing from a breakpoint must not reinstall it over its own 0xcc
+    let output = deet(
+        &[&sample("function_calls")],
+        "break func3\nrun\ncontinue\ncontinue\n",
+    );
+    assert_eq!(
+        output
+            .matches("Child stopped by SIGTRAP at func3 (")
+            .count(),
+        2
+    );
+    assert_eq!(output.matches("Hello from func3! 100").count(), 2);
+    assert_contains(&output, "Child exited (status 0)");
+}
+
+#[test]
+fn keeps_spaces_in_printf_formats() {
+    let commands = "printf \"a   b\\n\"\ndprintf func3,\"in  func3:   %d\\n\", a\nrun\n";
+    let output = deet(&[&sample("function_calls")], commands);
+    assert_contains(&output, "a   b\n");
+    assert_eq!(output.matches("in  func3:   100\n").count(), 2);
+    assert_contains(&output, "Child exited (status 0)");
+}
+
+#[test]
+fn stops_at_line_breakpoint() {
+    let output = deet(&[&sample("count")], "break 6\nrun\nnext\ncontinue\n");
+    assert_contains(&output, "(line 6)");
+    assert_contains(&output, "count.c:6)");
+    assert_contains(&output, "7\t    printf(\"4\\n\");");
+    assert_contains(&output, "Child exited (status 0)");
+}
+
+#[test]
+fn failing_command_aborts_script() {
+    let nested = script("nested", "break func3\nbreak nosuchfile.c:3\nbreak func1\n");
+    let outer = script(
+        "outer",
+        &format!("break main\nsource {}\nbreak func2\n", nested),
+    );
+    let other = script("other", "break 24\n");
+    let output = deet(
+        &["-x", &outer, "-x", &other, &sample("function_calls")],
+        "info breakpoints\n",
+    );
+    assert_contains(&output, "No source file named nosuchfile.c.");
+    assert_contains(&output, "(function main)");
+    assert_contains(&output, "(function func3)");
+    // The rest of the nested script and of the script sourcing it are skipped
+    assert!(!output.contains("(function func1)"), "{}", output);
+    assert!(!output.contains("(function func2)"), "{}", output);
+    // but later scripts still run
+    assert_contains(&output, "(line 24)");
+}
+
+#[test]
+fn limits_recursive_user_commands() {
+    let commands = "define forever\nforever\nend\nforever\nbreak func3\n";
+    let output = deet(&[&sample("function_calls")], commands);
+    assert_contains(&output, "Max user call depth exceeded -- command aborted.");
+    assert_contains(&output, "(function func3)");
+}
+
+#[test]
+fn reports_command_blocks_under_their_own_id() {
+    let commands = concat!(
+        "{\"id\": 7, \"command\": \"define hi\"}\n",
+        "{\"id\": 8, \"command\": \"print 1\"}\n",
+        "{\"id\": 9, \"command\": \"end\"}\n",
+    );
+    let output = deet(&["--interpreter=json", &sample("function_calls")], commands);
+    assert_contains(&output, "{\"event\":\"done\",\"id\":7}");
+    assert!(!output.contains("\"id\":9"), "{}", output);
+}
+
+#[test]
+fn runs_to_temporary_breakpoints_and_locations() {
+    let commands = "tbreak func2\nstart\ncontinue\nuntil 13\ncontinue\n";
+    let output = deet(&[&sample("function_calls")], commands);
+    assert_contains(&output, "Set temporary breakpoint 0 at");
+    assert_contains(&output, "Set temporary breakpoint 1 at");
+    assert_contains(&output, "Child stopped by SIGTRAP at main (");
+    assert_contains(&output, "Child stopped by SIGTRAP at func2 (");
+    assert_contains(&output, "13\t    func3(100);");
+    // Temporary breakpoints are gone once hit, so func2 is only stopped at once
+    assert_eq!(output.matches("at func2 (").count(), 1);
+    assert_contains(&output, "Child exited (status 0)");
+}
+
+#[test]
+fn restarts_from_checkpoint() {
+    let commands = "break func2\nrun\ncheckpoint\nnext\nnext\nrestart 0\nnext\ncontinue\n";
+    let output = deet(&[&sample("function_calls")], commands);
+    assert_contains(&output, "Checkpoint 0: fork returned pid ");
+    assert_contains(&output, "Switching to process ");
+    // The checkpoint is back at the start of func2, so line 11 is stepped to again
+    assert_eq!(output.matches("11\t    int sum = a + b;").count(), 2);
+    assert_contains(&output, "Child exited (status 0)");
+}
+
+#[test]
+fn calls_functions_in_the_inferior() {
+    let commands = "break func1\nrun\ncall func3(7)\ncall func2(1, 2)\ncontinue\n";
+    let output = deet(&[&sample("function_calls")], commands);
+    assert_contains(&output, "Hello from func3! 7\n");
+    assert_contains(&output, "func2(1, 2) was called\nsum = 3\n");
+    // The program carries on as if nothing had happened
+    assert_contains(&output, "func2(42, 5) was called\nsum = 47\n");
+    assert_contains(&output, "end of func1\n");
+    assert_contains(&output, "Child exited (status 0)");
+}